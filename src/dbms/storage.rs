@@ -1,4 +1,20 @@
+mod aio;
+mod alloc;
+mod buffer;
 pub mod ephemeral;
+mod error;
+mod fsm;
+mod heap;
 mod integrity;
 mod meta;
+mod overflow;
 mod page;
+mod scrub;
+#[cfg(test)]
+mod sim;
+mod store;
+mod superblock;
+mod wal;
+
+pub use error::{Error, Result};
+pub use store::PageStore;
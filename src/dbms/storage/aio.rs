@@ -0,0 +1,262 @@
+use std::{io, ops::Range};
+
+use crate::dbms::storage::{self, Error, store::Disk};
+
+mod pool;
+mod ring;
+
+pub use pool::Pool;
+pub use ring::Ring;
+
+// Holds exactly one page of the store that the queue was opened on.
+pub type Buffer = Box<[u8]>;
+
+#[derive(Debug)]
+pub enum Request {
+    Read(u64, Buffer),
+    Write(u64, Buffer),
+    // Makes every write submitted before it durable. It only starts once
+    // those writes have completed.
+    Sync,
+}
+
+// A finished request, handed back along with its buffer, which holds the
+// page for a read.
+#[derive(Debug)]
+pub struct Completion {
+    pub tag: u64,
+    pub request: Request,
+    pub result: storage::Result<()>,
+}
+
+// Page I/O that does not block the thread that asks for it. Requests are
+// queued up and submitted in batches, and their completions are reaped in
+// batches as well, in whatever order they finish.
+pub trait Queue {
+    fn page_size(&self) -> usize;
+
+    // Queues a request for the next submit, and returns the tag that its
+    // completion will carry. Tags are handed out in order.
+    fn push(&mut self, request: Request) -> u64;
+
+    // Submits every queued request at once.
+    fn submit(&mut self) -> io::Result<()>;
+
+    // Waits until at least `min` submitted requests have completed, or all
+    // of them if fewer are in flight, and returns every completion that is
+    // ready.
+    fn reap(&mut self, min: usize) -> io::Result<Vec<Completion>>;
+
+    // The number of requests that have been submitted but not reaped.
+    fn in_flight(&self) -> usize;
+
+    // Queues reads of a run of pages, so that they are in memory by the time
+    // they are needed, and returns their tags.
+    fn prefetch(&mut self, pages: Range<u64>) -> Range<u64> {
+        let mut tags = 0..0;
+        for page in pages {
+            let buf = vec![0u8; self.page_size()].into_boxed_slice();
+            let tag = self.push(Request::Read(page, buf));
+            if tags.is_empty() {
+                tags.start = tag;
+            }
+            tags.end = tag + 1;
+        }
+        tags
+    }
+}
+
+// Opens an io_uring queue on the file, and falls back to a thread pool where
+// io_uring is missing or disabled.
+pub fn open(disk: Disk, depth: u32) -> io::Result<Box<dyn Queue + Send>> {
+    match Ring::new(disk.try_clone()?, depth) {
+        Ok(ring) => Ok(Box::new(ring)),
+        Err(_) => Ok(Box::new(Pool::new(disk, depth as usize)?)),
+    }
+}
+
+// Refuses a request whose buffer does not hold exactly one page, which the
+// kernel would otherwise read past or write past the end of.
+fn check(request: &Request, page_size: usize) -> storage::Result<()> {
+    match request {
+        Request::Read(_, buf) | Request::Write(_, buf) if buf.len() != page_size => {
+            Err(Error::BufferSizeMismatch {
+                expected: page_size,
+                found: buf.len(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+// Turns the number of bytes a request moved into its result.
+fn result(request: &Request, n: usize) -> storage::Result<()> {
+    match request {
+        Request::Read(page, buf) if n < buf.len() => Err(Error::ShortRead {
+            page: *page,
+            expected: buf.len(),
+            found: n,
+        }),
+        Request::Write(_, buf) if n < buf.len() => {
+            Err(io::Error::from(io::ErrorKind::WriteZero).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::dbms::storage::{ephemeral, page};
+
+    fn reap_all(queue: &mut dyn Queue) -> HashMap<u64, Completion> {
+        let mut completions = HashMap::new();
+        while queue.in_flight() > 0 {
+            for completion in queue.reap(1).unwrap() {
+                completions.insert(completion.tag, completion);
+            }
+        }
+        completions
+    }
+
+    // Writes a batch of pages and a sync, then reads them back in a batch.
+    fn round_trip(queue: &mut dyn Queue) {
+        let writes: Vec<u64> = (0..8)
+            .map(|page| {
+                queue.push(Request::Write(
+                    page,
+                    Box::new([page as u8 + 1; page::DEFAULT_SIZE]),
+                ))
+            })
+            .collect();
+        let sync = queue.push(Request::Sync);
+        queue.submit().unwrap();
+        assert_eq!(9, queue.in_flight());
+        let completions = reap_all(queue);
+        for tag in writes.iter().chain([&sync]) {
+            assert!(completions[tag].result.is_ok());
+        }
+
+        let tags = queue.prefetch(0..8);
+        queue.submit().unwrap();
+        let mut completions = reap_all(queue);
+        for (page, tag) in tags.enumerate() {
+            match completions.remove(&tag).unwrap() {
+                Completion {
+                    request: Request::Read(read, buf),
+                    result: Ok(()),
+                    ..
+                } => {
+                    assert_eq!(page as u64, read);
+                    assert_eq!([page as u8 + 1; page::DEFAULT_SIZE][..], *buf);
+                }
+                completion => panic!("unexpected completion {completion:?}"),
+            }
+        }
+    }
+
+    fn read_past_end(queue: &mut dyn Queue) {
+        let tag = queue.push(Request::Read(8, Box::new([0u8; page::DEFAULT_SIZE])));
+        queue.submit().unwrap();
+        let completion = queue.reap(1).unwrap().pop().unwrap();
+        assert_eq!(tag, completion.tag);
+        match completion.result {
+            Ok(_) => panic!("allowed reading past the end"),
+            Err(error) => assert!(
+                matches!(
+                    error,
+                    Error::ShortRead {
+                        page: 8,
+                        found: 0,
+                        ..
+                    }
+                ),
+                "{error}"
+            ),
+        }
+    }
+
+    // The file ends halfway through page 8.
+    fn read_partial_page(queue: &mut dyn Queue) {
+        let tag = queue.push(Request::Read(8, Box::new([0u8; page::DEFAULT_SIZE])));
+        queue.submit().unwrap();
+        match reap_all(queue).remove(&tag).unwrap().result {
+            Ok(_) => panic!("allowed reading partial page"),
+            Err(error) => assert!(
+                matches!(
+                    error,
+                    Error::ShortRead {
+                        page: 8,
+                        found: 4096,
+                        ..
+                    }
+                ),
+                "{error}"
+            ),
+        }
+    }
+
+    // Buffers that do not hold exactly one page are refused, and the page
+    // is left as it was.
+    fn short_buffers(queue: &mut dyn Queue) {
+        let read = queue.push(Request::Read(0, Box::new([0u8; 16])));
+        let write = queue.push(Request::Write(1, Box::new([9u8; 16])));
+        let long = queue.push(Request::Write(2, Box::new([9u8; 2 * page::DEFAULT_SIZE])));
+        queue.submit().unwrap();
+        let completions = reap_all(queue);
+        for (tag, found) in [(read, 16), (write, 16), (long, 2 * page::DEFAULT_SIZE)] {
+            match &completions[&tag].result {
+                Ok(_) => panic!("allowed buffer of {found} bytes"),
+                Err(error) => assert!(
+                    matches!(
+                        error,
+                        Error::BufferSizeMismatch { expected: page::DEFAULT_SIZE, found: f }
+                            if *f == found
+                    ),
+                    "{error}"
+                ),
+            }
+        }
+
+        let tag = queue.push(Request::Read(1, Box::new([0u8; page::DEFAULT_SIZE])));
+        queue.submit().unwrap();
+        match reap_all(queue).remove(&tag).unwrap().request {
+            Request::Read(_, buf) => assert_eq!([2u8; page::DEFAULT_SIZE][..], *buf),
+            request => panic!("unexpected request {request:?}"),
+        }
+    }
+
+    #[test]
+    fn ring_round_trip() {
+        ephemeral::disk!(tmp {
+            let mut ring = Ring::new(tmp.store(page::DEFAULT_SIZE), 4).unwrap();
+            round_trip(&mut ring);
+            read_past_end(&mut ring);
+            short_buffers(&mut ring);
+            tmp.borrow_mut().set_len(8 * page::DEFAULT_SIZE as u64 + 4096).unwrap();
+            read_partial_page(&mut ring);
+        });
+    }
+
+    #[test]
+    fn pool_round_trip() {
+        ephemeral::disk!(tmp {
+            let mut pool = Pool::new(tmp.store(page::DEFAULT_SIZE), 4).unwrap();
+            round_trip(&mut pool);
+            read_past_end(&mut pool);
+            short_buffers(&mut pool);
+            tmp.borrow_mut().set_len(8 * page::DEFAULT_SIZE as u64 + 4096).unwrap();
+            read_partial_page(&mut pool);
+        });
+    }
+
+    #[test]
+    fn reap_with_nothing_in_flight() {
+        ephemeral::disk!(tmp {
+            let mut queue = open(tmp.store(page::DEFAULT_SIZE), 4).unwrap();
+            assert!(queue.reap(1).unwrap().is_empty());
+        });
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeSet,
+    io,
+    sync::{Arc, Condvar, Mutex, mpsc},
+    thread::{self, JoinHandle},
+};
+
+use crate::dbms::storage::{
+    PageStore,
+    aio::{self, Completion, Queue, Request},
+    store::Disk,
+};
+
+// Page I/O on a pool of threads that make plain blocking calls, for where
+// io_uring is not available.
+pub struct Pool {
+    jobs: Option<mpsc::Sender<(u64, Request)>>,
+    completions: mpsc::Receiver<Completion>,
+    workers: Vec<JoinHandle<()>>,
+    page_size: usize,
+    next: u64,
+    queued: Vec<(u64, Request)>,
+    in_flight: usize,
+    // The tags of the requests that have been submitted but not finished,
+    // which a sync waits for when they were submitted before it.
+    unfinished: Arc<(Mutex<BTreeSet<u64>>, Condvar)>,
+}
+
+impl Pool {
+    pub fn new(disk: Disk, threads: usize) -> io::Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<(u64, Request)>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let (sender, completions) = mpsc::channel();
+        let unfinished = Arc::new((Mutex::new(BTreeSet::new()), Condvar::new()));
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let mut disk = disk.try_clone()?;
+                let receiver = receiver.clone();
+                let sender = sender.clone();
+                let unfinished = unfinished.clone();
+                Ok(thread::spawn(move || {
+                    // Jobs are taken in the order they were submitted, so
+                    // the requests a sync waits for are all running by the
+                    // time the sync is taken.
+                    loop {
+                        // The lock is only held while waiting for a job, not
+                        // while running it.
+                        let job = receiver.lock().unwrap().recv();
+                        let Ok((tag, mut request)) = job else {
+                            return;
+                        };
+                        let result = run(&mut disk, &mut request, tag, &unfinished);
+                        let (lock, condvar) = &*unfinished;
+                        lock.lock().unwrap().remove(&tag);
+                        condvar.notify_all();
+                        let completion = Completion {
+                            tag,
+                            request,
+                            result,
+                        };
+                        if sender.send(completion).is_err() {
+                            return;
+                        }
+                    }
+                }))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            jobs: Some(jobs),
+            completions,
+            workers,
+            page_size: disk.page_size(),
+            next: 0,
+            queued: Vec::new(),
+            in_flight: 0,
+            unfinished,
+        })
+    }
+}
+
+fn run(
+    disk: &mut Disk,
+    request: &mut Request,
+    tag: u64,
+    unfinished: &(Mutex<BTreeSet<u64>>, Condvar),
+) -> crate::dbms::storage::Result<()> {
+    aio::check(request, disk.page_size())?;
+    match request {
+        Request::Read(page, buf) => {
+            let n = disk.read_page(*page, buf)?;
+            aio::result(request, n)
+        }
+        Request::Write(page, buf) => Ok(disk.write_page(*page, buf)?),
+        Request::Sync => {
+            let (lock, condvar) = unfinished;
+            let earlier = condvar
+                .wait_while(lock.lock().unwrap(), |tags| {
+                    tags.range(..tag).next().is_some()
+                })
+                .unwrap();
+            drop(earlier);
+            Ok(disk.sync()?)
+        }
+    }
+}
+
+impl Queue for Pool {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn push(&mut self, request: Request) -> u64 {
+        let tag = self.next;
+        self.next += 1;
+        self.queued.push((tag, request));
+        tag
+    }
+
+    fn submit(&mut self) -> io::Result<()> {
+        let queued = std::mem::take(&mut self.queued);
+        self.unfinished
+            .0
+            .lock()
+            .unwrap()
+            .extend(queued.iter().map(|(tag, _)| *tag));
+        for job in queued {
+            self.jobs
+                .as_ref()
+                .unwrap()
+                .send(job)
+                .map_err(|_| io::Error::other("page i/o pool has stopped"))?;
+            self.in_flight += 1;
+        }
+        Ok(())
+    }
+
+    fn reap(&mut self, min: usize) -> io::Result<Vec<Completion>> {
+        let mut completions = Vec::new();
+        while completions.len() < min.min(self.in_flight) {
+            let completion = self
+                .completions
+                .recv()
+                .map_err(|_| io::Error::other("page i/o pool has stopped"))?;
+            completions.push(completion);
+        }
+        completions.extend(self.completions.try_iter());
+        self.in_flight -= completions.len();
+        Ok(completions)
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        // Closing the channel lets the workers run out of jobs and stop.
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::{collections::HashMap, io, os::fd::AsRawFd};
+
+use io_uring::{IoUring, opcode, squeue, types};
+
+use crate::dbms::storage::{
+    PageStore,
+    aio::{self, Completion, Queue, Request},
+    store::Disk,
+};
+
+// Page I/O through io_uring. A batch of requests goes to the kernel with a
+// single system call, and completions are collected without one unless the
+// caller has to wait for them.
+pub struct Ring {
+    ring: IoUring,
+    disk: Disk,
+    next: u64,
+    queued: Vec<(u64, Request)>,
+    // Requests the kernel is working on, with the number of bytes moved so
+    // far. Their buffers are boxed, so they stay where the kernel expects
+    // them while the requests move around.
+    in_flight: HashMap<u64, (Request, usize)>,
+    // Requests that were refused before they reached the kernel, waiting to
+    // be reaped.
+    refused: Vec<Completion>,
+}
+
+impl Ring {
+    pub fn new(disk: Disk, depth: u32) -> io::Result<Self> {
+        Ok(Self {
+            ring: IoUring::new(depth)?,
+            disk,
+            next: 0,
+            queued: Vec::new(),
+            in_flight: HashMap::new(),
+            refused: Vec::new(),
+        })
+    }
+
+    // Builds the entry that moves the part of the page that is left after
+    // the first `done` bytes.
+    fn entry(&mut self, tag: u64, request: &mut Request, done: usize) -> squeue::Entry {
+        let fd = types::Fd(self.disk.file().as_raw_fd());
+        let size = self.disk.page_size();
+        let entry = match request {
+            Request::Read(page, buf) => {
+                let rest = &mut buf[done..];
+                opcode::Read::new(fd, rest.as_mut_ptr(), rest.len() as u32)
+                    .offset(*page * size as u64 + done as u64)
+                    .build()
+            }
+            Request::Write(page, buf) => {
+                let rest = &buf[done..];
+                opcode::Write::new(fd, rest.as_ptr(), rest.len() as u32)
+                    .offset(*page * size as u64 + done as u64)
+                    .build()
+            }
+            // Draining holds the sync back until everything submitted
+            // before it has completed.
+            Request::Sync => opcode::Fsync::new(fd)
+                .build()
+                .flags(squeue::Flags::IO_DRAIN),
+        };
+        entry.user_data(tag)
+    }
+
+    // Hands a request to the kernel. It only counts as in flight once its
+    // entry is in the submission queue, and is handed back if it never got
+    // there.
+    fn start(
+        &mut self,
+        tag: u64,
+        mut request: Request,
+        done: usize,
+    ) -> Result<(), (io::Error, Request)> {
+        let entry = self.entry(tag, &mut request, done);
+        // SAFETY: the buffer of the request is owned by `in_flight` until
+        // its completion has been reaped, and `drop` waits for that.
+        while unsafe { self.ring.submission().push(&entry) }.is_err() {
+            if let Err(error) = self.ring.submit() {
+                return Err((error, request));
+            }
+        }
+        self.in_flight.insert(tag, (request, done));
+        Ok(())
+    }
+}
+
+impl Queue for Ring {
+    fn page_size(&self) -> usize {
+        self.disk.page_size()
+    }
+
+    fn push(&mut self, request: Request) -> u64 {
+        let tag = self.next;
+        self.next += 1;
+        self.queued.push((tag, request));
+        tag
+    }
+
+    // Requests that did not make it to the kernel are queued again for the
+    // next submit.
+    fn submit(&mut self) -> io::Result<()> {
+        let mut queued = std::mem::take(&mut self.queued).into_iter();
+        while let Some((tag, request)) = queued.next() {
+            if let Err(error) = aio::check(&request, self.disk.page_size()) {
+                self.refused.push(Completion {
+                    tag,
+                    request,
+                    result: Err(error),
+                });
+                continue;
+            }
+            if let Err((error, request)) = self.start(tag, request, 0) {
+                self.queued = [(tag, request)].into_iter().chain(queued).collect();
+                return Err(error);
+            }
+        }
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    fn reap(&mut self, min: usize) -> io::Result<Vec<Completion>> {
+        let mut completions = std::mem::take(&mut self.refused);
+        loop {
+            let wait = min
+                .saturating_sub(completions.len())
+                .min(self.in_flight.len());
+            self.ring.submit_and_wait(wait)?;
+            let finished: Vec<(u64, i32)> = self
+                .ring
+                .completion()
+                .map(|entry| (entry.user_data(), entry.result()))
+                .collect();
+            for (tag, n) in finished {
+                let (request, done) = self.in_flight.remove(&tag).unwrap();
+                let result = match n {
+                    n if n < 0 => Err(io::Error::from_raw_os_error(-n).into()),
+                    // A transfer may stop short of the end of the page
+                    // without having reached the end of the file, in which
+                    // case the rest of it is retried.
+                    n if n > 0 && done + (n as usize) < len(&request) => {
+                        match self.start(tag, request, done + n as usize) {
+                            Ok(()) => continue,
+                            Err((error, request)) => {
+                                completions.push(Completion {
+                                    tag,
+                                    request,
+                                    result: Err(error.into()),
+                                });
+                                continue;
+                            }
+                        }
+                    }
+                    n => aio::result(&request, done + n as usize),
+                };
+                completions.push(Completion {
+                    tag,
+                    request,
+                    result,
+                });
+            }
+            if wait == 0 || completions.len() >= min || self.in_flight.is_empty() {
+                return Ok(completions);
+            }
+        }
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.len() + self.refused.len()
+    }
+}
+
+// The number of bytes a request moves.
+fn len(request: &Request) -> usize {
+    match request {
+        Request::Read(_, buf) | Request::Write(_, buf) => buf.len(),
+        Request::Sync => 0,
+    }
+}
+
+// The kernel may still be writing into the buffers of requests in flight,
+// so they are only let go once it is done with them.
+impl Drop for Ring {
+    fn drop(&mut self) {
+        while !self.in_flight.is_empty() {
+            if self.reap(1).is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use crate::dbms::storage::{
+    Error, PageStore, Result,
+    integrity::Algorithm,
+    meta::{self, Record, record},
+    page::{self, header},
+};
+
+// Marks the end of the free list.
+const NONE: u64 = u64::MAX;
+
+const ALGORITHM: Algorithm = Algorithm::Crc32c;
+
+// Free pages hold nothing but the page header and the number of the next one.
+const NEXT: usize = header::SIZE;
+
+// The allocator keeps its state in a meta page pair. Freed pages form a
+// singly linked list, where every free page stores the number of the next one
+// and the meta pair stores the head. Pages at or past `end` have never been
+// handed out and are allocated by appending to the store.
+struct Root {
+    head: u64,
+    end: u64,
+}
+
+impl Default for Root {
+    fn default() -> Self {
+        Self { head: NONE, end: 0 }
+    }
+}
+
+impl Record for Root {
+    const VERSION: u16 = 1;
+    const SIZE: usize = 16;
+
+    fn encode(&self, writer: &mut record::Writer<'_>) {
+        writer.u64(self.head);
+        writer.u64(self.end);
+    }
+
+    fn decode(&mut self, reader: &mut record::Reader<'_>, _: u16) {
+        self.head = reader.u64();
+        self.end = reader.u64();
+    }
+}
+
+impl Root {
+    fn read(store: &mut dyn PageStore, pair: (u64, u64)) -> Result<Self> {
+        record::read(store, pair)
+    }
+
+    fn write(&self, store: &mut dyn PageStore, pair: (u64, u64)) -> Result<()> {
+        record::write(store, pair, self)
+    }
+}
+
+// Sets up an allocator in the given meta pair. Every page that exists in the
+// store at this point, including the meta pair itself, is considered in use.
+pub fn init(store: &mut dyn PageStore, pair: (u64, u64)) -> Result<()> {
+    meta::init(store, pair)?;
+    let end = page::count(store)?;
+    Root { head: NONE, end }.write(store, pair)
+}
+
+pub fn allocate(store: &mut dyn PageStore, pair: (u64, u64)) -> Result<u64> {
+    let mut root = Root::read(store, pair)?;
+    if root.head == NONE {
+        let page = root.end;
+        let buf = page::buffer(store);
+        page::write(store, page, &buf)?;
+        root.end += 1;
+        root.write(store, pair)?;
+        return Ok(page);
+    }
+    let page = root.head;
+    let mut buf = page::buffer(store);
+    page::read(store, page, &mut buf)?;
+    header::expect(&buf, page, header::Kind::Free)?;
+    root.head = u64::from_le_bytes(buf[NEXT..NEXT + 8].try_into().unwrap());
+    root.write(store, pair)?;
+    // The page is wiped only once the list has moved past it, since the list
+    // would be cut short if that write landed first. A crash in between
+    // leaves a page that looks free without being on the list, which `free`
+    // has to allow for.
+    buf.fill(0);
+    page::write(store, page, &buf)?;
+    Ok(page)
+}
+
+pub fn free(store: &mut dyn PageStore, pair: (u64, u64), page: u64) -> Result<()> {
+    let mut root = Root::read(store, pair)?;
+    if page >= root.end || page == root.head {
+        return Err(Error::Unallocated { page });
+    }
+    if page == pair.0 || page == pair.1 {
+        return Err(Error::AllocatorPage { page });
+    }
+    let mut buf = page::buffer(store);
+    // A page that is free already is somewhere down the list, which freeing
+    // it again would turn into a cycle.
+    page::read(store, page, &mut buf)?;
+    if header::expect(&buf, page, header::Kind::Free).is_ok() && is_listed(store, &root, page)? {
+        return Err(Error::Unallocated { page });
+    }
+    header::init(&mut buf, header::Kind::Free, page, ALGORITHM);
+    buf[NEXT..NEXT + 8].copy_from_slice(&root.head.to_le_bytes());
+    header::seal(&mut buf);
+    page::write(store, page, &buf)?;
+    root.head = page;
+    root.write(store, pair)
+}
+
+// Walks the free list in search of the page. A list cannot hold more pages
+// than were ever handed out, so a longer walk has run into a cycle.
+fn is_listed(store: &mut dyn PageStore, root: &Root, page: u64) -> Result<bool> {
+    let mut buf = page::buffer(store);
+    let mut next = root.head;
+    for _ in 0..root.end {
+        if next == NONE {
+            return Ok(false);
+        }
+        if next == page {
+            return Ok(true);
+        }
+        page::read(store, next, &mut buf)?;
+        header::expect(&buf, next, header::Kind::Free)?;
+        next = u64::from_le_bytes(buf[NEXT..NEXT + 8].try_into().unwrap());
+    }
+    Err(Error::EndlessChain { head: root.head })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::storage::{
+        ephemeral,
+        store::{Fault, Faulty, Memory, Op},
+    };
+
+    fn setup(store: &mut dyn PageStore) {
+        page::write(store, 0, &[0u8; page::DEFAULT_SIZE]).unwrap();
+        page::write(store, 1, &[0u8; page::DEFAULT_SIZE]).unwrap();
+        init(store, (0, 1)).unwrap();
+    }
+
+    #[test]
+    fn init_reserves_existing_pages() {
+        ephemeral::file!(tmp {
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 1, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 2, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            init(tmp.borrow_mut(), (0, 1)).unwrap();
+
+            assert_eq!(3, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+        });
+        ephemeral::file!(tmp {
+            init(tmp.borrow_mut(), (1, 0)).unwrap();
+            assert_eq!(2, allocate(tmp.borrow_mut(), (1, 0)).unwrap());
+        });
+    }
+
+    #[test]
+    fn allocate_appends_when_free_list_is_empty() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            for expected in 2..6 {
+                assert_eq!(expected, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+                assert_eq!(expected + 1, page::count(tmp.borrow_mut()).unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn allocate_reuses_freed_pages() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            for _ in 0..4 {
+                allocate(tmp.borrow_mut(), (0, 1)).unwrap();
+            }
+            free(tmp.borrow_mut(), (0, 1), 3).unwrap();
+            free(tmp.borrow_mut(), (0, 1), 5).unwrap();
+
+            assert_eq!(5, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+            assert_eq!(3, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+            assert_eq!(6, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+            assert_eq!(7, page::count(tmp.borrow_mut()).unwrap());
+        });
+    }
+
+    #[test]
+    fn free_list_survives_reopening_file() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            for _ in 0..3 {
+                allocate(tmp.borrow_mut(), (0, 1)).unwrap();
+            }
+            free(tmp.borrow_mut(), (0, 1), 2).unwrap();
+            free(tmp.borrow_mut(), (0, 1), 4).unwrap();
+
+            let mut reopened = tmp.borrow_mut().clone();
+            assert_eq!(4, allocate(&mut reopened, (0, 1)).unwrap());
+            assert_eq!(2, allocate(&mut reopened, (0, 1)).unwrap());
+            assert_eq!(5, allocate(&mut reopened, (0, 1)).unwrap());
+        });
+    }
+
+    #[test]
+    fn free_given_unallocated_page() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            allocate(tmp.borrow_mut(), (0, 1)).unwrap();
+            match free(tmp.borrow_mut(), (0, 1), 3) {
+                Ok(_) => panic!("allowed freeing page past the end"),
+                Err(error) => assert!(matches!(error, Error::Unallocated { page: 3 }), "{error}"),
+            }
+            free(tmp.borrow_mut(), (0, 1), 2).unwrap();
+            match free(tmp.borrow_mut(), (0, 1), 2) {
+                Ok(_) => panic!("allowed freeing page twice"),
+                Err(error) => assert!(matches!(error, Error::Unallocated { page: 2 }), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn free_given_page_down_the_free_list() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            for _ in 0..2 {
+                allocate(tmp.borrow_mut(), (0, 1)).unwrap();
+            }
+            free(tmp.borrow_mut(), (0, 1), 2).unwrap();
+            free(tmp.borrow_mut(), (0, 1), 3).unwrap();
+            match free(tmp.borrow_mut(), (0, 1), 2) {
+                Ok(_) => panic!("allowed freeing page twice"),
+                Err(error) => assert!(matches!(error, Error::Unallocated { page: 2 }), "{error}"),
+            }
+            assert_eq!(3, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+            assert_eq!(2, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+            assert_eq!(4, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+        });
+    }
+
+    #[test]
+    fn allocate_zeroes_reused_page() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            allocate(tmp.borrow_mut(), (0, 1)).unwrap();
+            free(tmp.borrow_mut(), (0, 1), 2).unwrap();
+            assert_eq!(2, allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+            let mut buf = [1u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 2, &mut buf).unwrap();
+            assert_eq!([0u8; page::DEFAULT_SIZE], buf);
+
+            // Once handed out again, the page can be freed again.
+            free(tmp.borrow_mut(), (0, 1), 2).unwrap();
+        });
+    }
+
+    #[test]
+    fn free_after_crash_before_wiping_page() {
+        let mut store = Faulty::new(Memory::new());
+        setup(&mut store);
+        for _ in 0..2 {
+            allocate(&mut store, (0, 1)).unwrap();
+        }
+        free(&mut store, (0, 1), 2).unwrap();
+        free(&mut store, (0, 1), 3).unwrap();
+        // The list moves past page 3, which keeps looking free.
+        store.inject(Fault::Fail(Op::Write, Some(3)));
+        assert!(allocate(&mut store, (0, 1)).is_err());
+        store.heal();
+
+        free(&mut store, (0, 1), 3).unwrap();
+        assert_eq!(3, allocate(&mut store, (0, 1)).unwrap());
+        assert_eq!(2, allocate(&mut store, (0, 1)).unwrap());
+        assert_eq!(4, allocate(&mut store, (0, 1)).unwrap());
+    }
+
+    #[test]
+    fn free_given_meta_page() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            for page in [0, 1] {
+                match free(tmp.borrow_mut(), (0, 1), page) {
+                    Ok(_) => panic!("allowed freeing allocator meta page"),
+                    Err(error) => assert!(
+                        matches!(error, Error::AllocatorPage { page: p } if p == page),
+                        "{error}"
+                    ),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn allocate_when_free_list_is_corrupt() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            allocate(tmp.borrow_mut(), (0, 1)).unwrap();
+            free(tmp.borrow_mut(), (0, 1), 2).unwrap();
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 2, &mut buf).unwrap();
+            buf[NEXT] = !buf[NEXT];
+            page::write(tmp.borrow_mut(), 2, &buf).unwrap();
+
+            match allocate(tmp.borrow_mut(), (0, 1)) {
+                Ok(_) => panic!("allowed allocating from corrupt free list"),
+                Err(error) => assert!(matches!(error, Error::ChecksumMismatch { page: 2, .. }), "{error}"),
+            }
+
+            // The head was overwritten with a page of another kind.
+            header::init(&mut buf, header::Kind::Slotted, 2, ALGORITHM);
+            header::seal(&mut buf);
+            page::write(tmp.borrow_mut(), 2, &buf).unwrap();
+            match allocate(tmp.borrow_mut(), (0, 1)) {
+                Ok(_) => panic!("allowed allocating from corrupt free list"),
+                Err(error) => assert!(
+                    matches!(
+                        error,
+                        Error::BadHeader { page: 2, error: header::Error::UnexpectedKind(3) }
+                    ),
+                    "{error}"
+                ),
+            }
+        });
+    }
+}
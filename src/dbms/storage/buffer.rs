@@ -0,0 +1,536 @@
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
+};
+
+use crate::dbms::storage::{Error, PageStore, Result, page};
+
+pub mod policy;
+
+use policy::Policy;
+
+type Frame = Box<[u8]>;
+
+#[derive(Default)]
+struct Descriptor {
+    page: Option<u64>,
+    pins: usize,
+    dirty: bool,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+struct State {
+    table: HashMap<u64, usize>,
+    descriptors: Vec<Descriptor>,
+    policy: Box<dyn Policy>,
+    stats: Stats,
+}
+
+pub struct Pool {
+    store: RefCell<Box<dyn PageStore>>,
+    frames: Box<[RefCell<Frame>]>,
+    state: RefCell<State>,
+}
+
+impl Pool {
+    pub fn new(store: impl PageStore + 'static, capacity: usize) -> Self {
+        Self::with_policy(store, capacity, policy::Clock::new())
+    }
+
+    pub fn with_policy(
+        store: impl PageStore + 'static,
+        capacity: usize,
+        policy: impl Policy + 'static,
+    ) -> Self {
+        if capacity == 0 {
+            panic!("buffer pool must have at least one frame");
+        }
+        let page_size = store.page_size();
+        Self {
+            store: RefCell::new(Box::new(store)),
+            frames: (0..capacity)
+                .map(|_| RefCell::new(vec![0u8; page_size].into_boxed_slice()))
+                .collect(),
+            state: RefCell::new(State {
+                table: HashMap::with_capacity(capacity),
+                descriptors: (0..capacity).map(|_| Descriptor::default()).collect(),
+                policy: Box::new(policy),
+                stats: Stats::default(),
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.state.borrow().stats
+    }
+
+    pub fn pin(&self, page: u64) -> Result<Guard<'_>> {
+        if let Some(guard) = self.pin_resident(page) {
+            return Ok(guard);
+        }
+        let frame = self.claim()?;
+        page::read(
+            self.store.borrow_mut().as_mut(),
+            page,
+            &mut self.frames[frame].borrow_mut(),
+        )?;
+        Ok(self.install(frame, page, false))
+    }
+
+    // Pins a page without reading it from the store, which is how pages that
+    // do not exist yet are brought into the pool. The frame starts out
+    // zeroed and dirty so that it reaches the store on eviction or flush.
+    pub fn pin_new(&self, page: u64) -> Result<Guard<'_>> {
+        if let Some(guard) = self.pin_resident(page) {
+            self.frames[guard.frame].borrow_mut().fill(0);
+            self.state.borrow_mut().descriptors[guard.frame].dirty = true;
+            return Ok(guard);
+        }
+        let frame = self.claim()?;
+        self.frames[frame].borrow_mut().fill(0);
+        Ok(self.install(frame, page, true))
+    }
+
+    pub fn flush_page(&self, page: u64) -> Result<()> {
+        let frame = match self.state.borrow().table.get(&page) {
+            Some(frame) => *frame,
+            None => return Ok(()),
+        };
+        self.write_back(frame)
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        let mut dirty: Vec<(u64, usize)> = {
+            let state = self.state.borrow();
+            state
+                .table
+                .iter()
+                .filter(|(_, frame)| state.descriptors[**frame].dirty)
+                .map(|(page, frame)| (*page, *frame))
+                .collect()
+        };
+        // Pages past the end of the store can only be appended one at a time,
+        // so new pages must be written in ascending order.
+        dirty.sort_unstable();
+        for (_, frame) in dirty {
+            self.write_back(frame)?;
+        }
+        Ok(self.store.borrow_mut().sync()?)
+    }
+
+    fn pin_resident(&self, page: u64) -> Option<Guard<'_>> {
+        let mut state = self.state.borrow_mut();
+        let Some(frame) = state.table.get(&page).copied() else {
+            state.stats.misses += 1;
+            return None;
+        };
+        state.stats.hits += 1;
+        state.descriptors[frame].pins += 1;
+        state.policy.access(page);
+        Some(Guard {
+            pool: self,
+            frame,
+            page,
+        })
+    }
+
+    fn install(&self, frame: usize, page: u64, dirty: bool) -> Guard<'_> {
+        let mut state = self.state.borrow_mut();
+        state.table.insert(page, frame);
+        state.descriptors[frame] = Descriptor {
+            page: Some(page),
+            pins: 1,
+            dirty,
+        };
+        state.policy.admit(page);
+        Guard {
+            pool: self,
+            frame,
+            page,
+        }
+    }
+
+    // Finds a frame that can be reused, writing back its contents if they
+    // have been modified. The frame is left unmapped and must be installed
+    // by the caller.
+    fn claim(&self) -> Result<usize> {
+        let frame = self.victim()?;
+        let result = self.write_back(frame);
+        let mut state = self.state.borrow_mut();
+        if let Err(error) = result {
+            // The page stays cached so that its modifications are not lost,
+            // which means it has to be handed back to the policy.
+            if let Some(page) = state.descriptors[frame].page {
+                state.policy.admit(page);
+            }
+            return Err(error);
+        }
+        if let Some(page) = state.descriptors[frame].page.take() {
+            state.table.remove(&page);
+            state.stats.evictions += 1;
+        }
+        Ok(frame)
+    }
+
+    fn victim(&self) -> Result<usize> {
+        let mut state = self.state.borrow_mut();
+        if let Some(frame) = state.descriptors.iter().position(|d| d.page.is_none()) {
+            return Ok(frame);
+        }
+        let State {
+            table,
+            descriptors,
+            policy,
+            ..
+        } = &mut *state;
+        match policy.evict(&|page| descriptors[table[&page]].pins == 0) {
+            Some(page) => Ok(table[&page]),
+            None => Err(Error::AllFramesPinned),
+        }
+    }
+
+    fn write_back(&self, frame: usize) -> Result<()> {
+        let page = {
+            let state = self.state.borrow();
+            match state.descriptors[frame] {
+                Descriptor {
+                    page: Some(page),
+                    dirty: true,
+                    ..
+                } => page,
+                _ => return Ok(()),
+            }
+        };
+        // Pages past the end of the store can only be appended one at a time,
+        // so the pages between the end and this one are written first. Those
+        // that are not cached were never pinned, and hold zeroes.
+        let end = page::count(self.store.borrow_mut().as_mut())?;
+        for missing in end..page {
+            let resident = self.state.borrow().table.get(&missing).copied();
+            match resident {
+                Some(frame) => self.write_back(frame)?,
+                None => {
+                    let mut store = self.store.borrow_mut();
+                    let zeroes = page::buffer(store.as_ref());
+                    page::write(store.as_mut(), missing, &zeroes)?;
+                }
+            }
+        }
+        page::write(
+            self.store.borrow_mut().as_mut(),
+            page,
+            &self.frames[frame].borrow(),
+        )?;
+        self.state.borrow_mut().descriptors[frame].dirty = false;
+        Ok(())
+    }
+
+    fn unpin(&self, frame: usize) {
+        self.state.borrow_mut().descriptors[frame].pins -= 1;
+    }
+}
+
+pub struct Guard<'a> {
+    pool: &'a Pool,
+    frame: usize,
+    page: u64,
+}
+
+impl Guard<'_> {
+    pub fn page(&self) -> u64 {
+        self.page
+    }
+
+    pub fn read(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.pool.frames[self.frame].borrow(), |frame| &**frame)
+    }
+
+    pub fn write(&self) -> RefMut<'_, [u8]> {
+        self.pool.state.borrow_mut().descriptors[self.frame].dirty = true;
+        RefMut::map(self.pool.frames[self.frame].borrow_mut(), |frame| {
+            &mut **frame
+        })
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.pool.unpin(self.frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::storage::{
+        ephemeral,
+        store::{Fault, Faulty, Memory, Op},
+    };
+
+    #[test]
+    fn pin_reads_page_from_file() {
+        ephemeral::file!(tmp {
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 1, &[2u8; page::DEFAULT_SIZE]).unwrap();
+
+            let pool = Pool::new(tmp.borrow_mut().clone(), 2);
+            assert_eq!([1u8; page::DEFAULT_SIZE], *pool.pin(0).unwrap().read());
+            assert_eq!([2u8; page::DEFAULT_SIZE], *pool.pin(1).unwrap().read());
+        });
+    }
+
+    #[test]
+    fn pin_given_distant_page() {
+        ephemeral::file!(tmp {
+            let pool = Pool::new(tmp.borrow_mut().clone(), 2);
+            match pool.pin(0) {
+                Ok(_) => panic!("allowed pinning distant page"),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 0, end: 0 }), "{error}"),
+            }
+            // The frame claimed for the failed read must still be usable.
+            pool.pin_new(0).unwrap();
+            pool.pin_new(1).unwrap();
+        });
+    }
+
+    #[test]
+    fn pin_when_all_frames_are_pinned() {
+        ephemeral::file!(tmp {
+            let pool = Pool::new(tmp.borrow_mut().clone(), 2);
+            let _first = pool.pin_new(0).unwrap();
+            let _second = pool.pin_new(1).unwrap();
+            match pool.pin_new(2) {
+                Ok(_) => panic!("allowed evicting pinned page"),
+                Err(error) => assert!(matches!(error, Error::AllFramesPinned), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn pin_shares_frame_between_guards() {
+        ephemeral::file!(tmp {
+            let pool = Pool::new(tmp.borrow_mut().clone(), 1);
+            let first = pool.pin_new(0).unwrap();
+            let second = pool.pin(0).unwrap();
+            first.write()[0] = 7;
+            assert_eq!(7, second.read()[0]);
+            drop(first);
+            // The page is still pinned through the second guard.
+            assert!(pool.pin_new(1).is_err());
+            drop(second);
+            assert!(pool.pin_new(1).is_ok());
+        });
+    }
+
+    #[test]
+    fn eviction_writes_back_dirty_pages() {
+        ephemeral::file!(tmp {
+            let pool = Pool::new(tmp.borrow_mut().clone(), 1);
+            pool.pin_new(0).unwrap().write().fill(3);
+            // Nothing reaches the store while the page is cached.
+            assert_eq!(0, tmp.borrow_mut().size().unwrap());
+
+            pool.pin_new(1).unwrap().write().fill(4);
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
+            assert_eq!([3u8; page::DEFAULT_SIZE], buf);
+            assert_eq!([3u8; page::DEFAULT_SIZE], *pool.pin(0).unwrap().read());
+        });
+    }
+
+    #[test]
+    fn eviction_writes_back_pages_past_the_end_in_order() {
+        ephemeral::file!(tmp {
+            let pool = Pool::new(tmp.borrow_mut().clone(), 2);
+            pool.pin_new(3).unwrap().write().fill(3);
+            pool.pin_new(1).unwrap().write().fill(1);
+            // Evicting page 3 writes page 1 before it, and zeroes for the
+            // pages that were never pinned.
+            pool.pin_new(4).unwrap().write().fill(4);
+            pool.pin_new(5).unwrap();
+            assert_eq!(4, page::count(tmp.borrow_mut()).unwrap());
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            for (page, n) in [(0, 0), (1, 1), (2, 0), (3, 3)] {
+                page::read(tmp.borrow_mut(), page, &mut buf).unwrap();
+                assert_eq!([n; page::DEFAULT_SIZE], buf);
+            }
+            pool.flush().unwrap();
+            assert_eq!(6, page::count(tmp.borrow_mut()).unwrap());
+        });
+    }
+
+    #[test]
+    fn eviction_when_write_back_fails() {
+        let store = Faulty::new(Memory::new());
+        let pool = Pool::new(store.clone(), 1);
+        pool.pin_new(0).unwrap().write().fill(1);
+        store.inject(Fault::Fail(Op::Write, Some(0)));
+        assert!(pool.pin_new(1).is_err());
+        // The page stays cached, and nothing was evicted.
+        assert_eq!(0, pool.stats().evictions);
+        assert_eq!([1u8; page::DEFAULT_SIZE], *pool.pin(0).unwrap().read());
+
+        store.heal();
+        pool.pin_new(1).unwrap();
+        assert_eq!(1, pool.stats().evictions);
+    }
+
+    #[test]
+    fn eviction_skips_clean_pages() {
+        ephemeral::file!(tmp {
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 1, &[2u8; page::DEFAULT_SIZE]).unwrap();
+
+            let pool = Pool::new(tmp.borrow_mut().clone(), 1);
+            pool.pin(0).unwrap();
+            // Change the page behind the pool's back. A clean eviction must
+            // not overwrite it with the cached copy.
+            page::write(tmp.borrow_mut(), 0, &[5u8; page::DEFAULT_SIZE]).unwrap();
+            pool.pin(1).unwrap();
+
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
+            assert_eq!([5u8; page::DEFAULT_SIZE], buf);
+        });
+    }
+
+    #[test]
+    fn eviction_defaults_to_clock() {
+        ephemeral::file!(tmp {
+            for page in 0..3 {
+                page::write(tmp.borrow_mut(), page, &[page as u8; page::DEFAULT_SIZE]).unwrap();
+            }
+            let pool = Pool::new(tmp.borrow_mut().clone(), 2);
+            pool.pin(0).unwrap();
+            pool.pin(1).unwrap();
+            // Sweeping clears both reference bits and evicts page 0.
+            pool.pin(2).unwrap();
+            assert!(!pool.state.borrow().table.contains_key(&0));
+            // Page 1 was left without its reference bit, so it goes before
+            // the recently loaded page 2.
+            pool.pin(0).unwrap();
+            let state = pool.state.borrow();
+            assert!(state.table.contains_key(&0));
+            assert!(!state.table.contains_key(&1));
+            assert!(state.table.contains_key(&2));
+        });
+    }
+
+    #[test]
+    fn flush_writes_dirty_pages_in_order() {
+        ephemeral::file!(tmp {
+            let pool = Pool::new(tmp.borrow_mut().clone(), 3);
+            pool.pin_new(2).unwrap().write().fill(3);
+            pool.pin_new(0).unwrap().write().fill(1);
+            pool.pin_new(1).unwrap().write().fill(2);
+            pool.flush().unwrap();
+
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            for page in 0..3 {
+                page::read(tmp.borrow_mut(), page, &mut buf).unwrap();
+                assert_eq!([page as u8 + 1; page::DEFAULT_SIZE], buf);
+            }
+            assert!(pool.state.borrow().descriptors.iter().all(|d| !d.dirty));
+        });
+    }
+
+    #[test]
+    fn flush_keeps_pages_dirty_when_write_fails() {
+        let store = Faulty::new(Memory::new());
+        let pool = Pool::new(store.clone(), 2);
+        pool.pin_new(0).unwrap().write().fill(1);
+        pool.pin_new(1).unwrap().write().fill(2);
+        store.inject(Fault::Fail(Op::Write, Some(1)));
+        assert!(pool.flush().is_err());
+
+        store.heal();
+        pool.flush().unwrap();
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        page::read(&mut store.into_inner(), 1, &mut buf).unwrap();
+        assert_eq!([2u8; page::DEFAULT_SIZE], buf);
+    }
+
+    #[test]
+    fn flush_page_writes_single_page() {
+        ephemeral::file!(tmp {
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 1, &[1u8; page::DEFAULT_SIZE]).unwrap();
+
+            let pool = Pool::new(tmp.borrow_mut().clone(), 2);
+            pool.pin(0).unwrap().write().fill(8);
+            pool.pin(1).unwrap().write().fill(9);
+            pool.flush_page(1).unwrap();
+
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
+            assert_eq!([1u8; page::DEFAULT_SIZE], buf);
+            page::read(tmp.borrow_mut(), 1, &mut buf).unwrap();
+            assert_eq!([9u8; page::DEFAULT_SIZE], buf);
+            // Flushing a page that is not cached is a no-op.
+            pool.flush_page(5).unwrap();
+        });
+    }
+
+    #[test]
+    fn stats_count_hits_misses_and_evictions() {
+        ephemeral::file!(tmp {
+            for page in 0..3 {
+                page::write(tmp.borrow_mut(), page, &[0u8; page::DEFAULT_SIZE]).unwrap();
+            }
+            let pool = Pool::new(tmp.borrow_mut().clone(), 2);
+            pool.pin(0).unwrap();
+            pool.pin(0).unwrap();
+            pool.pin(1).unwrap();
+            assert_eq!(Stats { hits: 1, misses: 2, evictions: 0 }, pool.stats());
+            pool.pin(2).unwrap();
+            pool.pin_new(3).unwrap();
+            assert_eq!(Stats { hits: 1, misses: 4, evictions: 2 }, pool.stats());
+        });
+    }
+
+    // Replays a trace of hot point lookups interrupted by sequential scans
+    // over pages that are never read again, and returns the number of misses.
+    fn replay(policy: impl Policy + 'static) -> u64 {
+        let mut misses = 0;
+        ephemeral::file!(tmp {
+            for page in 0..80 {
+                page::write(tmp.borrow_mut(), page, &[0u8; page::DEFAULT_SIZE]).unwrap();
+            }
+            let pool = Pool::with_policy(tmp.borrow_mut().clone(), 8, policy);
+            for round in 0..8 {
+                for page in 0..4 {
+                    pool.pin(page).unwrap();
+                }
+                if round % 2 == 1 {
+                    for page in 16 + round * 8..24 + round * 8 {
+                        pool.pin(page).unwrap();
+                    }
+                }
+            }
+            misses = pool.stats().misses;
+        });
+        misses
+    }
+
+    #[test]
+    fn scan_resistant_policies_keep_hot_pages() {
+        // Plain LRU loses the hot pages to every scan.
+        assert_eq!(4 + 4 * 8 + 3 * 4, replay(policy::Lru::new()));
+        // The hot pages have been accessed twice before the first scan, so
+        // they outlive every page of it.
+        assert_eq!(4 + 4 * 8, replay(policy::LruK::new(2, 64)));
+        // The hot pages are lost to the first scan, but are promoted when
+        // they come back and survive the following ones.
+        assert_eq!(4 + 4 * 8 + 4, replay(policy::TwoQ::new(8)));
+    }
+}
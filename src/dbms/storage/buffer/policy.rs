@@ -0,0 +1,425 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+// Decides which cached page gets replaced when the pool runs out of frames.
+// Policies only deal in page numbers, which lets them remember pages that
+// are no longer resident.
+pub trait Policy {
+    // The page was loaded into the pool. This counts as its first access.
+    fn admit(&mut self, page: u64);
+
+    // The page was found in the pool.
+    fn access(&mut self, page: u64);
+
+    // Picks a resident page for which `evictable` holds and stops tracking it
+    // as resident.
+    fn evict(&mut self, evictable: &dyn Fn(u64) -> bool) -> Option<u64>;
+}
+
+#[derive(Default)]
+pub struct Clock {
+    ring: Vec<Option<(u64, bool)>>,
+    index: HashMap<u64, usize>,
+    hand: usize,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Policy for Clock {
+    fn admit(&mut self, page: u64) {
+        let slot = match self.ring.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => {
+                self.ring.push(None);
+                self.ring.len() - 1
+            }
+        };
+        self.ring[slot] = Some((page, true));
+        self.index.insert(page, slot);
+    }
+
+    fn access(&mut self, page: u64) {
+        if let Some(slot) = self.index.get(&page)
+            && let Some((_, referenced)) = &mut self.ring[*slot]
+        {
+            *referenced = true;
+        }
+    }
+
+    fn evict(&mut self, evictable: &dyn Fn(u64) -> bool) -> Option<u64> {
+        // Two full sweeps are enough to clear every reference bit and come
+        // back around to an evictable page, if there is one.
+        for _ in 0..self.ring.len() * 2 {
+            let hand = self.hand;
+            self.hand = (hand + 1) % self.ring.len();
+            let Some((page, referenced)) = &mut self.ring[hand] else {
+                continue;
+            };
+            if !evictable(*page) {
+                continue;
+            }
+            if *referenced {
+                *referenced = false;
+                continue;
+            }
+            let page = *page;
+            self.ring[hand] = None;
+            self.index.remove(&page);
+            return Some(page);
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct Lru {
+    tick: u64,
+    recency: BTreeMap<u64, u64>,
+    index: HashMap<u64, u64>,
+}
+
+impl Lru {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn touch(&mut self, page: u64) {
+        if let Some(tick) = self.index.insert(page, self.tick) {
+            self.recency.remove(&tick);
+        }
+        self.recency.insert(self.tick, page);
+        self.tick += 1;
+    }
+}
+
+impl Policy for Lru {
+    fn admit(&mut self, page: u64) {
+        self.touch(page);
+    }
+
+    fn access(&mut self, page: u64) {
+        if self.index.contains_key(&page) {
+            self.touch(page);
+        }
+    }
+
+    fn evict(&mut self, evictable: &dyn Fn(u64) -> bool) -> Option<u64> {
+        let (tick, page) = self
+            .recency
+            .iter()
+            .find(|(_, page)| evictable(**page))
+            .map(|(tick, page)| (*tick, *page))?;
+        self.recency.remove(&tick);
+        self.index.remove(&page);
+        Some(page)
+    }
+}
+
+// Evicts the page whose K-th most recent access lies furthest in the past.
+// Pages with fewer than K accesses are considered infinitely distant and go
+// first, oldest first, which keeps a single scan from flushing pages that are
+// referenced repeatedly. The access history of evicted pages is retained so
+// that a page coming back is not treated as new.
+pub struct LruK {
+    k: usize,
+    retain: usize,
+    tick: u64,
+    history: HashMap<u64, VecDeque<u64>>,
+    resident: HashSet<u64>,
+}
+
+impl LruK {
+    pub fn new(k: usize, retain: usize) -> Self {
+        if k == 0 {
+            panic!("LRU-K requires k to be at least 1");
+        }
+        Self {
+            k,
+            retain,
+            tick: 0,
+            history: HashMap::new(),
+            resident: HashSet::new(),
+        }
+    }
+
+    fn touch(&mut self, page: u64) {
+        let history = self.history.entry(page).or_default();
+        history.push_front(self.tick);
+        history.truncate(self.k);
+        self.tick += 1;
+    }
+
+    // Orders candidates so that the smallest key is the best victim.
+    fn key(&self, page: u64) -> (bool, u64) {
+        let history = &self.history[&page];
+        match history.get(self.k - 1) {
+            Some(kth) => (true, *kth),
+            None => (false, *history.back().unwrap()),
+        }
+    }
+
+    fn prune(&mut self) {
+        let excess = (self.history.len() - self.resident.len()).saturating_sub(self.retain);
+        if excess == 0 {
+            return;
+        }
+        let mut forgotten: Vec<(u64, u64)> = self
+            .history
+            .iter()
+            .filter(|(page, _)| !self.resident.contains(page))
+            .map(|(page, history)| (history[0], *page))
+            .collect();
+        forgotten.sort_unstable();
+        for (_, page) in forgotten.into_iter().take(excess) {
+            self.history.remove(&page);
+        }
+    }
+}
+
+impl Policy for LruK {
+    fn admit(&mut self, page: u64) {
+        self.resident.insert(page);
+        self.touch(page);
+    }
+
+    fn access(&mut self, page: u64) {
+        if self.resident.contains(&page) {
+            self.touch(page);
+        }
+    }
+
+    fn evict(&mut self, evictable: &dyn Fn(u64) -> bool) -> Option<u64> {
+        let page = self
+            .resident
+            .iter()
+            .copied()
+            .filter(|page| evictable(*page))
+            .min_by_key(|page| self.key(*page))?;
+        self.resident.remove(&page);
+        self.prune();
+        Some(page)
+    }
+}
+
+// The full 2Q algorithm. First-time pages enter a FIFO queue (A1in) and only
+// graduate to the LRU queue (Am) if they are requested again shortly after
+// being evicted, which is tracked through a queue of evicted page numbers
+// (A1out).
+pub struct TwoQ {
+    kin: usize,
+    kout: usize,
+    a1in: VecDeque<u64>,
+    a1out: VecDeque<u64>,
+    am: Lru,
+}
+
+impl TwoQ {
+    // Uses the queue sizes recommended by the paper, a quarter of the pool
+    // for A1in and half of it for A1out.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_sizes((capacity / 4).max(1), (capacity / 2).max(1))
+    }
+
+    pub fn with_sizes(kin: usize, kout: usize) -> Self {
+        Self {
+            kin,
+            kout,
+            a1in: VecDeque::new(),
+            a1out: VecDeque::new(),
+            am: Lru::new(),
+        }
+    }
+
+    fn evict_a1in(&mut self, evictable: &dyn Fn(u64) -> bool) -> Option<u64> {
+        let position = self.a1in.iter().position(|page| evictable(*page))?;
+        let page = self.a1in.remove(position).unwrap();
+        self.a1out.push_back(page);
+        Some(page)
+    }
+}
+
+impl Policy for TwoQ {
+    fn admit(&mut self, page: u64) {
+        match self.a1out.iter().position(|ghost| *ghost == page) {
+            Some(position) => {
+                self.a1out.remove(position);
+                self.am.admit(page);
+            }
+            None => self.a1in.push_back(page),
+        }
+        // A1out is only trimmed once the page that caused the eviction has
+        // been looked up in it, otherwise a page could be forgotten right
+        // before it is requested.
+        while self.a1out.len() > self.kout {
+            self.a1out.pop_front();
+        }
+    }
+
+    fn access(&mut self, page: u64) {
+        // Hits in A1in are deliberately ignored, a burst of accesses right
+        // after loading says nothing about long term popularity.
+        self.am.access(page);
+    }
+
+    fn evict(&mut self, evictable: &dyn Fn(u64) -> bool) -> Option<u64> {
+        if self.a1in.len() > self.kin {
+            self.evict_a1in(evictable)
+                .or_else(|| self.am.evict(evictable))
+        } else {
+            self.am
+                .evict(evictable)
+                .or_else(|| self.evict_a1in(evictable))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn any(_: u64) -> bool {
+        true
+    }
+
+    #[test]
+    fn clock_evicts_unreferenced_pages_first() {
+        let mut clock = Clock::new();
+        clock.admit(0);
+        clock.admit(1);
+        clock.admit(2);
+        // Sweeping clears every reference bit and evicts page 0.
+        assert_eq!(Some(0), clock.evict(&any));
+        // Page 1 is next in line, unless it is referenced again.
+        clock.access(1);
+        assert_eq!(Some(2), clock.evict(&any));
+        clock.admit(3);
+        assert_eq!(Some(1), clock.evict(&any));
+    }
+
+    #[test]
+    fn clock_skips_pages_that_are_not_evictable() {
+        let mut clock = Clock::new();
+        clock.admit(0);
+        clock.admit(1);
+        assert_eq!(Some(1), clock.evict(&|page| page != 0));
+        assert_eq!(None, clock.evict(&|page| page != 0));
+        assert_eq!(Some(0), clock.evict(&any));
+        assert_eq!(None, clock.evict(&any));
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut lru = Lru::new();
+        lru.admit(0);
+        lru.admit(1);
+        lru.admit(2);
+        lru.access(0);
+        assert_eq!(Some(1), lru.evict(&any));
+        assert_eq!(Some(0), lru.evict(&|page| page != 2));
+        assert_eq!(Some(2), lru.evict(&any));
+        assert_eq!(None, lru.evict(&any));
+    }
+
+    #[test]
+    fn lru_ignores_access_to_evicted_pages() {
+        let mut lru = Lru::new();
+        lru.admit(0);
+        assert_eq!(Some(0), lru.evict(&any));
+        lru.access(0);
+        assert_eq!(None, lru.evict(&any));
+    }
+
+    #[test]
+    fn lru_k_prefers_pages_with_fewer_than_k_accesses() {
+        let mut lru = LruK::new(2, 8);
+        lru.admit(0);
+        lru.access(0);
+        lru.admit(1);
+        lru.admit(2);
+        // Pages 1 and 2 are infinitely distant, so the oldest of them goes.
+        assert_eq!(Some(1), lru.evict(&any));
+        assert_eq!(Some(2), lru.evict(&any));
+        assert_eq!(Some(0), lru.evict(&any));
+    }
+
+    #[test]
+    fn lru_k_compares_kth_most_recent_access() {
+        let mut lru = LruK::new(2, 8);
+        lru.admit(0);
+        lru.admit(1);
+        lru.access(1);
+        lru.access(0);
+        lru.access(0);
+        // Page 1 was accessed most recently, but its second to last access
+        // is older than the one of page 0.
+        lru.access(1);
+        assert_eq!(Some(1), lru.evict(&any));
+    }
+
+    #[test]
+    fn lru_k_remembers_evicted_pages() {
+        let mut lru = LruK::new(2, 8);
+        lru.admit(0);
+        assert_eq!(Some(0), lru.evict(&any));
+        lru.admit(0);
+        lru.admit(1);
+        // Page 0 now has two accesses on record, page 1 only one.
+        assert_eq!(Some(1), lru.evict(&any));
+    }
+
+    #[test]
+    fn lru_k_forgets_oldest_history_beyond_retention() {
+        let mut lru = LruK::new(2, 1);
+        for page in 0..4 {
+            lru.admit(page);
+            assert_eq!(Some(page), lru.evict(&any));
+        }
+        assert_eq!(1, lru.history.len());
+        assert!(lru.history.contains_key(&3));
+    }
+
+    #[test]
+    fn two_q_promotes_pages_requested_after_eviction() {
+        let mut two_q = TwoQ::with_sizes(1, 2);
+        two_q.admit(0);
+        two_q.admit(1);
+        // A1in is over its size, so its oldest page goes to A1out.
+        assert_eq!(Some(0), two_q.evict(&any));
+        two_q.admit(0);
+        assert_eq!(vec![0], two_q.am.index.keys().copied().collect::<Vec<_>>());
+        two_q.admit(2);
+        // Page 0 is hot now and outlives the pages that only came by once.
+        assert_eq!(Some(1), two_q.evict(&any));
+        assert_eq!(Some(0), two_q.evict(&any));
+        assert_eq!(Some(2), two_q.evict(&any));
+    }
+
+    #[test]
+    fn two_q_bounds_ghost_queue() {
+        let mut two_q = TwoQ::with_sizes(1, 2);
+        for page in 0..4 {
+            two_q.admit(page);
+            two_q.admit(page + 100);
+            assert_eq!(Some(page), two_q.evict(&any));
+            assert_eq!(Some(page + 100), two_q.evict(&any));
+        }
+        // The queue is trimmed on the next admission.
+        two_q.admit(200);
+        assert_eq!(VecDeque::from([3, 103]), two_q.a1out);
+    }
+
+    #[test]
+    fn two_q_falls_back_to_other_queue() {
+        let mut two_q = TwoQ::with_sizes(1, 2);
+        two_q.admit(0);
+        two_q.admit(1);
+        assert_eq!(Some(0), two_q.evict(&any));
+        two_q.admit(0);
+        // Only A1in holds an evictable page.
+        assert_eq!(Some(1), two_q.evict(&|page| page != 0));
+        assert_eq!(Some(0), two_q.evict(&any));
+    }
+}
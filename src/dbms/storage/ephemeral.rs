@@ -2,32 +2,59 @@
 #[macro_export]
 macro_rules! file {
     ($name: ident $body: block) => {{
-        let mut $name = $crate::dbms::storage::ephemeral::File::new(
-            format!(
+        let mut $name = $crate::dbms::storage::ephemeral::File::new();
+        $body
+    }};
+}
+
+// For the few tests that need an actual file, such as those of the log, which
+// is not kept in pages.
+#[cfg(test)]
+#[macro_export]
+macro_rules! disk {
+    ($name: ident $body: block) => {{
+        let mut $name =
+            $crate::dbms::storage::ephemeral::Disk::new(std::env::temp_dir().join(format!(
                 "{}-{}.test",
                 module_path!().replace("::", "-"),
                 rand::random::<u32>()
-            )
-            .to_string(),
-        )
-        .unwrap();
+            )))
+            .unwrap();
         $body
     }};
 }
 
+#[cfg(test)]
 #[allow(unused_imports)]
-pub(crate) use file;
+pub(crate) use {disk, file};
 
 #[cfg(test)]
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct File {
-    handle: std::fs::File,
-    path: String,
+    store: crate::dbms::storage::store::Memory,
 }
 
 #[cfg(test)]
 impl File {
-    pub fn new(path: String) -> std::io::Result<Self> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn borrow_mut(&mut self) -> &mut crate::dbms::storage::store::Memory {
+        &mut self.store
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug)]
+pub struct Disk {
+    handle: std::fs::File,
+    path: std::path::PathBuf,
+}
+
+#[cfg(test)]
+impl Disk {
+    pub fn new(path: std::path::PathBuf) -> std::io::Result<Self> {
         Ok(Self {
             handle: std::fs::File::options()
                 .read(true)
@@ -42,10 +69,20 @@ impl File {
     pub fn borrow_mut(&mut self) -> &mut std::fs::File {
         &mut self.handle
     }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    // A page store on the file, with pages of the given size.
+    pub fn store(&self, page_size: usize) -> crate::dbms::storage::store::Disk {
+        crate::dbms::storage::store::Disk::create(self.handle.try_clone().unwrap(), page_size)
+            .unwrap()
+    }
 }
 
 #[cfg(test)]
-impl Drop for File {
+impl Drop for Disk {
     fn drop(&mut self) {
         std::fs::remove_file(&self.path).unwrap()
     }
@@ -58,11 +95,15 @@ mod tests {
         io::{Read, Seek, Write},
     };
 
+    use crate::dbms::storage::{page, store::PageStore};
+
     #[test]
     #[allow(unused_mut)]
     fn test_macro() {
-        file!(tmp {
-            assert!(tmp.path.starts_with("shepherd-dbms-storage-ephemeral"));
+        disk!(tmp {
+            let name = tmp.path.file_name().unwrap().to_str().unwrap();
+            assert!(name.starts_with("shepherd-dbms-storage-ephemeral"));
+            assert!(tmp.path.starts_with(std::env::temp_dir()));
         });
     }
 
@@ -70,11 +111,14 @@ mod tests {
     #[allow(unused_mut)]
     fn file_is_deleted_when_dropped() {
         let path;
-        file!(tmp {
+        disk!(tmp {
             path = tmp.path.clone();
         });
         match fs::exists(&path) {
-            Ok(true) => panic!("volatile file {path} exists after being dropped"),
+            Ok(true) => panic!(
+                "volatile file {} exists after being dropped",
+                path.display()
+            ),
             Err(error) => panic!("error returned when checking volatile file existence {error}"),
             _ => {}
         }
@@ -82,14 +126,14 @@ mod tests {
 
     #[test]
     fn file_is_writable() {
-        file!(tmp {
+        disk!(tmp {
             assert!(tmp.borrow_mut().write_all(&[0u8; 16]).is_ok());
         });
     }
 
     #[test]
     fn file_is_readable() {
-        file!(tmp {
+        disk!(tmp {
             tmp.borrow_mut().write_all(&[1u8; 16]).unwrap();
             tmp.borrow_mut().seek(std::io::SeekFrom::Start(0)).unwrap();
 
@@ -98,4 +142,13 @@ mod tests {
             assert_eq!(read_buffer, [1u8; 16]);
         });
     }
+
+    #[test]
+    fn memory_file_starts_empty() {
+        file!(tmp {
+            assert_eq!(0, tmp.borrow_mut().size().unwrap());
+            tmp.borrow_mut().write_page(0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            assert_eq!(page::DEFAULT_SIZE as u64, tmp.borrow_mut().size().unwrap());
+        });
+    }
 }
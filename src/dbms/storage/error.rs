@@ -0,0 +1,350 @@
+use std::{error, fmt, io};
+
+use crate::dbms::storage::{
+    page::{self, header},
+    superblock,
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // The page lies past the end of the file. Pages can only be written
+    // right at the end, which extends the file by one page.
+    OutOfRange {
+        page: u64,
+        end: u64,
+    },
+    CopyToSelf {
+        page: u64,
+    },
+    ChecksumMismatch {
+        page: u64,
+        expected: u64,
+        found: u64,
+    },
+    // The file ended in the middle of the page.
+    ShortRead {
+        page: u64,
+        expected: usize,
+        found: usize,
+    },
+    BadHeader {
+        page: u64,
+        error: header::Error,
+    },
+    UnsupportedPageSize {
+        size: usize,
+    },
+    // A meta page records another page size than that of the store, so the
+    // file was opened with the wrong one.
+    PageSizeMismatch {
+        page: u64,
+        expected: usize,
+        found: usize,
+    },
+    // A buffer handed in for a page does not hold exactly one page.
+    BufferSizeMismatch {
+        expected: usize,
+        found: usize,
+    },
+    // No meta page at the start of the file is intact at any page size.
+    UnknownPageSize,
+    // The superblock does not start with the magic number.
+    NotADatabase,
+    // Only an empty store can be formatted as a new database.
+    NotEmpty,
+    // The page was never handed out by the allocator, or is free already.
+    Unallocated {
+        page: u64,
+    },
+    // The page holds the state of the allocator itself.
+    AllocatorPage {
+        page: u64,
+    },
+    NoSuchRecord {
+        page: u64,
+        slot: u16,
+    },
+    RecordTooLarge {
+        size: usize,
+        max: usize,
+    },
+    // A record stub leads to a slot that holds no moved record.
+    DanglingStub {
+        page: u64,
+        slot: u16,
+    },
+    // A page of the free space map has no room where the tree above it says
+    // there is some.
+    StaleFreeSpaceMap {
+        page: u64,
+    },
+    // A linked chain of pages leads back into itself, which following it
+    // would never get out of.
+    EndlessChain {
+        head: u64,
+    },
+    // The buffer pool has no frame left to read a page into.
+    AllFramesPinned,
+    NotALog,
+    LogChecksumMismatch {
+        lsn: u64,
+        expected: u64,
+        found: u64,
+    },
+    // The log record matches its checksum, but does not make sense.
+    CorruptLogRecord {
+        lsn: u64,
+    },
+    // The record was discarded when the log was truncated.
+    TruncatedLogRecord {
+        lsn: u64,
+    },
+    // A flush of the log failed, after which there is no telling which of
+    // the buffered records reached the file.
+    LogFailed,
+    PendingLogRecords,
+    ActiveTransactions,
+    UnsupportedFormat {
+        version: u16,
+    },
+    // The file uses on-disk structures that this version does not know.
+    UnsupportedFeatures {
+        features: u64,
+    },
+    // A meta record was written by a newer version, with a layout that
+    // this version does not know.
+    UnsupportedRecord {
+        version: u16,
+        expected: u16,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OutOfRange { page, end } => {
+                write!(
+                    f,
+                    "page {page} is out of range, the file ends at page {end}"
+                )
+            }
+            Error::CopyToSelf { page } => write!(f, "tried to copy page {page} to itself"),
+            Error::ChecksumMismatch {
+                page,
+                expected,
+                found,
+            } => write!(
+                f,
+                "checksum mismatch on page {page}, expected {expected:#x} but found {found:#x}"
+            ),
+            Error::ShortRead {
+                page,
+                expected,
+                found,
+            } => write!(
+                f,
+                "short read of page {page}, expected {expected} bytes but found {found}"
+            ),
+            Error::BadHeader { page, error } => write!(f, "bad header on page {page}, {error}"),
+            Error::UnsupportedPageSize { size } => write!(
+                f,
+                "unsupported page size {size}, expected a power of two from {} to {}",
+                page::MIN_SIZE,
+                page::MAX_SIZE
+            ),
+            Error::PageSizeMismatch {
+                page,
+                expected,
+                found,
+            } => write!(
+                f,
+                "page {page} records a page size of {found} bytes, expected {expected}"
+            ),
+            Error::BufferSizeMismatch { expected, found } => write!(
+                f,
+                "buffer of {found} bytes does not hold a page of {expected} bytes"
+            ),
+            Error::UnknownPageSize => write!(f, "no intact meta page to take the page size from"),
+            Error::NotADatabase => write!(f, "file is not a shepherd database"),
+            Error::NotEmpty => write!(f, "tried to format store that is not empty"),
+            Error::Unallocated { page } => write!(f, "tried to free unallocated page {page}"),
+            Error::AllocatorPage { page } => {
+                write!(f, "tried to free allocator meta page {page}")
+            }
+            Error::NoSuchRecord { page, slot } => {
+                write!(f, "no such record in slot {slot} of page {page}")
+            }
+            Error::RecordTooLarge { size, max } => write!(
+                f,
+                "record of {size} bytes does not fit in a page, which holds at most {max}"
+            ),
+            Error::DanglingStub { page, slot } => write!(
+                f,
+                "record stub points to slot {slot} of page {page}, which holds no record"
+            ),
+            Error::StaleFreeSpaceMap { page } => {
+                write!(f, "free space map page {page} is stale")
+            }
+            Error::EndlessChain { head } => {
+                write!(f, "chain of pages starting at page {head} runs in a cycle")
+            }
+            Error::AllFramesPinned => write!(f, "all buffer frames are pinned"),
+            Error::NotALog => write!(f, "file is not a write-ahead log"),
+            Error::LogChecksumMismatch {
+                lsn,
+                expected,
+                found,
+            } => write!(
+                f,
+                "checksum mismatch on log record {lsn}, expected {expected:#x} but found {found:#x}"
+            ),
+            Error::CorruptLogRecord { lsn } => write!(f, "log record {lsn} is corrupt"),
+            Error::TruncatedLogRecord { lsn } => {
+                write!(f, "tried to read truncated log record {lsn}")
+            }
+            Error::LogFailed => write!(f, "write-ahead log failed to flush"),
+            Error::PendingLogRecords => write!(f, "tried to truncate log with pending records"),
+            Error::ActiveTransactions => {
+                write!(f, "tried to truncate log with active transactions")
+            }
+            Error::UnsupportedFormat { version } => write!(
+                f,
+                "unsupported format version {version}, expected {}",
+                superblock::VERSION
+            ),
+            Error::UnsupportedFeatures { features } => {
+                write!(f, "unsupported features {features:#x}")
+            }
+            Error::UnsupportedRecord { version, expected } => write!(
+                f,
+                "unsupported meta record version {version}, expected at most {expected}"
+            ),
+            Error::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::BadHeader { error, .. } => Some(error),
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+// Lets code that still deals in `io::Result` pass storage errors on with `?`.
+// The typed error stays available through `io::Error::get_ref`.
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::Io(error) => return error,
+            Error::OutOfRange { .. }
+            | Error::CopyToSelf { .. }
+            | Error::UnsupportedPageSize { .. }
+            | Error::BufferSizeMismatch { .. }
+            | Error::NotEmpty
+            | Error::Unallocated { .. }
+            | Error::AllocatorPage { .. }
+            | Error::RecordTooLarge { .. }
+            | Error::PendingLogRecords
+            | Error::ActiveTransactions => io::ErrorKind::InvalidInput,
+            Error::ChecksumMismatch { .. }
+            | Error::BadHeader { .. }
+            | Error::PageSizeMismatch { .. }
+            | Error::UnknownPageSize
+            | Error::NotADatabase
+            | Error::UnsupportedFormat { .. }
+            | Error::UnsupportedFeatures { .. }
+            | Error::UnsupportedRecord { .. }
+            | Error::DanglingStub { .. }
+            | Error::StaleFreeSpaceMap { .. }
+            | Error::EndlessChain { .. }
+            | Error::NotALog
+            | Error::LogChecksumMismatch { .. }
+            | Error::CorruptLogRecord { .. } => io::ErrorKind::InvalidData,
+            Error::NoSuchRecord { .. } | Error::TruncatedLogRecord { .. } => {
+                io::ErrorKind::NotFound
+            }
+            Error::AllFramesPinned => io::ErrorKind::ResourceBusy,
+            Error::LogFailed => io::ErrorKind::Other,
+            Error::ShortRead { .. } => io::ErrorKind::UnexpectedEof,
+        };
+        io::Error::new(kind, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            "page 4 is out of range, the file ends at page 2",
+            Error::OutOfRange { page: 4, end: 2 }.to_string()
+        );
+        assert_eq!(
+            "checksum mismatch on page 3, expected 0xab but found 0x12",
+            Error::ChecksumMismatch {
+                page: 3,
+                expected: 0xAB,
+                found: 0x12
+            }
+            .to_string()
+        );
+        assert_eq!(
+            "bad header on page 1, page 2 was found in its place",
+            Error::BadHeader {
+                page: 1,
+                error: header::Error::Misdirected {
+                    expected: 1,
+                    found: 2
+                }
+            }
+            .to_string()
+        );
+        assert_eq!(
+            "unsupported page size 3000, expected a power of two from 4096 to 65536",
+            Error::UnsupportedPageSize { size: 3000 }.to_string()
+        );
+    }
+
+    #[test]
+    fn io_error_is_source() {
+        let error = Error::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io::ErrorKind::PermissionDenied, source.kind());
+    }
+
+    #[test]
+    fn into_io_error_keeps_typed_error() {
+        let error = io::Error::from(Error::ShortRead {
+            page: 1,
+            expected: 8192,
+            found: 100,
+        });
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::ShortRead { found: 100, .. })
+        ));
+
+        // Plain I/O errors are passed on as they are.
+        let error = io::Error::from(Error::Io(io::Error::from(io::ErrorKind::NotFound)));
+        assert_eq!(io::ErrorKind::NotFound, error.kind());
+        assert!(error.get_ref().is_none());
+    }
+}
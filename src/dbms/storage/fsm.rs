@@ -0,0 +1,354 @@
+use crate::dbms::storage::{
+    Error, PageStore, Result, alloc,
+    integrity::Algorithm,
+    page::{self, header},
+};
+
+// Marks the end of the chain of map pages.
+pub const NONE: u64 = u64::MAX;
+
+const ALGORITHM: Algorithm = Algorithm::Crc32c;
+
+// Every page of the map holds the page header, the number of the next page
+// of the map and a tree of categories.
+const NEXT: usize = header::SIZE;
+const TREE: usize = NEXT + 8;
+
+// The free space of a page is kept as one of 256 categories, each a 256th of
+// the page size. A page in a category has at least that many bytes free, so
+// the map never points at a page that is too full, though it may pass over a
+// page with just enough room.
+const CATEGORIES: usize = 256;
+
+// How many pages a page of the map keeps the categories of. The tree of a
+// page is complete, so that its leaves are in the order of the pages.
+const fn leaves(page_size: usize) -> usize {
+    let mut leaves = 1;
+    while 4 * leaves - 1 <= page_size - TREE {
+        leaves *= 2;
+    }
+    leaves
+}
+
+// Trees of categories are kept in arrays, with the children of a node at
+// 2n + 1 and 2n + 2 and the leaves in the second half. Every node holds the
+// largest category below it, so finding the first leaf of at least a given
+// category takes a walk from the root down.
+fn set(tree: &mut [u8], leaf: usize, category: u8) {
+    let mut node = tree.len() / 2 + leaf;
+    tree[node] = category;
+    while node > 0 {
+        node = (node - 1) / 2;
+        tree[node] = tree[2 * node + 1].max(tree[2 * node + 2]);
+    }
+}
+
+fn search(tree: &[u8], category: u8) -> Option<usize> {
+    if tree.first().is_none_or(|root| *root < category) {
+        return None;
+    }
+    let mut node = 0;
+    while node < tree.len() / 2 {
+        node = if tree[2 * node + 1] >= category {
+            2 * node + 1
+        } else {
+            2 * node + 2
+        };
+    }
+    Some(node - tree.len() / 2)
+}
+
+// Keeps the free space of a sequence of pages, which are numbered from zero
+// by whoever owns them. The map is made of a chain of pages taken from the
+// allocator in the given meta pair. Finding a page takes a walk down a tree
+// over the pages of the map, which is kept in memory, and then one down the
+// tree of the map page it leads to.
+#[derive(Debug)]
+pub struct FreeSpaceMap {
+    alloc: (u64, u64),
+    pages: Vec<u64>,
+    top: Vec<u8>,
+    leaves: usize,
+    step: usize,
+}
+
+impl FreeSpaceMap {
+    // An empty map, which takes its first page once it is updated.
+    pub fn new(store: &dyn PageStore, alloc: (u64, u64)) -> Self {
+        Self {
+            alloc,
+            pages: Vec::new(),
+            top: Vec::new(),
+            leaves: leaves(store.page_size()),
+            step: store.page_size() / CATEGORIES,
+        }
+    }
+
+    // Opens the map whose chain starts at the given page.
+    pub fn open(store: &mut dyn PageStore, alloc: (u64, u64), head: u64) -> Result<Self> {
+        let mut map = Self::new(store, alloc);
+        let mut roots = Vec::new();
+        let mut next = head;
+        let mut buf = page::buffer(store);
+        while next != NONE {
+            page::read(store, next, &mut buf)?;
+            verify(&buf, next)?;
+            map.pages.push(next);
+            roots.push(buf[TREE]);
+            next = u64::from_le_bytes(buf[NEXT..TREE].try_into().unwrap());
+        }
+        map.build(&roots);
+        Ok(map)
+    }
+
+    // The first page of the chain, or `NONE` while the map has no pages.
+    pub fn head(&self) -> u64 {
+        self.pages.first().copied().unwrap_or(NONE)
+    }
+
+    fn category(&self, free: usize) -> u8 {
+        (free / self.step).min(CATEGORIES - 1) as u8
+    }
+
+    // Records how many bytes are free in the given page.
+    pub fn update(&mut self, store: &mut dyn PageStore, page: u64, free: usize) -> Result<()> {
+        let index = page as usize / self.leaves;
+        while self.pages.len() <= index {
+            self.grow(store)?;
+        }
+        let mut buf = page::buffer(store);
+        page::read(store, self.pages[index], &mut buf)?;
+        verify(&buf, self.pages[index])?;
+        let category = self.category(free);
+        let tree = &mut buf[TREE..TREE + 2 * self.leaves - 1];
+        set(tree, page as usize % self.leaves, category);
+        let root = tree[0];
+        header::seal(&mut buf);
+        page::write(store, self.pages[index], &buf)?;
+        set(&mut self.top, index, root);
+        Ok(())
+    }
+
+    // Finds the first page other than the given one with at least the given
+    // number of bytes free, as far as the categories tell.
+    pub fn find(
+        &self,
+        store: &mut dyn PageStore,
+        size: usize,
+        except: Option<u64>,
+    ) -> Result<Option<u64>> {
+        let category = size.div_ceil(self.step);
+        if category >= CATEGORIES {
+            return Ok(None);
+        }
+        let category = category as u8;
+        let excepted = |index| except.is_some_and(|page| page as usize / self.leaves == index);
+        // The page to pass over is left out of the trees on the way down to
+        // it, which are copies.
+        let mut top = self.top.clone();
+        let mut buf = page::buffer(store);
+        while let Some(index) = search(&top, category) {
+            page::read(store, self.pages[index], &mut buf)?;
+            verify(&buf, self.pages[index])?;
+            let tree = &mut buf[TREE..TREE + 2 * self.leaves - 1];
+            if let Some(page) = except
+                && excepted(index)
+            {
+                set(tree, page as usize % self.leaves, 0);
+            }
+            match search(tree, category) {
+                Some(leaf) => return Ok(Some((index * self.leaves + leaf) as u64)),
+                None if excepted(index) => set(&mut top, index, tree[0]),
+                None => {
+                    return Err(Error::StaleFreeSpaceMap {
+                        page: self.pages[index],
+                    });
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn grow(&mut self, store: &mut dyn PageStore) -> Result<()> {
+        let page = alloc::allocate(store, self.alloc)?;
+        let mut buf = page::buffer(store);
+        header::init(&mut buf, header::Kind::FreeSpace, page, ALGORITHM);
+        buf[NEXT..TREE].copy_from_slice(&NONE.to_le_bytes());
+        header::seal(&mut buf);
+        page::write(store, page, &buf)?;
+        if let Some(&last) = self.pages.last() {
+            page::read(store, last, &mut buf)?;
+            verify(&buf, last)?;
+            buf[NEXT..TREE].copy_from_slice(&page.to_le_bytes());
+            header::seal(&mut buf);
+            page::write(store, last, &buf)?;
+        }
+        self.pages.push(page);
+        let mut roots: Vec<u8> = (0..self.top.len().div_ceil(2))
+            .map(|index| self.top[self.top.len() / 2 + index])
+            .take(self.pages.len() - 1)
+            .collect();
+        roots.push(0);
+        self.build(&roots);
+        Ok(())
+    }
+
+    // Builds the tree over the pages of the map from their roots.
+    fn build(&mut self, roots: &[u8]) {
+        if roots.is_empty() {
+            self.top.clear();
+            return;
+        }
+        self.top = vec![0u8; 2 * roots.len().next_power_of_two() - 1];
+        for (index, root) in roots.iter().enumerate() {
+            set(&mut self.top, index, *root);
+        }
+    }
+}
+
+fn verify(buf: &[u8], page: u64) -> Result<()> {
+    header::expect(buf, page, header::Kind::FreeSpace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::storage::store::Memory;
+
+    fn setup(store: &mut dyn PageStore) {
+        let zeroes = page::buffer(store);
+        page::write(store, 0, &zeroes).unwrap();
+        page::write(store, 1, &zeroes).unwrap();
+        alloc::init(store, (0, 1)).unwrap();
+    }
+
+    #[test]
+    fn search_finds_first_leaf_of_category() {
+        let mut tree = [0u8; 15];
+        for (leaf, category) in [(1, 3), (4, 9), (6, 5)] {
+            set(&mut tree, leaf, category);
+        }
+        assert_eq!(9, tree[0]);
+        assert_eq!(Some(1), search(&tree, 1));
+        assert_eq!(Some(1), search(&tree, 3));
+        assert_eq!(Some(4), search(&tree, 4));
+        assert_eq!(Some(4), search(&tree, 9));
+        assert_eq!(None, search(&tree, 10));
+        assert_eq!(None, search(&[], 1));
+
+        set(&mut tree, 4, 0);
+        assert_eq!(5, tree[0]);
+        assert_eq!(Some(6), search(&tree, 4));
+    }
+
+    #[test]
+    fn leaves_fit_in_page() {
+        let mut size = page::MIN_SIZE;
+        while size <= page::MAX_SIZE {
+            let leaves = leaves(size);
+            assert!(leaves.is_power_of_two());
+            assert!(TREE + 2 * leaves - 1 <= size);
+            assert!(TREE + 4 * leaves - 1 > size);
+            size *= 2;
+        }
+    }
+
+    #[test]
+    fn find_given_size() {
+        let mut store = Memory::new();
+        setup(&mut store);
+        let mut map = FreeSpaceMap::new(&store, (0, 1));
+        assert_eq!(None, map.find(&mut store, 10, None).unwrap());
+
+        map.update(&mut store, 0, 100).unwrap();
+        map.update(&mut store, 1, 4000).unwrap();
+        map.update(&mut store, 2, 8000).unwrap();
+        assert_eq!(Some(0), map.find(&mut store, 90, None).unwrap());
+        assert_eq!(Some(1), map.find(&mut store, 101, None).unwrap());
+        assert_eq!(Some(2), map.find(&mut store, 4001, None).unwrap());
+        assert_eq!(None, map.find(&mut store, 8001, None).unwrap());
+
+        // The page to pass over is skipped, and the search goes on past it.
+        assert_eq!(Some(1), map.find(&mut store, 90, Some(0)).unwrap());
+        assert_eq!(Some(2), map.find(&mut store, 101, Some(1)).unwrap());
+        assert_eq!(None, map.find(&mut store, 4001, Some(2)).unwrap());
+
+        // Categories round the free space down, so a page with just enough
+        // room may be passed over, but never one without.
+        map.update(&mut store, 0, 95).unwrap();
+        assert_eq!(Some(1), map.find(&mut store, 95, None).unwrap());
+    }
+
+    #[test]
+    fn update_spreads_over_map_pages() {
+        let mut store = Memory::with_page_size(4096);
+        setup(&mut store);
+        let mut map = FreeSpaceMap::new(&store, (0, 1));
+        let leaves = leaves(4096) as u64;
+        map.update(&mut store, 3 * leaves + 5, 2000).unwrap();
+        assert_eq!(4, map.pages.len());
+        assert_eq!(
+            Some(3 * leaves + 5),
+            map.find(&mut store, 1000, None).unwrap()
+        );
+
+        map.update(&mut store, leaves, 3000).unwrap();
+        assert_eq!(Some(leaves), map.find(&mut store, 1000, None).unwrap());
+        assert_eq!(Some(leaves), map.find(&mut store, 2500, None).unwrap());
+        assert_eq!(None, map.find(&mut store, 3500, None).unwrap());
+        // Passing over the only page with room in a map page moves on to the
+        // next map page with room.
+        assert_eq!(
+            Some(3 * leaves + 5),
+            map.find(&mut store, 1000, Some(leaves)).unwrap()
+        );
+
+        let mut reopened = FreeSpaceMap::open(&mut store, (0, 1), map.head()).unwrap();
+        assert_eq!(map.pages, reopened.pages);
+        assert_eq!(Some(leaves), reopened.find(&mut store, 2500, None).unwrap());
+        reopened.update(&mut store, leaves, 0).unwrap();
+        assert_eq!(
+            Some(3 * leaves + 5),
+            reopened.find(&mut store, 1000, None).unwrap()
+        );
+    }
+
+    #[test]
+    fn open_given_corrupt_page() {
+        let mut store = Memory::new();
+        setup(&mut store);
+        let mut map = FreeSpaceMap::new(&store, (0, 1));
+        map.update(&mut store, 0, 100).unwrap();
+        let mut buf = page::buffer(&store);
+        page::read(&mut store, map.head(), &mut buf).unwrap();
+        buf[TREE] ^= 0x01;
+        page::write(&mut store, map.head(), &buf).unwrap();
+        match FreeSpaceMap::open(&mut store, (0, 1), map.head()) {
+            Ok(_) => panic!("allowed opening corrupt free space map"),
+            Err(error) => assert!(
+                matches!(error, Error::ChecksumMismatch { page, .. } if page == map.head()),
+                "{error}"
+            ),
+        }
+    }
+
+    #[test]
+    fn find_when_map_page_disagrees_with_top() {
+        let mut store = Memory::new();
+        setup(&mut store);
+        let mut map = FreeSpaceMap::new(&store, (0, 1));
+        map.update(&mut store, 0, 100).unwrap();
+        let mut buf = page::buffer(&store);
+        page::read(&mut store, map.head(), &mut buf).unwrap();
+        buf[TREE..].fill(0);
+        header::seal(&mut buf);
+        page::write(&mut store, map.head(), &buf).unwrap();
+        match map.find(&mut store, 50, None) {
+            Ok(found) => panic!("allowed finding page in stale map, found {found:?}"),
+            Err(error) => assert!(
+                matches!(error, Error::StaleFreeSpaceMap { page } if page == map.head()),
+                "{error}"
+            ),
+        }
+    }
+}
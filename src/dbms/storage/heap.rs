@@ -0,0 +1,853 @@
+use std::collections::HashMap;
+
+use crate::dbms::storage::{
+    Error, PageStore, Result, alloc,
+    fsm::{self, FreeSpaceMap},
+    integrity::Algorithm,
+    meta::{self, Record, record},
+    page::{self, header, slot},
+};
+
+// Marks the end of the directory.
+const NONE: u64 = u64::MAX;
+
+const ALGORITHM: Algorithm = Algorithm::Crc32c;
+
+// The pages of a heap are listed in a chain of directory pages, every one
+// holding the page header, the number of the next directory page, the number
+// of entries and the entries themselves.
+const NEXT: usize = header::SIZE;
+const COUNT: usize = NEXT + 8;
+const ENTRIES: usize = COUNT + 2;
+
+// How many heap pages a directory page lists.
+const fn entries(page_size: usize) -> usize {
+    (page_size - ENTRIES) / 8
+}
+
+// Every record is stored behind a tag telling what the slot holds. A record
+// that outgrows its page is moved to another one and leaves a stub behind, so
+// that its id stays the same.
+const PLAIN: u8 = 0;
+const STUB: u8 = 1;
+// Reached through a stub only, scans pass over it.
+const MOVED: u8 = 2;
+
+// Records shorter than a stub are padded to its size, so that a stub always
+// fits in their place. The byte after the tag of a padded record holds its
+// length.
+const PADDED: u8 = 0x80;
+const STUB_SIZE: usize = 1 + 8 + 2;
+
+// How many consecutive pages a scan reads at once.
+const BATCH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rid {
+    pub page: u64,
+    pub slot: slot::Id,
+}
+
+fn encode(tag: u8, record: &[u8]) -> Vec<u8> {
+    if 1 + record.len() >= STUB_SIZE {
+        return [&[tag], record].concat();
+    }
+    let mut stored = vec![0u8; STUB_SIZE];
+    stored[0] = tag | PADDED;
+    stored[1] = record.len() as u8;
+    stored[2..2 + record.len()].copy_from_slice(record);
+    stored
+}
+
+fn decode(stored: &[u8]) -> (u8, &[u8]) {
+    if stored[0] & PADDED != 0 {
+        (stored[0] & !PADDED, &stored[2..2 + stored[1] as usize])
+    } else {
+        (stored[0], &stored[1..])
+    }
+}
+
+fn stub(target: Rid) -> Vec<u8> {
+    let mut stored = vec![STUB];
+    stored.extend_from_slice(&target.page.to_le_bytes());
+    stored.extend_from_slice(&target.slot.to_le_bytes());
+    stored
+}
+
+fn target(record: &[u8]) -> Rid {
+    Rid {
+        page: u64::from_le_bytes(record[0..8].try_into().unwrap()),
+        slot: slot::Id::from_le_bytes(record[8..10].try_into().unwrap()),
+    }
+}
+
+fn no_such_record(rid: Rid) -> Error {
+    Error::NoSuchRecord {
+        page: rid.page,
+        slot: rid.slot,
+    }
+}
+
+// The heap keeps its directory and its free space map in a meta pair of its
+// own. The map has no pages until the heap has one.
+#[derive(Debug, PartialEq)]
+struct Root {
+    head: u64,
+    tail: u64,
+    fsm: u64,
+}
+
+impl Default for Root {
+    fn default() -> Self {
+        Self {
+            head: NONE,
+            tail: NONE,
+            fsm: fsm::NONE,
+        }
+    }
+}
+
+impl Record for Root {
+    const VERSION: u16 = 1;
+    const SIZE: usize = 24;
+
+    fn encode(&self, writer: &mut record::Writer<'_>) {
+        writer.u64(self.head);
+        writer.u64(self.tail);
+        writer.u64(self.fsm);
+    }
+
+    fn decode(&mut self, reader: &mut record::Reader<'_>, _: u16) {
+        self.head = reader.u64();
+        self.tail = reader.u64();
+        self.fsm = reader.u64();
+    }
+}
+
+// An unordered collection of records in slot pages, which are taken from the
+// allocator in the given meta pair as the heap grows. Records are addressed
+// by the page and slot they were inserted into. The free space map numbers
+// the pages of the heap in the order they were added to it.
+#[derive(Debug)]
+pub struct Heap {
+    alloc: (u64, u64),
+    pair: (u64, u64),
+    root: Root,
+    pages: Vec<u64>,
+    index: HashMap<u64, usize>,
+    fsm: FreeSpaceMap,
+    max: usize,
+}
+
+impl Heap {
+    // Sets up an empty heap in the given meta pair.
+    pub fn create(store: &mut dyn PageStore, alloc: (u64, u64), pair: (u64, u64)) -> Result<Self> {
+        meta::init(store, pair)?;
+        Ok(Self::new(store, alloc, pair, Root::default()))
+    }
+
+    // Opens the heap in the given meta pair.
+    pub fn open(store: &mut dyn PageStore, alloc: (u64, u64), pair: (u64, u64)) -> Result<Self> {
+        let root = record::read(store, pair)?;
+        let mut heap = Self::new(store, alloc, pair, root);
+        let mut next = heap.root.head;
+        let mut buf = page::buffer(store);
+        // The tail is taken from the directory itself, since a crash may have
+        // linked a new page to it without recording that in the root.
+        while next != NONE {
+            heap.root.tail = next;
+            page::read(store, next, &mut buf)?;
+            verify(&buf, next)?;
+            let count = u16::from_le_bytes(buf[COUNT..ENTRIES].try_into().unwrap()) as usize;
+            for entry in buf[ENTRIES..ENTRIES + 8 * count].chunks_exact(8) {
+                heap.push(u64::from_le_bytes(entry.try_into().unwrap()));
+            }
+            next = u64::from_le_bytes(buf[NEXT..COUNT].try_into().unwrap());
+        }
+        if heap.root.fsm != fsm::NONE {
+            heap.fsm = FreeSpaceMap::open(store, alloc, heap.root.fsm)?;
+        }
+        Ok(heap)
+    }
+
+    fn new(store: &dyn PageStore, alloc: (u64, u64), pair: (u64, u64), root: Root) -> Self {
+        let mut empty = page::buffer(store);
+        slot::init(&mut empty, 0);
+        Self {
+            alloc,
+            pair,
+            root,
+            pages: Vec::new(),
+            index: HashMap::new(),
+            fsm: FreeSpaceMap::new(store, alloc),
+            max: slot::free_space(&empty) - 1,
+        }
+    }
+
+    // The pages of the heap, in the order they were added to it.
+    pub fn pages(&self) -> impl Iterator<Item = u64> + '_ {
+        self.pages.iter().copied()
+    }
+
+    pub fn insert(&mut self, store: &mut dyn PageStore, record: &[u8]) -> Result<Rid> {
+        self.check(record)?;
+        self.place(store, PLAIN, record, None)
+    }
+
+    pub fn get(&self, store: &mut dyn PageStore, rid: Rid) -> Result<Vec<u8>> {
+        let buf = self.load(store, rid)?;
+        let (tag, record) = decode(slot::read(&buf, rid.slot).map_err(|_| no_such_record(rid))?);
+        match tag {
+            PLAIN => Ok(record.to_vec()),
+            STUB => self.follow(store, target(record)),
+            _ => Err(no_such_record(rid)),
+        }
+    }
+
+    pub fn update(&mut self, store: &mut dyn PageStore, rid: Rid, record: &[u8]) -> Result<()> {
+        self.check(record)?;
+        let mut buf = self.load(store, rid)?;
+        let (tag, stored) = decode(slot::read(&buf, rid.slot).map_err(|_| no_such_record(rid))?);
+        match tag {
+            PLAIN => {
+                if slot::update(&mut buf, rid.slot, &encode(PLAIN, record)).is_err() {
+                    let moved = self.place(store, MOVED, record, Some(rid.page))?;
+                    // The record takes at least as much room as the stub.
+                    slot::update(&mut buf, rid.slot, &stub(moved)).expect("stub fits in place");
+                }
+                self.save(store, rid.page, &buf)
+            }
+            STUB => {
+                let target = target(stored);
+                let mut buf = self.load(store, target)?;
+                if slot::update(&mut buf, target.slot, &encode(MOVED, record)).is_ok() {
+                    return self.save(store, target.page, &buf);
+                }
+                // The stub is pointed at the new copy before the old one goes,
+                // so that a crash in between leaves an orphaned copy at worst,
+                // which no stub leads to.
+                let moved = self.place(store, MOVED, record, Some(target.page))?;
+                // The record may have been moved to the page of the stub.
+                let mut buf = self.load(store, rid)?;
+                slot::update(&mut buf, rid.slot, &stub(moved)).expect("stub fits in place");
+                self.save(store, rid.page, &buf)?;
+                let mut buf = self.load(store, target)?;
+                slot::delete(&mut buf, target.slot).map_err(|_| no_such_record(target))?;
+                self.save(store, target.page, &buf)
+            }
+            _ => Err(no_such_record(rid)),
+        }
+    }
+
+    pub fn delete(&mut self, store: &mut dyn PageStore, rid: Rid) -> Result<()> {
+        let mut buf = self.load(store, rid)?;
+        let (tag, stored) = decode(slot::read(&buf, rid.slot).map_err(|_| no_such_record(rid))?);
+        let target = match tag {
+            PLAIN => None,
+            STUB => Some(target(stored)),
+            _ => return Err(no_such_record(rid)),
+        };
+        slot::delete(&mut buf, rid.slot).map_err(|_| no_such_record(rid))?;
+        self.save(store, rid.page, &buf)?;
+        // The stub goes first, so that a crash in between leaves an orphaned
+        // copy rather than a stub leading nowhere.
+        if let Some(target) = target {
+            let mut buf = self.load(store, target)?;
+            slot::delete(&mut buf, target.slot).map_err(|_| no_such_record(target))?;
+            self.save(store, target.page, &buf)?;
+        }
+        Ok(())
+    }
+
+    // Iterates over every record in the heap, in no particular order.
+    pub fn scan<'a>(&'a self, store: &'a mut dyn PageStore) -> Scan<'a> {
+        Scan {
+            heap: self,
+            store,
+            next: 0,
+            records: Vec::new(),
+        }
+    }
+
+    fn check(&self, record: &[u8]) -> Result<()> {
+        if record.len() > self.max {
+            return Err(Error::RecordTooLarge {
+                size: record.len(),
+                max: self.max,
+            });
+        }
+        Ok(())
+    }
+
+    // Loads the page of the record, which has to be a page of the heap.
+    fn load(&self, store: &mut dyn PageStore, rid: Rid) -> Result<Vec<u8>> {
+        if !self.index.contains_key(&rid.page) {
+            return Err(no_such_record(rid));
+        }
+        load(store, rid.page)
+    }
+
+    fn save(&mut self, store: &mut dyn PageStore, page: u64, buf: &[u8]) -> Result<()> {
+        page::write(store, page, buf)?;
+        self.note(store, page, slot::free_space(buf))
+    }
+
+    fn push(&mut self, page: u64) {
+        self.index.insert(page, self.pages.len());
+        self.pages.push(page);
+    }
+
+    // Records the free space of the page in the map, and the map in the root
+    // once it has its first page.
+    fn note(&mut self, store: &mut dyn PageStore, page: u64, free: usize) -> Result<()> {
+        self.fsm.update(store, self.index[&page] as u64, free)?;
+        if self.root.fsm != self.fsm.head() {
+            self.root.fsm = self.fsm.head();
+            record::write(store, self.pair, &self.root)?;
+        }
+        Ok(())
+    }
+
+    fn follow(&self, store: &mut dyn PageStore, target: Rid) -> Result<Vec<u8>> {
+        let dangling = || Error::DanglingStub {
+            page: target.page,
+            slot: target.slot,
+        };
+        if !self.index.contains_key(&target.page) {
+            return Err(dangling());
+        }
+        let buf = load(store, target.page)?;
+        match slot::read(&buf, target.slot).map(decode) {
+            Ok((MOVED, record)) => Ok(record.to_vec()),
+            _ => Err(dangling()),
+        }
+    }
+
+    // Stores the record in the first page with room for it other than the
+    // given one, adding a page to the heap if there is none.
+    fn place(
+        &mut self,
+        store: &mut dyn PageStore,
+        tag: u8,
+        record: &[u8],
+        except: Option<u64>,
+    ) -> Result<Rid> {
+        let stored = encode(tag, record);
+        let except = except.map(|page| self.index[&page] as u64);
+        while let Some(index) = self.fsm.find(store, stored.len(), except)? {
+            let page = self.pages[index as usize];
+            let mut buf = load(store, page)?;
+            match slot::insert(&mut buf, &stored) {
+                Ok(slot) => {
+                    self.save(store, page, &buf)?;
+                    return Ok(Rid { page, slot });
+                }
+                // The map may be behind the page after a crash.
+                Err(_) => self.note(store, page, slot::free_space(&buf))?,
+            }
+        }
+        let (page, mut buf) = self.grow(store)?;
+        let slot = slot::insert(&mut buf, &stored).expect("record fits in empty page");
+        self.save(store, page, &buf)?;
+        Ok(Rid { page, slot })
+    }
+
+    fn grow(&mut self, store: &mut dyn PageStore) -> Result<(u64, Vec<u8>)> {
+        let page = alloc::allocate(store, self.alloc)?;
+        let mut buf = page::buffer(store);
+        slot::init(&mut buf, page);
+        page::write(store, page, &buf)?;
+
+        let mut directory = page::buffer(store);
+        let tail = self.root.tail;
+        let full = tail == NONE || {
+            page::read(store, tail, &mut directory)?;
+            verify(&directory, tail)?;
+            count(&directory) == entries(directory.len())
+        };
+        if full {
+            // The new directory page is written before anything links to it,
+            // so a crash in between leaves no more than an unreachable page.
+            let next = alloc::allocate(store, self.alloc)?;
+            let mut fresh = page::buffer(store);
+            header::init(&mut fresh, header::Kind::Directory, next, ALGORITHM);
+            fresh[NEXT..COUNT].copy_from_slice(&NONE.to_le_bytes());
+            append(&mut fresh, page);
+            page::write(store, next, &fresh)?;
+            if tail == NONE {
+                self.root.head = next;
+            } else {
+                directory[NEXT..COUNT].copy_from_slice(&next.to_le_bytes());
+                header::seal(&mut directory);
+                page::write(store, tail, &directory)?;
+            }
+            self.root.tail = next;
+            record::write(store, self.pair, &self.root)?;
+        } else {
+            append(&mut directory, page);
+            page::write(store, tail, &directory)?;
+        }
+
+        self.push(page);
+        Ok((page, buf))
+    }
+}
+
+fn count(directory: &[u8]) -> usize {
+    u16::from_le_bytes(directory[COUNT..ENTRIES].try_into().unwrap()) as usize
+}
+
+// Adds the page to the directory page, which must have room for it.
+fn append(directory: &mut [u8], page: u64) {
+    let n = count(directory);
+    directory[ENTRIES + 8 * n..ENTRIES + 8 * (n + 1)].copy_from_slice(&page.to_le_bytes());
+    directory[COUNT..ENTRIES].copy_from_slice(&(n as u16 + 1).to_le_bytes());
+    header::seal(directory);
+}
+
+fn verify(buf: &[u8], page: u64) -> Result<()> {
+    header::expect(buf, page, header::Kind::Directory)
+}
+
+fn load(store: &mut dyn PageStore, page: u64) -> Result<Vec<u8>> {
+    let mut buf = page::buffer(store);
+    page::read(store, page, &mut buf)?;
+    slot::verify_checksum(&buf, page)?;
+    Ok(buf)
+}
+
+// Reads the pages of the heap in batches of consecutive pages, and asks the
+// store to fetch the next batch while the records of one are handed out.
+pub struct Scan<'a> {
+    heap: &'a Heap,
+    store: &'a mut dyn PageStore,
+    next: usize,
+    // The records of the current batch, in reverse.
+    records: Vec<(Rid, Vec<u8>)>,
+}
+
+impl Scan<'_> {
+    // The number of pages from the given one on that are consecutive in the
+    // store, up to a batch.
+    fn run(&self, from: usize) -> usize {
+        let pages = &self.heap.pages[from..];
+        pages
+            .iter()
+            .take(BATCH)
+            .enumerate()
+            .take_while(|(n, page)| **page == pages[0] + *n as u64)
+            .count()
+    }
+
+    fn load(&mut self) -> Result<()> {
+        let first = self.heap.pages[self.next];
+        let len = self.run(self.next);
+        let mut bufs: Vec<Vec<u8>> = (0..len).map(|_| page::buffer(self.store)).collect();
+        let mut slices: Vec<&mut [u8]> = bufs.iter_mut().map(|buf| buf.as_mut_slice()).collect();
+        page::read_range(self.store, first, &mut slices)?;
+        self.next += len;
+        if self.next < self.heap.pages.len() {
+            let start = self.heap.pages[self.next];
+            let end = start + self.run(self.next) as u64;
+            page::prefetch(self.store, start..end)?;
+        }
+        for (page, buf) in (first..).zip(&bufs) {
+            slot::verify_checksum(buf, page)?;
+            for (slot, stored) in slot::records(buf) {
+                let rid = Rid { page, slot };
+                match decode(stored) {
+                    (PLAIN, record) => self.records.push((rid, record.to_vec())),
+                    (STUB, record) => {
+                        let record = self.heap.follow(self.store, target(record))?;
+                        self.records.push((rid, record));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.records.reverse();
+        Ok(())
+    }
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<(Rid, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.records.is_empty() {
+            if self.next == self.heap.pages.len() {
+                return None;
+            }
+            if let Err(error) = self.load() {
+                // A scan that fails does not go on.
+                self.next = self.heap.pages.len();
+                return Some(Err(error));
+            }
+        }
+        self.records.pop().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::dbms::storage::store::{Fault, Faulty, Memory, Op};
+
+    // The allocator in pages 0 and 1, and the heap in pages 2 and 3.
+    fn setup(store: &mut dyn PageStore) -> Heap {
+        let zeroes = page::buffer(store);
+        page::write(store, 0, &zeroes).unwrap();
+        page::write(store, 1, &zeroes).unwrap();
+        alloc::init(store, (0, 1)).unwrap();
+        let pair = (
+            alloc::allocate(store, (0, 1)).unwrap(),
+            alloc::allocate(store, (0, 1)).unwrap(),
+        );
+        Heap::create(store, (0, 1), pair).unwrap()
+    }
+
+    fn all(heap: &Heap, store: &mut dyn PageStore) -> BTreeMap<Rid, Vec<u8>> {
+        heap.scan(store).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn insert_then_get() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let first = heap.insert(&mut store, b"first").unwrap();
+        let second = heap.insert(&mut store, &[7u8; 300]).unwrap();
+        let empty = heap.insert(&mut store, b"").unwrap();
+
+        assert_eq!(first.page, second.page);
+        assert_eq!(b"first", heap.get(&mut store, first).unwrap().as_slice());
+        assert_eq!(vec![7u8; 300], heap.get(&mut store, second).unwrap());
+        assert!(heap.get(&mut store, empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn insert_fills_pages_before_adding_one() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let record = [1u8; 1000];
+        let rids: Vec<Rid> = (0..20)
+            .map(|_| heap.insert(&mut store, &record).unwrap())
+            .collect();
+        let pages: Vec<u64> = heap.pages().collect();
+        assert_eq!(3, pages.len());
+        assert!(rids.iter().take(8).all(|rid| rid.page == pages[0]));
+
+        // Room that is freed up is used again before the heap grows.
+        heap.delete(&mut store, rids[3]).unwrap();
+        assert_eq!(pages[0], heap.insert(&mut store, &record).unwrap().page);
+    }
+
+    #[test]
+    fn insert_given_record_larger_than_page() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        match heap.insert(&mut store, &[0u8; page::DEFAULT_SIZE]) {
+            Ok(_) => panic!("allowed inserting record larger than a page"),
+            Err(error) => assert!(
+                matches!(error, Error::RecordTooLarge { size: page::DEFAULT_SIZE, max } if max == heap.max),
+                "{error}"
+            ),
+        }
+        let max = heap.max;
+        let rid = heap.insert(&mut store, &vec![1u8; max]).unwrap();
+        assert_eq!(max, heap.get(&mut store, rid).unwrap().len());
+    }
+
+    #[test]
+    fn get_given_unknown_record() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let rid = heap.insert(&mut store, b"record").unwrap();
+        heap.delete(&mut store, rid).unwrap();
+        for rid in [rid, Rid { page: 0, slot: 0 }, Rid { page: 99, slot: 0 }] {
+            match heap.get(&mut store, rid) {
+                Ok(_) => panic!("allowed getting record that does not exist"),
+                Err(error) => assert!(
+                    matches!(error, Error::NoSuchRecord { page, slot } if page == rid.page && slot == rid.slot),
+                    "{error}"
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn get_given_stub_to_missing_record() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let rid = heap.insert(&mut store, b"x").unwrap();
+        while heap.pages().count() == 1 {
+            heap.insert(&mut store, &[0u8; 500]).unwrap();
+        }
+        heap.update(&mut store, rid, &[2u8; 4000]).unwrap();
+        let buf = load(&mut store, rid.page).unwrap();
+        let moved = target(decode(slot::read(&buf, rid.slot).unwrap()).1);
+        let mut buf = load(&mut store, moved.page).unwrap();
+        slot::delete(&mut buf, moved.slot).unwrap();
+        page::write(&mut store, moved.page, &buf).unwrap();
+        match heap.get(&mut store, rid) {
+            Ok(_) => panic!("allowed following stub to missing record"),
+            Err(error) => assert!(
+                matches!(error, Error::DanglingStub { page, slot } if page == moved.page && slot == moved.slot),
+                "{error}"
+            ),
+        }
+    }
+
+    #[test]
+    fn update_in_place() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let rid = heap.insert(&mut store, b"short").unwrap();
+        heap.update(&mut store, rid, b"somewhat longer").unwrap();
+        assert_eq!(
+            b"somewhat longer",
+            heap.get(&mut store, rid).unwrap().as_slice()
+        );
+        assert_eq!(1, heap.pages().count());
+    }
+
+    #[test]
+    fn update_moves_record_that_outgrows_page() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let rid = heap.insert(&mut store, b"x").unwrap();
+        while heap.pages().count() == 1 {
+            heap.insert(&mut store, &[0u8; 500]).unwrap();
+        }
+        heap.update(&mut store, rid, &[2u8; 4000]).unwrap();
+        assert_eq!(vec![2u8; 4000], heap.get(&mut store, rid).unwrap());
+
+        // It moves again when it outgrows the page it was moved to, and keeps
+        // its id throughout.
+        heap.update(&mut store, rid, &[3u8; 6000]).unwrap();
+        assert_eq!(vec![3u8; 6000], heap.get(&mut store, rid).unwrap());
+        heap.update(&mut store, rid, b"y").unwrap();
+        assert_eq!(b"y", heap.get(&mut store, rid).unwrap().as_slice());
+
+        let records = all(&heap, &mut store);
+        assert_eq!(Some(&b"y".to_vec()), records.get(&rid));
+        assert_eq!(1, records.values().filter(|record| *record == b"y").count());
+
+        heap.delete(&mut store, rid).unwrap();
+        assert!(heap.get(&mut store, rid).is_err());
+        assert!(!all(&heap, &mut store).values().any(|record| record == b"y"));
+    }
+
+    #[test]
+    fn moved_record_survives_failed_write_of_old_copy() {
+        let mut store = Faulty::new(Memory::new());
+        let mut heap = setup(&mut store);
+        let rid = heap.insert(&mut store, b"x").unwrap();
+        while heap.pages().count() == 1 {
+            heap.insert(&mut store, &[0u8; 500]).unwrap();
+        }
+        heap.update(&mut store, rid, &[2u8; 4000]).unwrap();
+        let pages = heap.pages().count();
+        while heap.pages().count() == pages {
+            heap.insert(&mut store, &[0u8; 500]).unwrap();
+        }
+        let moved = |store: &mut dyn PageStore| {
+            let buf = load(store, rid.page).unwrap();
+            target(decode(slot::read(&buf, rid.slot).unwrap()).1)
+        };
+
+        // The old copy stays behind when the record moves again.
+        let old = moved(&mut store);
+        store.inject(Fault::Fail(Op::Write, Some(old.page)));
+        assert!(heap.update(&mut store, rid, &[3u8; 6000]).is_err());
+        store.heal();
+        assert_ne!(old, moved(&mut store));
+        assert_eq!(vec![3u8; 6000], heap.get(&mut store, rid).unwrap());
+        assert!(
+            !all(&heap, &mut store)
+                .values()
+                .any(|record| record.len() == 4000)
+        );
+
+        // Neither is the moved copy removed when the record is deleted.
+        let new = moved(&mut store);
+        store.inject(Fault::Fail(Op::Write, Some(new.page)));
+        assert!(heap.delete(&mut store, rid).is_err());
+        store.heal();
+        assert!(heap.get(&mut store, rid).is_err());
+        assert!(
+            !all(&heap, &mut store)
+                .values()
+                .any(|record| record.len() == 6000)
+        );
+    }
+
+    #[test]
+    fn scan_returns_every_record_once() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let mut expected = BTreeMap::new();
+        for n in 0..200u32 {
+            let record = n.to_le_bytes().repeat(150 + n as usize % 200);
+            expected.insert(heap.insert(&mut store, &record).unwrap(), record);
+        }
+        for (n, rid) in expected
+            .keys()
+            .copied()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .enumerate()
+        {
+            if n % 3 == 0 {
+                heap.delete(&mut store, rid).unwrap();
+                expected.remove(&rid);
+            }
+        }
+        assert!(heap.pages().count() > BATCH);
+        assert_eq!(expected, all(&heap, &mut store));
+    }
+
+    #[test]
+    fn scan_of_empty_heap() {
+        let mut store = Memory::new();
+        let heap = setup(&mut store);
+        assert_eq!(0, heap.scan(&mut store).count());
+    }
+
+    #[test]
+    fn open_restores_heap() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let mut rids = Vec::new();
+        for n in 0..300u32 {
+            rids.push(
+                heap.insert(&mut store, &n.to_le_bytes().repeat(10))
+                    .unwrap(),
+            );
+        }
+        heap.delete(&mut store, rids[0]).unwrap();
+        let expected = all(&heap, &mut store);
+
+        let mut reopened = Heap::open(&mut store, (0, 1), (2, 3)).unwrap();
+        assert_eq!(
+            heap.pages().collect::<Vec<_>>(),
+            reopened.pages().collect::<Vec<_>>()
+        );
+        assert_eq!(expected, all(&reopened, &mut store));
+        assert_eq!(
+            1u32.to_le_bytes().repeat(10),
+            reopened.get(&mut store, rids[1]).unwrap()
+        );
+        // The room left by the deleted record is found again.
+        assert_eq!(rids[0], reopened.insert(&mut store, b"again").unwrap());
+    }
+
+    #[test]
+    fn open_given_corrupt_directory() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        heap.insert(&mut store, b"record").unwrap();
+        let mut buf = page::buffer(&store);
+        page::read(&mut store, heap.root.head, &mut buf).unwrap();
+        buf[ENTRIES] ^= 0x01;
+        page::write(&mut store, heap.root.head, &buf).unwrap();
+        match Heap::open(&mut store, (0, 1), (2, 3)) {
+            Ok(_) => panic!("allowed opening heap with corrupt directory"),
+            Err(error) => assert!(
+                matches!(error, Error::ChecksumMismatch { page, .. } if page == heap.root.head),
+                "{error}"
+            ),
+        }
+    }
+
+    #[test]
+    fn open_after_crash_while_adding_directory_page() {
+        // A heap whose first directory page is full, and the records in it.
+        let full = || {
+            let mut store = Faulty::new(Memory::with_page_size(4096));
+            let mut heap = setup(&mut store);
+            while heap.pages().count() < entries(4096) {
+                heap.insert(&mut store, &[1u8; 3000]).unwrap();
+            }
+            let records = all(&heap, &mut store);
+            (store, heap, records)
+        };
+        let (mut store, mut heap, _) = full();
+        let writes = store.writes();
+        heap.insert(&mut store, &[2u8; 3000]).unwrap();
+        let directory = heap.root.tail;
+
+        // The store stops at each write of the insert in turn, before the
+        // write or partway through it. A torn write of a page the heap
+        // already links to cannot be recovered from, so only the new
+        // directory page is torn.
+        for n in 0..store.writes() - writes {
+            for torn in [false, true] {
+                let (mut store, mut heap, records) = full();
+                store.inject(if torn {
+                    Fault::TearWrite(n, 64)
+                } else {
+                    Fault::FailWrite(n)
+                });
+                assert!(heap.insert(&mut store, &[2u8; 3000]).is_err());
+                store.heal();
+                if torn && store.unsynced().last() != Some(&directory) {
+                    continue;
+                }
+
+                let mut reopened = Heap::open(&mut store, (0, 1), (2, 3)).unwrap();
+                let found = all(&reopened, &mut store);
+                assert!(
+                    records
+                        .iter()
+                        .all(|(rid, record)| found.get(rid) == Some(record))
+                );
+                let rid = reopened.insert(&mut store, &[3u8; 3000]).unwrap();
+                assert_eq!(vec![3u8; 3000], reopened.get(&mut store, rid).unwrap());
+                let again = Heap::open(&mut store, (0, 1), (2, 3)).unwrap();
+                assert_eq!(
+                    reopened.pages().collect::<Vec<_>>(),
+                    again.pages().collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn insert_when_free_space_map_is_stale() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let rid = heap.insert(&mut store, &[1u8; 8000]).unwrap();
+        // As if the page had been written but not the map before a crash.
+        heap.fsm.update(&mut store, 0, page::DEFAULT_SIZE).unwrap();
+
+        assert_ne!(
+            rid.page,
+            heap.insert(&mut store, &[2u8; 1000]).unwrap().page
+        );
+        assert_eq!(Some(1), heap.fsm.find(&mut store, 1000, None).unwrap());
+    }
+
+    #[test]
+    fn update_moves_record_past_its_own_page() {
+        let mut store = Memory::new();
+        let mut heap = setup(&mut store);
+        let rid = heap.insert(&mut store, &[1u8; 3000]).unwrap();
+        heap.insert(&mut store, &[1u8; 4500]).unwrap();
+        let other = heap.insert(&mut store, &[1u8; 3000]).unwrap().page;
+        assert_ne!(rid.page, other);
+        // As if the page had been written but not the map before a crash,
+        // which puts the page of the record first in line.
+        heap.fsm.update(&mut store, 0, page::DEFAULT_SIZE).unwrap();
+
+        heap.update(&mut store, rid, &[2u8; 4000]).unwrap();
+        assert_eq!(vec![2u8; 4000], heap.get(&mut store, rid).unwrap());
+        let buf = load(&mut store, rid.page).unwrap();
+        assert_eq!(
+            other,
+            target(decode(slot::read(&buf, rid.slot).unwrap()).1).page
+        );
+        assert_eq!(2, heap.pages().count());
+    }
+}
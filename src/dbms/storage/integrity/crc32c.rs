@@ -0,0 +1,126 @@
+use super::Checksum;
+
+// The Castagnoli polynomial, reflected.
+const POLY: u32 = 0x82F6_3B78;
+
+// Slicing-by-8 tables, where table k maps a byte to its contribution to the
+// CRC when followed by k more bytes.
+static TABLES: [[u32; 256]; 8] = tables();
+
+const fn tables() -> [[u32; 256]; 8] {
+    let mut tables = [[0u32; 256]; 8];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        tables[0][n] = crc;
+        n += 1;
+    }
+    let mut k = 1;
+    while k < 8 {
+        let mut n = 0;
+        while n < 256 {
+            let previous = tables[k - 1][n];
+            tables[k][n] = (previous >> 8) ^ tables[0][(previous & 0xFF) as usize];
+            n += 1;
+        }
+        k += 1;
+    }
+    tables
+}
+
+fn software(mut crc: u32, n: &[u8]) -> u32 {
+    let mut chunks = n.chunks_exact(8);
+    for chunk in &mut chunks {
+        let v = u64::from_le_bytes(chunk.try_into().unwrap()) ^ crc as u64;
+        crc = TABLES[7][(v & 0xFF) as usize]
+            ^ TABLES[6][(v >> 8 & 0xFF) as usize]
+            ^ TABLES[5][(v >> 16 & 0xFF) as usize]
+            ^ TABLES[4][(v >> 24 & 0xFF) as usize]
+            ^ TABLES[3][(v >> 32 & 0xFF) as usize]
+            ^ TABLES[2][(v >> 40 & 0xFF) as usize]
+            ^ TABLES[1][(v >> 48 & 0xFF) as usize]
+            ^ TABLES[0][(v >> 56) as usize];
+    }
+    for byte in chunks.remainder() {
+        crc = TABLES[0][((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+fn hardware(crc: u32, n: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u8, _mm_crc32_u64};
+
+    let mut crc = crc as u64;
+    let mut chunks = n.chunks_exact(8);
+    for chunk in &mut chunks {
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let mut crc = crc as u32;
+    for byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, *byte);
+    }
+    crc
+}
+
+pub struct Crc32c;
+
+impl Crc32c {
+    fn update(crc: u32, n: &[u8]) -> u32 {
+        #[cfg(target_arch = "x86_64")]
+        if std::is_x86_feature_detected!("sse4.2") {
+            // SAFETY: the CPU has just been checked for SSE 4.2.
+            return unsafe { hardware(crc, n) };
+        }
+        software(crc, n)
+    }
+}
+
+impl Checksum for Crc32c {
+    fn checksum(&self, n: &[u8]) -> u64 {
+        !Self::update(!0, n) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(0xE306_9283, Crc32c.checksum(b"123456789"));
+        assert_eq!(0, Crc32c.checksum(b""));
+    }
+
+    #[test]
+    fn software_matches_hardware() {
+        let n: Vec<u8> = (0..1000).map(|n| (n * 7 % 256) as u8).collect();
+        for len in [0, 1, 7, 8, 9, 63, 1000] {
+            assert_eq!(
+                Crc32c::update(!0, &n[..len]),
+                software(!0, &n[..len]),
+                "mismatch for length {len}"
+            );
+        }
+    }
+
+    #[test]
+    fn known_vectors() {
+        assert_eq!(0x8A91_36AA, Crc32c.checksum(&[0u8; 32]));
+        assert_eq!(0x62A8_AB43, Crc32c.checksum(&[0xFFu8; 32]));
+        let n: Vec<u8> = (0..8192u64)
+            .map(|n| ((n * 0x9E37_79B1) >> 13) as u8)
+            .collect();
+        assert_eq!(0x24B9_EBB2, Crc32c.checksum(&n));
+    }
+}
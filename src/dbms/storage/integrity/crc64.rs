@@ -0,0 +1,81 @@
+use super::Checksum;
+
+// The ECMA-182 polynomial, reflected, as used by CRC-64/XZ.
+const POLY: u64 = 0xC96C_5795_D787_0F42;
+
+// Slicing-by-8 tables, like the ones for CRC-32C.
+static TABLES: [[u64; 256]; 8] = tables();
+
+const fn tables() -> [[u64; 256]; 8] {
+    let mut tables = [[0u64; 256]; 8];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        tables[0][n] = crc;
+        n += 1;
+    }
+    let mut k = 1;
+    while k < 8 {
+        let mut n = 0;
+        while n < 256 {
+            let previous = tables[k - 1][n];
+            tables[k][n] = (previous >> 8) ^ tables[0][(previous & 0xFF) as usize];
+            n += 1;
+        }
+        k += 1;
+    }
+    tables
+}
+
+pub struct Crc64;
+
+impl Checksum for Crc64 {
+    fn checksum(&self, n: &[u8]) -> u64 {
+        let mut crc = !0u64;
+        let mut chunks = n.chunks_exact(8);
+        for chunk in &mut chunks {
+            let v = u64::from_le_bytes(chunk.try_into().unwrap()) ^ crc;
+            crc = TABLES[7][(v & 0xFF) as usize]
+                ^ TABLES[6][(v >> 8 & 0xFF) as usize]
+                ^ TABLES[5][(v >> 16 & 0xFF) as usize]
+                ^ TABLES[4][(v >> 24 & 0xFF) as usize]
+                ^ TABLES[3][(v >> 32 & 0xFF) as usize]
+                ^ TABLES[2][(v >> 40 & 0xFF) as usize]
+                ^ TABLES[1][(v >> 48 & 0xFF) as usize]
+                ^ TABLES[0][(v >> 56) as usize];
+        }
+        for byte in chunks.remainder() {
+            crc = TABLES[0][((crc ^ *byte as u64) & 0xFF) as usize] ^ (crc >> 8);
+        }
+        !crc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(0x995D_C9BB_DF19_39FA, Crc64.checksum(b"123456789"));
+        assert_eq!(0, Crc64.checksum(b""));
+    }
+
+    #[test]
+    fn known_vectors() {
+        assert_eq!(0xC95A_F861_7CD5_330C, Crc64.checksum(&[0u8; 32]));
+        let n: Vec<u8> = (0..8192u64)
+            .map(|n| ((n * 0x9E37_79B1) >> 13) as u8)
+            .collect();
+        assert_eq!(0x6A45_AE3C_8DFE_8BD1, Crc64.checksum(&n));
+    }
+}
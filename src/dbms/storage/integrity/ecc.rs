@@ -0,0 +1,301 @@
+// Reed-Solomon codes over GF(2^8), used to repair pages in place.
+//
+// A buffer is split into codewords of 128 bytes, 16 of which are parity, so
+// every codeword can correct up to 8 corrupt bytes anywhere in it. The
+// codewords are interleaved byte by byte, which spreads a burst of corruption
+// over all of them. A buffer of N codewords can therefore repair any run of
+// up to 8 * N corrupt bytes, which for an 8 KB page is a whole 512 byte
+// sector. The parity of every codeword goes at the end of the buffer.
+
+const CODEWORD: usize = 128;
+const PARITY: usize = 16;
+const DATA: usize = CODEWORD - PARITY;
+
+// The primitive polynomial x^8 + x^4 + x^3 + x^2 + 1, with 2 as generator.
+const POLY: u16 = 0x11D;
+
+struct Field {
+    // Twice as long as needed, so that products of logarithms need no
+    // reduction modulo 255.
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+static FIELD: Field = field();
+
+const fn field() -> Field {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut n = 0;
+    while n < 255 {
+        exp[n] = x as u8;
+        log[x as usize] = n as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= POLY;
+        }
+        n += 1;
+    }
+    while n < 512 {
+        exp[n] = exp[n - 255];
+        n += 1;
+    }
+    Field { exp, log }
+}
+
+const fn product(field: &Field, a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    field.exp[field.log[a as usize] as usize + field.log[b as usize] as usize]
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    product(&FIELD, a, b)
+}
+
+fn div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    FIELD.exp[FIELD.log[a as usize] as usize + 255 - FIELD.log[b as usize] as usize]
+}
+
+fn pow(n: usize) -> u8 {
+    FIELD.exp[n % 255]
+}
+
+// Evaluates a polynomial with its lowest degree coefficient first.
+fn eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, c| mul(acc, x) ^ c)
+}
+
+// The generator polynomial (x - 1)(x - 2)...(x - 2^15), highest degree
+// coefficient first, which is the order the encoder consumes it in.
+static GENERATOR: [u8; PARITY + 1] = generator();
+
+const fn generator() -> [u8; PARITY + 1] {
+    let field = field();
+    let mut g = [0u8; PARITY + 1];
+    g[0] = 1;
+    let mut root = 0;
+    while root < PARITY {
+        // Multiplies by (x - 2^root), where the degree grows by one.
+        let mut i = root + 1;
+        while i > 0 {
+            g[i] ^= product(&field, g[i - 1], field.exp[root]);
+            i -= 1;
+        }
+        root += 1;
+    }
+    g
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Uncorrectable;
+
+// The number of bytes at the end of a buffer of the given length that hold
+// parity rather than data.
+pub fn parity(len: usize) -> usize {
+    len / CODEWORD * PARITY
+}
+
+fn stripes(buf: &[u8]) -> usize {
+    assert!(
+        buf.len().is_multiple_of(CODEWORD),
+        "buffer length is not a multiple of {CODEWORD}"
+    );
+    buf.len() / CODEWORD
+}
+
+// Computes the parity over everything before it and stores it at the end of
+// the buffer.
+pub fn encode(buf: &mut [u8]) {
+    let stripes = stripes(buf);
+    for stripe in 0..stripes {
+        let mut parity = [0u8; PARITY];
+        for symbol in 0..DATA {
+            let feedback = buf[symbol * stripes + stripe] ^ parity[0];
+            parity.copy_within(1.., 0);
+            parity[PARITY - 1] = 0;
+            if feedback != 0 {
+                for (p, g) in parity.iter_mut().zip(&GENERATOR[1..]) {
+                    *p ^= mul(*g, feedback);
+                }
+            }
+        }
+        for (n, p) in parity.into_iter().enumerate() {
+            buf[(DATA + n) * stripes + stripe] = p;
+        }
+    }
+}
+
+// Corrects the buffer in place and returns the number of bytes that had to be
+// corrected. Fails without touching the buffer if some codeword holds more
+// corrupt bytes than its parity can correct.
+pub fn decode(buf: &mut [u8]) -> Result<usize, Uncorrectable> {
+    let stripes = stripes(buf);
+    let mut corrections = Vec::new();
+    for stripe in 0..stripes {
+        let mut codeword = [0u8; CODEWORD];
+        for (symbol, c) in codeword.iter_mut().enumerate() {
+            *c = buf[symbol * stripes + stripe];
+        }
+        for (symbol, e) in correct(&codeword)? {
+            corrections.push((symbol * stripes + stripe, e));
+        }
+    }
+    for (at, e) in &corrections {
+        buf[*at] ^= e;
+    }
+    Ok(corrections.len())
+}
+
+// Finds the errors in a codeword with Berlekamp-Massey, Chien search and
+// Forney's algorithm. Returns the position and magnitude of every error.
+fn correct(codeword: &[u8; CODEWORD]) -> Result<Vec<(usize, u8)>, Uncorrectable> {
+    // The codeword is a polynomial with its first byte as the highest degree
+    // coefficient, and is divisible by the generator unless it is corrupt.
+    let mut syndromes = [0u8; PARITY];
+    for (n, s) in syndromes.iter_mut().enumerate() {
+        *s = codeword.iter().fold(0, |acc, c| mul(acc, pow(n)) ^ c);
+    }
+    if syndromes.iter().all(|s| *s == 0) {
+        return Ok(Vec::new());
+    }
+
+    // The error locator, lowest degree first, has the inverse positions of
+    // the errors as its roots.
+    let mut locator = vec![1u8];
+    let mut previous = vec![1u8];
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut scale = 1u8;
+    for n in 0..PARITY {
+        let mut discrepancy = syndromes[n];
+        for i in 1..=errors.min(locator.len() - 1) {
+            discrepancy ^= mul(locator[i], syndromes[n - i]);
+        }
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let factor = div(discrepancy, scale);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, b) in previous.iter().enumerate() {
+            next[i + shift] ^= mul(factor, *b);
+        }
+        if 2 * errors <= n {
+            previous = std::mem::replace(&mut locator, next);
+            errors = n + 1 - errors;
+            scale = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+    locator.truncate(errors + 1);
+    if errors > PARITY / 2 {
+        return Err(Uncorrectable);
+    }
+
+    // The error evaluator, lowest degree first.
+    let mut evaluator = [0u8; PARITY];
+    for (i, s) in syndromes.iter().enumerate() {
+        for (j, l) in locator.iter().enumerate() {
+            if i + j < PARITY {
+                evaluator[i + j] ^= mul(*s, *l);
+            }
+        }
+    }
+    // In characteristic 2 only the odd terms survive differentiation.
+    let derivative: Vec<u8> = (0..locator.len())
+        .map(|i| if i % 2 == 1 { locator[i] } else { 0 })
+        .skip(1)
+        .collect();
+
+    let mut found = Vec::new();
+    for position in 0..CODEWORD {
+        let degree = CODEWORD - 1 - position;
+        let x = pow(degree);
+        let inverse = pow(255 - degree % 255);
+        if eval(&locator, inverse) != 0 {
+            continue;
+        }
+        let denominator = eval(&derivative, inverse);
+        if denominator == 0 {
+            return Err(Uncorrectable);
+        }
+        found.push((
+            position,
+            mul(x, div(eval(&evaluator, inverse), denominator)),
+        ));
+    }
+    if found.len() != errors {
+        return Err(Uncorrectable);
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(len: usize) -> Vec<u8> {
+        let mut buf: Vec<u8> = (0..len).map(|n| (n * 31 % 251) as u8).collect();
+        encode(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn parity_takes_an_eighth() {
+        assert_eq!(1024, parity(8192));
+        assert_eq!(512, parity(4096));
+    }
+
+    #[test]
+    fn decode_when_intact() {
+        let mut buf = encoded(8192);
+        let original = buf.clone();
+        assert_eq!(Ok(0), decode(&mut buf));
+        assert_eq!(original, buf);
+    }
+
+    #[test]
+    fn decode_repairs_scattered_errors() {
+        let original = encoded(8192);
+        let mut buf = original.clone();
+        // Eight errors in the first codeword, including its parity.
+        for symbol in [0, 5, 17, 60, 99, 111, 112, 127] {
+            buf[symbol * 64] ^= 0x5A;
+        }
+        buf[3] = !buf[3];
+        assert_eq!(Ok(9), decode(&mut buf));
+        assert_eq!(original, buf);
+    }
+
+    #[test]
+    fn decode_repairs_sector() {
+        let original = encoded(8192);
+        for start in [0, 512, 1000, 7680] {
+            let mut buf = original.clone();
+            buf[start..start + 512].fill(0xFF);
+            assert!(decode(&mut buf).is_ok(), "sector at {start} not repaired");
+            assert_eq!(original, buf);
+        }
+    }
+
+    #[test]
+    fn decode_when_uncorrectable() {
+        let mut buf = encoded(8192);
+        for symbol in 0..9 {
+            buf[symbol * 64] ^= 0x01 + symbol as u8;
+        }
+        let corrupt = buf.clone();
+        assert!(decode(&mut buf).is_err());
+        assert_eq!(corrupt, buf);
+    }
+}
@@ -0,0 +1,222 @@
+use super::Checksum;
+
+// The 64 bit variant of XXH3 with the default secret and a seed of zero.
+
+const PRIME32_1: u64 = 0x9E37_79B1;
+const PRIME32_2: u64 = 0x85EB_CA77;
+const PRIME32_3: u64 = 0xC2B2_AE3D;
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+const PRIME_MX1: u64 = 0x1656_6791_9E37_79F9;
+const PRIME_MX2: u64 = 0x9FB2_1C65_1E98_DF25;
+
+const SECRET: [u8; 192] = [
+    0xb8, 0xfe, 0x6c, 0x39, 0x23, 0xa4, 0x4b, 0xbe, 0x7c, 0x01, 0x81, 0x2c, 0xf7, 0x21, 0xad, 0x1c,
+    0xde, 0xd4, 0x6d, 0xe9, 0x83, 0x90, 0x97, 0xdb, 0x72, 0x40, 0xa4, 0xa4, 0xb7, 0xb3, 0x67, 0x1f,
+    0xcb, 0x79, 0xe6, 0x4e, 0xcc, 0xc0, 0xe5, 0x78, 0x82, 0x5a, 0xd0, 0x7d, 0xcc, 0xff, 0x72, 0x21,
+    0xb8, 0x08, 0x46, 0x74, 0xf7, 0x43, 0x24, 0x8e, 0xe0, 0x35, 0x90, 0xe6, 0x81, 0x3a, 0x26, 0x4c,
+    0x3c, 0x28, 0x52, 0xbb, 0x91, 0xc3, 0x00, 0xcb, 0x88, 0xd0, 0x65, 0x8b, 0x1b, 0x53, 0x2e, 0xa3,
+    0x71, 0x64, 0x48, 0x97, 0xa2, 0x0d, 0xf9, 0x4e, 0x38, 0x19, 0xef, 0x46, 0xa9, 0xde, 0xac, 0xd8,
+    0xa8, 0xfa, 0x76, 0x3f, 0xe3, 0x9c, 0x34, 0x3f, 0xf9, 0xdc, 0xbb, 0xc7, 0xc7, 0x0b, 0x4f, 0x1d,
+    0x8a, 0x51, 0xe0, 0x4b, 0xcd, 0xb4, 0x59, 0x31, 0xc8, 0x9f, 0x7e, 0xc9, 0xd9, 0x78, 0x73, 0x64,
+    0xea, 0xc5, 0xac, 0x83, 0x34, 0xd3, 0xeb, 0xc3, 0xc5, 0x81, 0xa0, 0xff, 0xfa, 0x13, 0x63, 0xeb,
+    0x17, 0x0d, 0xdd, 0x51, 0xb7, 0xf0, 0xda, 0x49, 0xd3, 0x16, 0x55, 0x26, 0x29, 0xd4, 0x68, 0x9e,
+    0x2b, 0x16, 0xbe, 0x58, 0x7d, 0x47, 0xa1, 0xfc, 0x8f, 0xf8, 0xb8, 0xd1, 0x7a, 0xd0, 0x31, 0xce,
+    0x45, 0xcb, 0x3a, 0x8f, 0x95, 0x16, 0x04, 0x28, 0xaf, 0xd7, 0xfb, 0xca, 0xbb, 0x4b, 0x40, 0x7e,
+];
+
+const STRIPE: usize = 64;
+const STRIPES_PER_BLOCK: usize = (SECRET.len() - STRIPE) / 8;
+const BLOCK: usize = STRIPE * STRIPES_PER_BLOCK;
+
+fn read32(n: &[u8], at: usize) -> u64 {
+    u32::from_le_bytes(n[at..at + 4].try_into().unwrap()) as u64
+}
+
+fn read64(n: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(n[at..at + 8].try_into().unwrap())
+}
+
+fn fold(a: u64, b: u64) -> u64 {
+    let product = a as u128 * b as u128;
+    product as u64 ^ (product >> 64) as u64
+}
+
+fn avalanche(mut h: u64) -> u64 {
+    h ^= h >> 37;
+    h = h.wrapping_mul(PRIME_MX1);
+    h ^ h >> 32
+}
+
+fn avalanche64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(PRIME64_2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(PRIME64_3);
+    h ^ h >> 32
+}
+
+fn mix16(n: &[u8], at: usize, secret: usize) -> u64 {
+    fold(
+        read64(n, at) ^ read64(&SECRET, secret),
+        read64(n, at + 8) ^ read64(&SECRET, secret + 8),
+    )
+}
+
+fn short(n: &[u8]) -> u64 {
+    let len = n.len() as u64;
+    match n.len() {
+        0 => avalanche64(read64(&SECRET, 56) ^ read64(&SECRET, 64)),
+        1..=3 => {
+            let combined = (n[0] as u64) << 16
+                | (n[n.len() >> 1] as u64) << 24
+                | n[n.len() - 1] as u64
+                | len << 8;
+            avalanche64(combined ^ (read32(&SECRET, 0) ^ read32(&SECRET, 4)))
+        }
+        4..=8 => {
+            let flip = read64(&SECRET, 8) ^ read64(&SECRET, 16);
+            let mut h = (read32(n, n.len() - 4) + (read32(n, 0) << 32)) ^ flip;
+            h ^= h.rotate_left(49) ^ h.rotate_left(24);
+            h = h.wrapping_mul(PRIME_MX2);
+            h ^= (h >> 35).wrapping_add(len);
+            h = h.wrapping_mul(PRIME_MX2);
+            h ^ h >> 28
+        }
+        _ => {
+            let low = read64(n, 0) ^ read64(&SECRET, 24) ^ read64(&SECRET, 32);
+            let high = read64(n, n.len() - 8) ^ read64(&SECRET, 40) ^ read64(&SECRET, 48);
+            avalanche(
+                len.wrapping_add(low.swap_bytes())
+                    .wrapping_add(high)
+                    .wrapping_add(fold(low, high)),
+            )
+        }
+    }
+}
+
+fn medium(n: &[u8]) -> u64 {
+    let len = n.len();
+    let mut acc = (len as u64).wrapping_mul(PRIME64_1);
+    if len <= 128 {
+        // Pairs of 16 byte blocks from both ends, working inwards.
+        let pairs = (len - 1) / 32 + 1;
+        for pair in (0..pairs).rev() {
+            acc = acc.wrapping_add(mix16(n, 16 * pair, 32 * pair));
+            acc = acc.wrapping_add(mix16(n, len - 16 * (pair + 1), 32 * pair + 16));
+        }
+        return avalanche(acc);
+    }
+    for round in 0..8 {
+        acc = acc.wrapping_add(mix16(n, 16 * round, 16 * round));
+    }
+    acc = avalanche(acc);
+    for round in 8..len / 16 {
+        acc = acc.wrapping_add(mix16(n, 16 * round, 16 * (round - 8) + 3));
+    }
+    acc = acc.wrapping_add(mix16(n, len - 16, 136 - 17));
+    avalanche(acc)
+}
+
+fn accumulate(acc: &mut [u64; 8], stripe: &[u8], secret: usize) {
+    for i in 0..8 {
+        let value = read64(stripe, 8 * i);
+        let key = value ^ read64(&SECRET, secret + 8 * i);
+        acc[i ^ 1] = acc[i ^ 1].wrapping_add(value);
+        acc[i] = acc[i].wrapping_add((key & 0xFFFF_FFFF).wrapping_mul(key >> 32));
+    }
+}
+
+fn scramble(acc: &mut [u64; 8]) {
+    for (i, lane) in acc.iter_mut().enumerate() {
+        let key = read64(&SECRET, SECRET.len() - STRIPE + 8 * i);
+        *lane = (*lane ^ *lane >> 47 ^ key).wrapping_mul(PRIME32_1);
+    }
+}
+
+fn long(n: &[u8]) -> u64 {
+    let mut acc = [
+        PRIME32_3, PRIME64_1, PRIME64_2, PRIME64_3, PRIME64_4, PRIME32_2, PRIME64_5, PRIME32_1,
+    ];
+    let blocks = (n.len() - 1) / BLOCK;
+    for block in 0..blocks {
+        for stripe in 0..STRIPES_PER_BLOCK {
+            accumulate(&mut acc, &n[block * BLOCK + stripe * STRIPE..], stripe * 8);
+        }
+        scramble(&mut acc);
+    }
+    let stripes = (n.len() - 1 - blocks * BLOCK) / STRIPE;
+    for stripe in 0..stripes {
+        accumulate(&mut acc, &n[blocks * BLOCK + stripe * STRIPE..], stripe * 8);
+    }
+    // The last stripe always ends at the end of the input, overlapping the
+    // previous one if need be.
+    accumulate(&mut acc, &n[n.len() - STRIPE..], SECRET.len() - STRIPE - 7);
+
+    let mut result = (n.len() as u64).wrapping_mul(PRIME64_1);
+    for i in 0..4 {
+        result = result.wrapping_add(fold(
+            acc[2 * i] ^ read64(&SECRET, 11 + 16 * i),
+            acc[2 * i + 1] ^ read64(&SECRET, 11 + 16 * i + 8),
+        ));
+    }
+    avalanche(result)
+}
+
+pub struct Xxh3;
+
+impl Checksum for Xxh3 {
+    fn checksum(&self, n: &[u8]) -> u64 {
+        match n.len() {
+            0..=16 => short(n),
+            17..=240 => medium(n),
+            _ => long(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|n| ((n as u64 * 0x9E37_79B1) >> 13) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn known_vectors() {
+        // Lengths on both sides of every boundary between code paths.
+        let expected = [
+            (0, 0x2D06_8005_38D3_94C2),
+            (1, 0xC44B_DFF4_074E_ECDB),
+            (3, 0xA1C4_A825_9B82_7291),
+            (4, 0xBB4E_3D89_EE0B_271D),
+            (8, 0x79D0_2238_B80E_37B1),
+            (9, 0xF64C_ECC4_271F_F461),
+            (16, 0x222E_9AEA_D6BD_DD51),
+            (17, 0x47AA_D6B3_75EB_4BBA),
+            (33, 0x537E_7ED2_6D82_5E92),
+            (65, 0x4B4C_E705_0EEB_9559),
+            (97, 0x0DD8_8DB1_BBAF_7326),
+            (128, 0x421A_9C90_5C6E_66BA),
+            (129, 0x9E24_1480_0F83_768A),
+            (240, 0xB714_C5FD_2274_4964),
+            (241, 0xBC42_4A2C_480D_D281),
+            (1024, 0x1FD1_5E7D_36F5_E1BC),
+            (1025, 0xFE08_E5A8_74D2_3FD2),
+            (8192, 0x4583_0B92_71BF_697A),
+        ];
+        for (len, hash) in expected {
+            assert_eq!(
+                hash,
+                Xxh3.checksum(&input(len)),
+                "mismatch for length {len}"
+            );
+        }
+    }
+}
@@ -1,43 +1,151 @@
-use std::{fs::File, io};
+use std::{fs::File, io, os::unix::fs::FileExt};
 
-use crate::dbms::storage::{integrity, page};
+use crate::dbms::storage::{Error, PageStore, Result, integrity::Algorithm, page};
 
-pub const SIZE: usize = page::SIZE - 1;
+pub mod record;
 
-const CRC_POLY: u8 = 0xB0;
+pub use record::Record;
 
-pub fn write(file: &mut File, pair: (u64, u64), buf: &[u8; SIZE]) -> io::Result<()> {
-    page::copy(file, pair.0, pair.1)?;
-    // Ensure that the backup has reached the storage medium before continuing.
-    file.sync_all()?;
+// Both pages of a pair start with the page size of the store, so that a file
+// can be opened without knowing it, followed by a sequence number, and end
+// with a CRC-32C of everything before it. A CRC of a single byte would let one
+// in 256 torn pages pass as intact. The value is kept in between.
+//
+// Writes alternate between the two pages, each with a sequence number one
+// higher than the last, and reads take the intact page with the highest one.
+// A write that is torn by a crash leaves the other page, which holds the
+// previous version, as the newest intact one.
+const PAGE_SIZE: usize = 0;
+const SEQUENCE: usize = 4;
+const VALUE: usize = 12;
+const CRC: usize = 4;
 
-    let mut page = [0u8; page::SIZE];
-    page[0..SIZE].copy_from_slice(buf);
-    page[SIZE] = integrity::crc(CRC_POLY, buf);
-    page::write(file, pair.0, &page)
+// The largest value that a pair holds in a store with the given page size.
+pub const fn capacity(page_size: usize) -> usize {
+    page_size - VALUE - CRC
 }
 
-pub fn read(file: &mut File, pair: (u64, u64), buf: &mut [u8; SIZE]) -> io::Result<()> {
-    let mut page = [0u8; page::SIZE];
-    page::read(file, pair.0, &mut page)?;
-    if page[SIZE] != integrity::crc(CRC_POLY, &page[0..SIZE]) {
-        // The calculated CRC is different from the stored CRC. It does not
-        // matter what has gone wrong at this point, just that the backup data
-        // should take the place of the main data.
-        page::read(file, pair.1, &mut page)?;
-        page[SIZE] = integrity::crc(CRC_POLY, &page[0..SIZE]);
-        page::write(file, pair.0, &page)?;
-    }
-    buf.copy_from_slice(&page[0..SIZE]);
+fn checksum(data: &[u8]) -> u32 {
+    Algorithm::Crc32c.get().checksum(data) as u32
+}
+
+fn stored(page: &[u8]) -> u32 {
+    u32::from_le_bytes(page[page.len() - CRC..].try_into().unwrap())
+}
+
+fn recorded(page: &[u8]) -> usize {
+    u32::from_le_bytes(page[PAGE_SIZE..PAGE_SIZE + 4].try_into().unwrap()) as usize
+}
+
+fn sequence(page: &[u8]) -> u64 {
+    u64::from_le_bytes(page[SEQUENCE..SEQUENCE + 8].try_into().unwrap())
+}
+
+fn seal(page: &mut [u8], sequence: u64) {
+    let len = page.len();
+    page[PAGE_SIZE..PAGE_SIZE + 4].copy_from_slice(&(len as u32).to_le_bytes());
+    page[SEQUENCE..SEQUENCE + 8].copy_from_slice(&sequence.to_le_bytes());
+    let crc = checksum(&page[..len - CRC]);
+    page[len - CRC..].copy_from_slice(&crc.to_le_bytes());
+}
+
+// Finds the page of the pair that holds its newest version. Pages with the
+// same sequence number hold the same version, in which case the first page
+// is taken.
+fn newest(store: &mut dyn PageStore, pair: (u64, u64)) -> Result<(u64, Vec<u8>)> {
+    let mut first = page::buffer(store);
+    page::read(store, pair.0, &mut first)?;
+    let mut second = page::buffer(store);
+    page::read(store, pair.1, &mut second)?;
+    match (is_intact(&first), is_intact(&second)) {
+        (true, true) if sequence(&second) > sequence(&first) => Ok((pair.1, second)),
+        (true, _) => Ok((pair.0, first)),
+        (false, true) => Ok((pair.1, second)),
+        (false, false) => Err(Error::ChecksumMismatch {
+            page: pair.1,
+            expected: stored(&second) as u64,
+            found: checksum(&second[..second.len() - CRC]) as u64,
+        }),
+    }
+}
+
+// Refuses values that do not fit in a page of the pair.
+fn check_value(store: &dyn PageStore, buf: &[u8]) -> Result<()> {
+    let max = capacity(store.page_size());
+    if buf.len() > max {
+        return Err(Error::RecordTooLarge {
+            size: buf.len(),
+            max,
+        });
+    }
+    Ok(())
+}
+
+// Values shorter than the capacity are padded with zeroes, and read back
+// by passing a buffer of the same length. The value is durable once the
+// write returns.
+pub fn write(store: &mut dyn PageStore, pair: (u64, u64), buf: &[u8]) -> Result<()> {
+    check_value(store, buf)?;
+    // The page that does not hold the newest version is overwritten. A pair
+    // without an intact page is refused, since it was either never set up or
+    // has lost its value, which starting it over would hide.
+    let (page, newest) = newest(store, pair)?;
+    let target = if page == pair.0 { pair.1 } else { pair.0 };
+    let next = sequence(&newest) + 1;
+    let mut page = page::buffer(store);
+    page[VALUE..VALUE + buf.len()].copy_from_slice(buf);
+    seal(&mut page, next);
+    page::write(store, target, &page)?;
+    // The next write overwrites the page that holds this version now, which
+    // must have reached the storage medium by then.
+    Ok(store.sync()?)
+}
+
+pub fn read(store: &mut dyn PageStore, pair: (u64, u64), buf: &mut [u8]) -> Result<()> {
+    check_value(store, buf)?;
+    let (page, newest) = newest(store, pair)?;
+    if recorded(&newest) != newest.len() {
+        return Err(Error::PageSizeMismatch {
+            page,
+            expected: newest.len(),
+            found: recorded(&newest),
+        });
+    }
+    buf.copy_from_slice(&newest[VALUE..VALUE + buf.len()]);
     Ok(())
 }
 
-pub fn init(file: &mut File, pair: (u64, u64)) -> io::Result<()> {
-    let mut page = [0u8; page::SIZE];
-    page[SIZE] = integrity::crc(CRC_POLY, &page[0..SIZE]);
-    page::write(file, pair.1, &page)?;
-    file.sync_all()?;
-    page::write(file, pair.0, &page)
+// Whether a page of a meta pair, either one, matches its CRC.
+pub fn is_intact(page: &[u8]) -> bool {
+    stored(page) == checksum(&page[..page.len() - CRC])
+}
+
+pub fn init(store: &mut dyn PageStore, pair: (u64, u64)) -> Result<()> {
+    let mut page = page::buffer(store);
+    seal(&mut page, 0);
+    page::write(store, pair.1, &page)?;
+    page::write(store, pair.0, &page)?;
+    Ok(store.sync()?)
+}
+
+// Finds the page size of a file from the meta pair at its start, by trying
+// every supported size until a page of the pair is intact at it and records
+// it. Both pages are tried, in case one of them was torn.
+pub fn probe(file: &File) -> Result<usize> {
+    let mut size = page::MIN_SIZE;
+    while size <= page::MAX_SIZE {
+        for page in [0, 1] {
+            let mut buf = vec![0u8; size];
+            match file.read_exact_at(&mut buf, page * size as u64) {
+                Ok(()) if is_intact(&buf) && recorded(&buf) == size => return Ok(size),
+                Ok(()) => {}
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+        size *= 2;
+    }
+    Err(Error::UnknownPageSize)
 }
 
 #[cfg(test)]
@@ -45,78 +153,125 @@ mod tests {
     use core::panic;
 
     use super::*;
-    use crate::dbms::storage::ephemeral;
+    use crate::dbms::storage::{
+        Error, ephemeral,
+        store::{Crash, Disk, Fault, Faulty, Memory, Op},
+    };
+
+    const SIZE: usize = capacity(page::DEFAULT_SIZE);
 
     #[test]
-    fn write_when_backup_fails() {
+    fn write_when_other_page_is_out_of_range() {
         ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
-            // Making the backup page a distant page forces an error.
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            // Making the other page a distant page forces an error.
             match write(tmp.borrow_mut(), (0, 2), &[0u8; SIZE]) {
-                Ok(_) => panic!("allowed backup page failure"),
-                Err(error) => assert_eq!("tried to write distant page", error.to_string()),
+                Ok(_) => panic!("allowed out of range meta page"),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 2, end: 1 }), "{error}"),
             }
-            let mut buf = [0u8; page::SIZE];
+            let mut buf = [0u8; page::DEFAULT_SIZE];
             page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
-            assert_eq!([1u8; page::SIZE], buf);
+            assert_eq!([1u8; page::DEFAULT_SIZE], buf);
         });
     }
 
     #[test]
-    fn write_when_main_fails() {
-        ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
-            // Making the main page a distant page forces an error.
-            match write(tmp.borrow_mut(), (2, 0), &[0u8; SIZE]) {
-                Ok(_) => panic!("allowed main page failure"),
-                Err(error) => assert_eq!("tried to read distant page", error.to_string()),
-            }
-            let mut buf = [0u8; page::SIZE];
-            page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
-            assert_eq!([1u8; page::SIZE], buf);
-        });
+    fn write_when_sync_fails() {
+        let mut store = durable_pair(1);
+        store.inject(Fault::Fail(Op::Sync, None));
+        match write(&mut store, (0, 1), &[2u8; SIZE]) {
+            Ok(_) => panic!("allowed write without sync"),
+            Err(error) => assert!(matches!(error, Error::Io(_)), "{error}"),
+        }
+        store.heal();
+        store.crash(Crash::Drop).unwrap();
+
+        let mut buf = [0u8; SIZE];
+        read(&mut store, (0, 1), &mut buf).unwrap();
+        assert_eq!([1u8; SIZE], buf);
+    }
+
+    #[test]
+    fn write_alternates_between_pages() {
+        let mut store = durable_pair(1);
+        for (n, page) in [(2u8, 0), (3, 1), (4, 0)] {
+            let (writes, syncs) = (store.writes(), store.syncs());
+            write(&mut store, (0, 1), &[n; SIZE]).unwrap();
+            // Every update costs a single write and a single sync.
+            assert_eq!(writes + 1, store.writes());
+            assert_eq!(syncs + 1, store.syncs());
+
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(&mut store, page, &mut buf).unwrap();
+            assert_eq!([n; SIZE], buf[VALUE..VALUE + SIZE]);
+            assert_eq!(n as u64, sequence(&buf));
+            assert!(is_intact(&buf));
+        }
+        let mut buf = [0u8; SIZE];
+        read(&mut store, (0, 1), &mut buf).unwrap();
+        assert_eq!([4u8; SIZE], buf);
     }
 
     #[test]
-    fn write_without_errors() {
+    fn write_given_pair_without_intact_page() {
         ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
-            page::write(tmp.borrow_mut(), 1, &[2u8; page::SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 1, &[2u8; page::DEFAULT_SIZE]).unwrap();
 
-            write(tmp.borrow_mut(), (1, 0), &[3u8; SIZE]).unwrap();
+            match write(tmp.borrow_mut(), (1, 0), &[3u8; SIZE]) {
+                Ok(_) => panic!("allowed writing pair without intact page"),
+                Err(error) => assert!(matches!(error, Error::ChecksumMismatch { .. }), "{error}"),
+            }
 
-            let mut buf = [0u8; page::SIZE];
+            // Neither page was overwritten.
+            let mut buf = [0u8; page::DEFAULT_SIZE];
             page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
-            assert_eq!([2u8; page::SIZE], buf);
-
+            assert_eq!([1u8; page::DEFAULT_SIZE], buf);
             page::read(tmp.borrow_mut(), 1, &mut buf).unwrap();
-            assert_eq!(buf[0..SIZE], [3u8; SIZE]);
-            assert_eq!(integrity::crc(CRC_POLY, &buf[0..SIZE]), buf[SIZE]);
+            assert_eq!([2u8; page::DEFAULT_SIZE], buf);
         });
     }
 
     #[test]
-    fn read_when_main_is_corrupt() {
+    fn write_and_read_given_value_too_large() {
+        let mut store = durable_pair(1);
+        let writes = store.writes();
+        match write(&mut store, (0, 1), &[2u8; SIZE + 1]) {
+            Ok(_) => panic!("allowed writing value that does not fit"),
+            Err(error) => assert!(
+                matches!(error, Error::RecordTooLarge { size, max: SIZE } if size == SIZE + 1),
+                "{error}"
+            ),
+        }
+        assert_eq!(writes, store.writes());
+        match read(&mut store, (0, 1), &mut [0u8; SIZE + 1]) {
+            Ok(_) => panic!("allowed reading value that does not fit"),
+            Err(error) => assert!(
+                matches!(error, Error::RecordTooLarge { max: SIZE, .. }),
+                "{error}"
+            ),
+        }
+    }
+
+    #[test]
+    fn read_when_newest_is_corrupt() {
         ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
+            setup(tmp.borrow_mut());
+            write(tmp.borrow_mut(), (0, 1), &[1u8; SIZE]).unwrap();
             write(tmp.borrow_mut(), (0, 1), &[2u8; SIZE]).unwrap();
             // Overwrite the CRC error detection code at the end of the page.
-            page::write(tmp.borrow_mut(), 0, &[4u8; page::SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 0, &[4u8; page::DEFAULT_SIZE]).unwrap();
 
             let mut buf = [0u8; SIZE];
             read(tmp.borrow_mut(), (0, 1), &mut buf).unwrap();
             assert_eq!([1u8; SIZE], buf);
-            // Make sure the backup data is written to the main page.
-            let mut buf = [0u8; page::SIZE];
-            page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
-            assert_eq!([1u8; SIZE], buf[0..SIZE]);
-            assert_eq!(integrity::crc(CRC_POLY, &buf[0..SIZE]), buf[SIZE]);
         });
 
         ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
+            setup(tmp.borrow_mut());
+            write(tmp.borrow_mut(), (0, 1), &[1u8; SIZE]).unwrap();
             write(tmp.borrow_mut(), (0, 1), &[2u8; SIZE]).unwrap();
-            let mut buf = [0u8; page::SIZE];
+            let mut buf = [0u8; page::DEFAULT_SIZE];
             page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
             // Single byte corruption.
             buf[0] = !buf[0];
@@ -125,72 +280,233 @@ mod tests {
             let mut buf = [0u8; SIZE];
             read(tmp.borrow_mut(), (0, 1), &mut buf).unwrap();
             assert_eq!([1u8; SIZE], buf);
-            // Make sure the backup data is written to the main page.
-            let mut buf = [0u8; page::SIZE];
+            // The corrupt page is left for the next write to replace.
+            write(tmp.borrow_mut(), (0, 1), &[3u8; SIZE]).unwrap();
+            let mut buf = [0u8; page::DEFAULT_SIZE];
             page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
-            assert_eq!([1u8; SIZE], buf[0..SIZE]);
-            assert_eq!(integrity::crc(CRC_POLY, &buf[0..SIZE]), buf[SIZE]);
+            assert_eq!([3u8; SIZE], buf[VALUE..VALUE + SIZE]);
+            assert!(is_intact(&buf));
         });
     }
 
     #[test]
-    fn read_when_main_is_intact() {
+    fn read_takes_highest_sequence() {
         ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
-            write(tmp.borrow_mut(), (0, 1), &[2u8; SIZE]).unwrap();
+            setup(tmp.borrow_mut());
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            page[VALUE] = 7;
+            seal(&mut page, 9);
+            page::write(tmp.borrow_mut(), 1, &page).unwrap();
+            page[VALUE] = 8;
+            seal(&mut page, 8);
+            page::write(tmp.borrow_mut(), 0, &page).unwrap();
 
-            let mut buf = [0u8; SIZE];
+            let mut buf = [0u8; 1];
             read(tmp.borrow_mut(), (0, 1), &mut buf).unwrap();
-            assert_eq!([2u8; SIZE], buf);
+            assert_eq!([7], buf);
         });
     }
 
     #[test]
     fn init_when_backup_fails() {
         ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
             // Making the backup page a distant page forces an error.
             match init(tmp.borrow_mut(), (0, 2)) {
                 Ok(_) => panic!("allowed meta init page failure"),
-                Err(error) => assert_eq!("tried to write distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 2, end: 1 }), "{error}"),
             }
-            let mut buf = [0u8; page::SIZE];
+            let mut buf = [0u8; page::DEFAULT_SIZE];
             page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
-            assert_eq!([1u8; page::SIZE], buf);
+            assert_eq!([1u8; page::DEFAULT_SIZE], buf);
         });
     }
 
     #[test]
     fn init_when_main_fails() {
         ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
             // Making the backup page a distant page forces an error.
             match init(tmp.borrow_mut(), (2, 0)) {
                 Ok(_) => panic!("allowed meta init failure"),
-                Err(error) => assert_eq!("tried to write distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 2, end: 1 }), "{error}"),
             }
-            let mut buf = [0u8; page::SIZE];
+            let mut buf = [0u8; page::DEFAULT_SIZE];
             page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
-            assert_eq!([0u8; SIZE], buf[0..SIZE]);
-            assert_eq!(integrity::crc(CRC_POLY, &[0u8; SIZE]), buf[SIZE]);
+            assert_eq!([0u8; SIZE], buf[VALUE..VALUE + SIZE]);
+            assert!(is_intact(&buf));
         });
     }
 
     #[test]
     fn init_without_errors() {
         ephemeral::file!(tmp {
-            page::write(tmp.borrow_mut(), 0, &[1u8; page::SIZE]).unwrap();
-            page::write(tmp.borrow_mut(), 1, &[2u8; page::SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(tmp.borrow_mut(), 1, &[2u8; page::DEFAULT_SIZE]).unwrap();
 
             init(tmp.borrow_mut(), (1, 0)).unwrap();
 
-            let mut expected = [0u8; page::SIZE];
-            expected[SIZE] = integrity::crc(CRC_POLY, &expected[0..SIZE]);
-            let mut buf = [0u8; page::SIZE];
+            let mut expected = [0u8; page::DEFAULT_SIZE];
+            seal(&mut expected, 0);
+            let mut buf = [0u8; page::DEFAULT_SIZE];
             page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
             assert_eq!(expected, buf);
             page::read(tmp.borrow_mut(), 1, &mut buf).unwrap();
             assert_eq!(expected, buf);
         });
     }
+
+    // A meta pair at pages 0 and 1 that durably holds the given value.
+    fn durable_pair(n: u8) -> Faulty<Memory> {
+        let mut store = Faulty::new(Memory::new());
+        setup(&mut store);
+        write(&mut store, (0, 1), &[n; SIZE]).unwrap();
+        store
+    }
+
+    #[test]
+    fn read_when_both_pages_are_corrupt() {
+        let mut store = durable_pair(1);
+        store.inject(Fault::FlipBit(0, 7));
+        store.inject(Fault::FlipBit(1, 7));
+        let writes = store.writes();
+        let mut buf = [0u8; SIZE];
+        match read(&mut store, (0, 1), &mut buf) {
+            Ok(_) => panic!("allowed reading corrupt pair"),
+            Err(error) => assert!(
+                matches!(error, Error::ChecksumMismatch { page: 1, .. }),
+                "{error}"
+            ),
+        }
+        assert_eq!(writes, store.writes());
+    }
+
+    #[test]
+    fn read_when_newest_reads_corrupt() {
+        let mut store = durable_pair(1);
+        store.inject(Fault::FlipBit(1, 100));
+        let mut buf = [0u8; SIZE];
+        read(&mut store, (0, 1), &mut buf).unwrap();
+        // The other page holds the value from before the last write.
+        assert_eq!([0u8; SIZE], buf);
+    }
+
+    #[test]
+    fn write_is_atomic_across_crashes() {
+        for len in [0, 1, page::DEFAULT_SIZE / 2, SIZE, page::DEFAULT_SIZE] {
+            for seed in 0..8 {
+                let mut store = durable_pair(1);
+                store.inject(Fault::TearWrite(0, len));
+                let _ = write(&mut store, (0, 1), &[2u8; SIZE]);
+                store.crash(Crash::Reorder(seed)).unwrap();
+
+                let mut buf = [0u8; SIZE];
+                read(&mut store, (0, 1), &mut buf).unwrap();
+                assert!(
+                    buf == [1u8; SIZE] || buf == [2u8; SIZE],
+                    "torn write of {len} bytes with seed {seed} lost the pair"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn write_after_torn_write_keeps_previous_version() {
+        let mut store = durable_pair(1);
+        store.inject(Fault::TearWrite(0, 100));
+        assert!(write(&mut store, (0, 1), &[2u8; SIZE]).is_err());
+        // The torn page does not count as the newest, so the next write
+        // replaces it rather than the page that holds the durable version.
+        store.inject(Fault::FailSync(0));
+        assert!(write(&mut store, (0, 1), &[3u8; SIZE]).is_err());
+        store.heal();
+        store.crash(Crash::Drop).unwrap();
+
+        let mut buf = [0u8; SIZE];
+        read(&mut store, (0, 1), &mut buf).unwrap();
+        assert_eq!([1u8; SIZE], buf);
+    }
+
+    // Sets up a meta pair at pages 0 and 1 of an empty store.
+    fn setup(store: &mut dyn PageStore) {
+        let zeroes = page::buffer(store);
+        page::write(store, 0, &zeroes).unwrap();
+        page::write(store, 1, &zeroes).unwrap();
+        init(store, (0, 1)).unwrap();
+    }
+
+    #[test]
+    fn short_value_at_every_page_size() {
+        let mut size = page::MIN_SIZE;
+        while size <= page::MAX_SIZE {
+            let mut store = Memory::with_page_size(size);
+            setup(&mut store);
+            write(&mut store, (0, 1), b"value").unwrap();
+            assert_eq!(2 * size as u64, store.size().unwrap());
+
+            let mut buf = [0u8; 5];
+            read(&mut store, (0, 1), &mut buf).unwrap();
+            assert_eq!(b"value", &buf);
+            size *= 2;
+        }
+    }
+
+    #[test]
+    fn read_given_other_page_size() {
+        let mut store = Memory::new();
+        setup(&mut store);
+        let mut page = [0u8; page::DEFAULT_SIZE];
+        page[PAGE_SIZE..PAGE_SIZE + 4].copy_from_slice(&4096u32.to_le_bytes());
+        let crc = checksum(&page[..page::DEFAULT_SIZE - CRC]);
+        page[page::DEFAULT_SIZE - CRC..].copy_from_slice(&crc.to_le_bytes());
+        page::write(&mut store, 0, &page).unwrap();
+
+        match read(&mut store, (0, 1), &mut [0u8; 8]) {
+            Ok(_) => panic!("allowed reading pair of other page size"),
+            Err(error) => assert!(
+                matches!(
+                    error,
+                    Error::PageSizeMismatch {
+                        page: 0,
+                        expected: 8192,
+                        found: 4096
+                    }
+                ),
+                "{error}"
+            ),
+        }
+    }
+
+    #[test]
+    fn probe_finds_page_size() {
+        for size in [4096, 16384, 65536] {
+            ephemeral::disk!(tmp {
+                let mut store = Disk::create(tmp.borrow_mut().try_clone().unwrap(), size).unwrap();
+                setup(&mut store);
+                assert_eq!(size, probe(tmp.borrow_mut()).unwrap());
+            });
+        }
+    }
+
+    #[test]
+    fn probe_when_main_is_torn() {
+        ephemeral::disk!(tmp {
+            let mut store = Disk::create(tmp.borrow_mut().try_clone().unwrap(), 16384).unwrap();
+            setup(&mut store);
+            page::write(&mut store, 0, &[0u8; 16384]).unwrap();
+            assert_eq!(16384, probe(tmp.borrow_mut()).unwrap());
+        });
+    }
+
+    #[test]
+    fn probe_given_file_without_meta_pair() {
+        ephemeral::disk!(tmp {
+            let mut store = Disk::create(tmp.borrow_mut().try_clone().unwrap(), 4096).unwrap();
+            page::write(&mut store, 0, &[1u8; 4096]).unwrap();
+            match probe(tmp.borrow_mut()) {
+                Ok(size) => panic!("found page size {size} without meta pair"),
+                Err(error) => assert!(matches!(error, Error::UnknownPageSize), "{error}"),
+            }
+        });
+    }
 }
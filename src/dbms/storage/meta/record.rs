@@ -0,0 +1,273 @@
+use crate::dbms::storage::{Error, PageStore, Result, meta};
+
+// A record is kept in a meta pair behind the version of the layout that it
+// was written with. A pair that was initialized but never written holds
+// version zero, which reads as the default record.
+const VERSION: usize = 0;
+const FIELDS: usize = 2;
+
+// Structs with a fixed layout of little-endian fields that are kept in meta
+// pairs. Fields are only ever appended to the layout, each time under a new
+// version, so that records written by older versions can still be read.
+pub trait Record: Default {
+    // The version of the layout that records are written with, from one.
+    const VERSION: u16;
+    // How many bytes the fields of this version take.
+    const SIZE: usize;
+
+    fn encode(&self, writer: &mut Writer<'_>);
+
+    // Decodes the fields of a record written with the given version, which
+    // is never newer than this one. Fields added since keep their defaults.
+    fn decode(&mut self, reader: &mut Reader<'_>, version: u16);
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let (head, tail) = self.buf.split_at(N);
+        self.buf = tail;
+        head.try_into().unwrap()
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        u8::from_le_bytes(self.bytes())
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.bytes())
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.bytes())
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.bytes())
+    }
+
+    pub fn u128(&mut self) -> u128 {
+        u128::from_le_bytes(self.bytes())
+    }
+}
+
+pub struct Writer<'a> {
+    buf: &'a mut [u8],
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        let (head, tail) = std::mem::take(&mut self.buf).split_at_mut(bytes.len());
+        head.copy_from_slice(bytes);
+        self.buf = tail;
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u128(&mut self, value: u128) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    // How many bytes are left to write.
+    pub fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+pub fn read<R: Record>(store: &mut dyn PageStore, pair: (u64, u64)) -> Result<R> {
+    let mut buf = vec![0u8; FIELDS + R::SIZE];
+    meta::read(store, pair, &mut buf)?;
+    let version = u16::from_le_bytes(buf[VERSION..VERSION + 2].try_into().unwrap());
+    if version > R::VERSION {
+        return Err(Error::UnsupportedRecord {
+            version,
+            expected: R::VERSION,
+        });
+    }
+    let mut record = R::default();
+    if version > 0 {
+        // Records of older versions are shorter, and the rest of the buffer
+        // holds the zeroes that the pair was padded with.
+        record.decode(&mut Reader::new(&buf[FIELDS..]), version);
+    }
+    Ok(record)
+}
+
+pub fn write<R: Record>(store: &mut dyn PageStore, pair: (u64, u64), record: &R) -> Result<()> {
+    assert!(R::VERSION > 0, "meta record version zero is reserved");
+    let mut buf = vec![0u8; FIELDS + R::SIZE];
+    buf[VERSION..VERSION + 2].copy_from_slice(&R::VERSION.to_le_bytes());
+    let mut writer = Writer::new(&mut buf[FIELDS..]);
+    record.encode(&mut writer);
+    assert_eq!(0, writer.remaining(), "meta record does not fill its size");
+    meta::write(store, pair, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::storage::{page, store::Memory};
+
+    // The first version of a record, and the second that appended a field.
+    #[derive(Debug, Default, PartialEq)]
+    struct Old {
+        root: u64,
+        count: u32,
+    }
+
+    impl Record for Old {
+        const VERSION: u16 = 1;
+        const SIZE: usize = 12;
+
+        fn encode(&self, writer: &mut Writer<'_>) {
+            writer.u64(self.root);
+            writer.u32(self.count);
+        }
+
+        fn decode(&mut self, reader: &mut Reader<'_>, _: u16) {
+            self.root = reader.u64();
+            self.count = reader.u32();
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct New {
+        root: u64,
+        count: u32,
+        lsn: u64,
+    }
+
+    impl Default for New {
+        fn default() -> Self {
+            Self {
+                root: 0,
+                count: 0,
+                lsn: u64::MAX,
+            }
+        }
+    }
+
+    impl Record for New {
+        const VERSION: u16 = 2;
+        const SIZE: usize = 20;
+
+        fn encode(&self, writer: &mut Writer<'_>) {
+            writer.u64(self.root);
+            writer.u32(self.count);
+            writer.u64(self.lsn);
+        }
+
+        fn decode(&mut self, reader: &mut Reader<'_>, version: u16) {
+            self.root = reader.u64();
+            self.count = reader.u32();
+            if version >= 2 {
+                self.lsn = reader.u64();
+            }
+        }
+    }
+
+    fn setup() -> Memory {
+        let mut store = Memory::new();
+        let zeroes = page::buffer(&store);
+        page::write(&mut store, 0, &zeroes).unwrap();
+        page::write(&mut store, 1, &zeroes).unwrap();
+        meta::init(&mut store, (0, 1)).unwrap();
+        store
+    }
+
+    #[test]
+    fn write_then_read() {
+        let mut store = setup();
+        let record = New {
+            root: 7,
+            count: 3,
+            lsn: 1 << 40,
+        };
+        write(&mut store, (0, 1), &record).unwrap();
+        assert_eq!(record, read(&mut store, (0, 1)).unwrap());
+
+        // The fields follow the version in little-endian order.
+        let mut buf = [0u8; 22];
+        meta::read(&mut store, (0, 1), &mut buf).unwrap();
+        assert_eq!([2, 0, 7, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0], buf[..14]);
+        assert_eq!((1u64 << 40).to_le_bytes(), buf[14..]);
+    }
+
+    #[test]
+    fn read_before_first_write() {
+        let mut store = setup();
+        assert_eq!(New::default(), read(&mut store, (0, 1)).unwrap());
+    }
+
+    #[test]
+    fn read_given_older_version() {
+        let mut store = setup();
+        write(&mut store, (0, 1), &Old { root: 7, count: 3 }).unwrap();
+        assert_eq!(
+            New {
+                root: 7,
+                count: 3,
+                lsn: u64::MAX
+            },
+            read(&mut store, (0, 1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn read_given_newer_version() {
+        let mut store = setup();
+        write(&mut store, (0, 1), &New::default()).unwrap();
+        match read::<Old>(&mut store, (0, 1)) {
+            Ok(_) => panic!("allowed reading record of newer version"),
+            Err(error) => assert_eq!(
+                "unsupported meta record version 2, expected at most 1",
+                error.to_string()
+            ),
+        }
+    }
+
+    #[test]
+    #[should_panic = "meta record does not fill its size"]
+    fn write_given_short_encoding() {
+        #[derive(Default)]
+        struct Short;
+
+        impl Record for Short {
+            const VERSION: u16 = 1;
+            const SIZE: usize = 8;
+
+            fn encode(&self, writer: &mut Writer<'_>) {
+                writer.u32(0);
+            }
+
+            fn decode(&mut self, _: &mut Reader<'_>, _: u16) {}
+        }
+
+        write(&mut setup(), (0, 1), &Short).unwrap();
+    }
+}
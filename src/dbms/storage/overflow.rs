@@ -0,0 +1,366 @@
+use std::io::{self, Read, Write};
+
+use crate::dbms::storage::{
+    Error, PageStore, Result, alloc,
+    integrity::Algorithm,
+    page::{self, header},
+};
+
+// Marks the last page of a chain.
+const NONE: u64 = u64::MAX;
+
+// Overflow pages are mostly record data, which XXH3 gets through the fastest.
+const ALGORITHM: Algorithm = Algorithm::Xxh3;
+
+// Every page in a chain starts with the page header, followed by the number of
+// the next page and the number of data bytes stored in this one.
+const NEXT: usize = header::SIZE;
+const LEN: usize = NEXT + 8;
+const DATA: usize = LEN + 2;
+
+// The number of data bytes that fit in an overflow page.
+pub const fn capacity(page_size: usize) -> usize {
+    page_size - DATA
+}
+
+// Streams a record into a chain of overflow pages taken from the allocator in
+// the given meta pair. The chain is only complete once `finish` has returned
+// its head, pages of an unfinished chain are leaked.
+pub struct Writer<'a> {
+    store: &'a mut dyn PageStore,
+    pair: (u64, u64),
+    head: u64,
+    current: u64,
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(store: &'a mut dyn PageStore, pair: (u64, u64)) -> Result<Self> {
+        let head = alloc::allocate(store, pair)?;
+        let buf = page::buffer(store);
+        Ok(Self {
+            store,
+            pair,
+            head,
+            current: head,
+            buf,
+            len: 0,
+        })
+    }
+
+    pub fn finish(mut self) -> Result<u64> {
+        self.seal(NONE)?;
+        Ok(self.head)
+    }
+
+    fn seal(&mut self, next: u64) -> Result<()> {
+        header::init(
+            &mut self.buf,
+            header::Kind::Overflow,
+            self.current,
+            ALGORITHM,
+        );
+        self.buf[NEXT..LEN].copy_from_slice(&next.to_le_bytes());
+        self.buf[LEN..DATA].copy_from_slice(&(self.len as u16).to_le_bytes());
+        self.buf[DATA + self.len..].fill(0);
+        header::seal(&mut self.buf);
+        page::write(self.store, self.current, &self.buf)
+    }
+}
+
+impl Write for Writer<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // A full page is only written out once there is more data, since the
+        // last page of the chain has no successor.
+        let capacity = capacity(self.buf.len());
+        if self.len == capacity {
+            let next = alloc::allocate(self.store, self.pair)?;
+            self.seal(next)?;
+            self.current = next;
+            self.len = 0;
+        }
+        let n = buf.len().min(capacity - self.len);
+        self.buf[DATA + self.len..DATA + self.len + n].copy_from_slice(&buf[..n]);
+        self.len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Reader<'a> {
+    store: &'a mut dyn PageStore,
+    head: u64,
+    next: u64,
+    // The number of pages read so far, which a chain that leads back into
+    // itself keeps adding to past the number of pages in the store.
+    pages: u64,
+    buf: Vec<u8>,
+    pos: usize,
+    len: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(store: &'a mut dyn PageStore, head: u64) -> Self {
+        let buf = page::buffer(store);
+        Self {
+            store,
+            head,
+            next: head,
+            pages: 0,
+            buf,
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    fn load(&mut self) -> Result<()> {
+        if self.pages == page::count(self.store)? {
+            return Err(Error::EndlessChain { head: self.head });
+        }
+        self.pages += 1;
+        page::read(self.store, self.next, &mut self.buf)?;
+        verify(&self.buf, self.next)?;
+        self.next = u64::from_le_bytes(self.buf[NEXT..LEN].try_into().unwrap());
+        self.len = u16::from_le_bytes(self.buf[LEN..DATA].try_into().unwrap()) as usize;
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl Read for Reader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Pages can be empty, which happens for the head of an empty record.
+        while self.pos == self.len {
+            if self.next == NONE {
+                return Ok(0);
+            }
+            self.load()?;
+        }
+        let n = buf.len().min(self.len - self.pos);
+        buf[..n].copy_from_slice(&self.buf[DATA + self.pos..DATA + self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn verify(buf: &[u8], page: u64) -> Result<()> {
+    header::expect(buf, page, header::Kind::Overflow)
+}
+
+// Returns every page of the chain starting at `head` to the allocator. The
+// whole chain is checked first, so that a corrupt one is left as it is
+// instead of being freed halfway.
+pub fn free(store: &mut dyn PageStore, pair: (u64, u64), head: u64) -> Result<()> {
+    let count = page::count(store)?;
+    let mut pages = Vec::new();
+    let mut next = head;
+    let mut buf = page::buffer(store);
+    while next != NONE {
+        if pages.len() as u64 == count {
+            return Err(Error::EndlessChain { head });
+        }
+        page::read(store, next, &mut buf)?;
+        verify(&buf, next)?;
+        pages.push(next);
+        next = u64::from_le_bytes(buf[NEXT..LEN].try_into().unwrap());
+    }
+    for page in pages {
+        alloc::free(store, pair, page)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::storage::{ephemeral, store::Memory};
+
+    fn setup(store: &mut dyn PageStore) {
+        let zeroes = page::buffer(store);
+        page::write(store, 0, &zeroes).unwrap();
+        page::write(store, 1, &zeroes).unwrap();
+        alloc::init(store, (0, 1)).unwrap();
+    }
+
+    fn store(store: &mut dyn PageStore, record: &[u8]) -> u64 {
+        let mut writer = Writer::new(store, (0, 1)).unwrap();
+        writer.write_all(record).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn load(store: &mut dyn PageStore, head: u64) -> io::Result<Vec<u8>> {
+        let mut record = Vec::new();
+        Reader::new(store, head).read_to_end(&mut record)?;
+        Ok(record)
+    }
+
+    // Reading goes through `io::Read`, which keeps the storage error inside.
+    fn storage_error(error: &io::Error) -> Option<&Error> {
+        error.get_ref()?.downcast_ref::<Error>()
+    }
+
+    #[test]
+    fn record_spanning_several_pages() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let record: Vec<u8> = (0..200_000).map(|n| (n % 251) as u8).collect();
+            let head = store(tmp.borrow_mut(), &record);
+
+            assert_eq!(2, head);
+            assert_eq!(2 + record.len().div_ceil(capacity(page::DEFAULT_SIZE)) as u64, page::count(tmp.borrow_mut()).unwrap());
+            assert_eq!(record, load(tmp.borrow_mut(), head).unwrap());
+        });
+    }
+
+    #[test]
+    fn record_at_smallest_and_largest_page_size() {
+        for size in [page::MIN_SIZE, page::MAX_SIZE] {
+            let mut memory = Memory::with_page_size(size);
+            setup(&mut memory);
+            let record: Vec<u8> = (0..capacity(size) * 5 / 2)
+                .map(|n| (n % 251) as u8)
+                .collect();
+            let head = store(&mut memory, &record);
+
+            assert_eq!(5, page::count(&mut memory).unwrap());
+            assert_eq!(record, load(&mut memory, head).unwrap());
+        }
+    }
+
+    #[test]
+    fn record_filling_pages_exactly() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let record = vec![7u8; capacity(page::DEFAULT_SIZE) * 2];
+            let head = store(tmp.borrow_mut(), &record);
+
+            // No empty page is chained on after the last full one.
+            assert_eq!(4, page::count(tmp.borrow_mut()).unwrap());
+            assert_eq!(record, load(tmp.borrow_mut(), head).unwrap());
+        });
+    }
+
+    #[test]
+    fn empty_record() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let head = store(tmp.borrow_mut(), &[]);
+            assert_eq!(3, page::count(tmp.borrow_mut()).unwrap());
+            assert!(load(tmp.borrow_mut(), head).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn reader_streams_in_small_reads() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let record: Vec<u8> = (0..capacity(page::DEFAULT_SIZE) * 3 / 2).map(|n| n as u8).collect();
+            let head = store(tmp.borrow_mut(), &record);
+
+            let mut reader = Reader::new(tmp.borrow_mut(), head);
+            let mut chunk = [0u8; 1000];
+            let mut streamed = Vec::new();
+            loop {
+                let n = reader.read(&mut chunk).unwrap();
+                if n == 0 {
+                    break;
+                }
+                streamed.extend_from_slice(&chunk[..n]);
+            }
+            assert_eq!(record, streamed);
+        });
+    }
+
+    #[test]
+    fn reader_when_page_is_corrupt() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let head = store(tmp.borrow_mut(), &vec![1u8; capacity(page::DEFAULT_SIZE) + 10]);
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), head + 1, &mut buf).unwrap();
+            buf[DATA] = !buf[DATA];
+            page::write(tmp.borrow_mut(), head + 1, &buf).unwrap();
+
+            match load(tmp.borrow_mut(), head) {
+                Ok(_) => panic!("allowed reading corrupt overflow page"),
+                Err(error) => {
+                    assert_eq!(io::ErrorKind::InvalidData, error.kind());
+                    assert!(
+                        matches!(storage_error(&error), Some(Error::ChecksumMismatch { page, .. }) if *page == head + 1),
+                        "{error}"
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn reader_when_page_is_misdirected() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let head = store(tmp.borrow_mut(), &vec![1u8; capacity(page::DEFAULT_SIZE) + 10]);
+            // The second page of the chain ends up where the head should be.
+            page::copy(tmp.borrow_mut(), head + 1, head).unwrap();
+
+            match load(tmp.borrow_mut(), head) {
+                Ok(_) => panic!("allowed reading misdirected overflow page"),
+                Err(error) => assert!(
+                    matches!(
+                        storage_error(&error),
+                        Some(Error::BadHeader { page, error: header::Error::Misdirected { .. } }) if *page == head
+                    ),
+                    "{error}"
+                ),
+            }
+        });
+    }
+
+    #[test]
+    fn reader_and_free_when_chain_loops() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let head = store(tmp.borrow_mut(), &vec![1u8; capacity(page::DEFAULT_SIZE) * 3]);
+            // The last page of the chain leads back to the second one.
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), head + 2, &mut buf).unwrap();
+            buf[NEXT..LEN].copy_from_slice(&(head + 1).to_le_bytes());
+            header::seal(&mut buf);
+            page::write(tmp.borrow_mut(), head + 2, &buf).unwrap();
+
+            match load(tmp.borrow_mut(), head) {
+                Ok(_) => panic!("allowed reading endless chain"),
+                Err(error) => assert!(
+                    matches!(storage_error(&error), Some(Error::EndlessChain { head: h }) if *h == head),
+                    "{error}"
+                ),
+            }
+            match free(tmp.borrow_mut(), (0, 1), head) {
+                Ok(_) => panic!("allowed freeing endless chain"),
+                Err(error) => assert!(matches!(error, Error::EndlessChain { head: h } if h == head), "{error}"),
+            }
+            // None of the chain was freed.
+            assert_eq!(5, alloc::allocate(tmp.borrow_mut(), (0, 1)).unwrap());
+        });
+    }
+
+    #[test]
+    fn free_returns_chain_to_allocator() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let head = store(tmp.borrow_mut(), &vec![1u8; capacity(page::DEFAULT_SIZE) * 3]);
+            free(tmp.borrow_mut(), (0, 1), head).unwrap();
+
+            let record = vec![2u8; capacity(page::DEFAULT_SIZE) * 2 + 1];
+            let head = store(tmp.borrow_mut(), &record);
+            assert_eq!(5, page::count(tmp.borrow_mut()).unwrap());
+            assert_eq!(record, load(tmp.borrow_mut(), head).unwrap());
+        });
+    }
+}
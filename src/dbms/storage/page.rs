@@ -1,46 +1,611 @@
-use std::{
-    error::Error,
-    fs::File,
-    io::{self, Read, Seek, Write},
-};
+use std::ops::Range;
 
-pub const SIZE: usize = 8192;
+use crate::dbms::storage::{Error, PageStore, Result};
 
-pub fn read(file: &mut File, page: u64, buf: &mut [u8; SIZE]) -> io::Result<()> {
-    let max = file.metadata()?.len() / SIZE as u64;
-    if page + 1 > max {
-        return Err(io::Error::other("tried to read distant page"));
+// Every store has a page size of its own, which is a power of two in this
+// range and fixed when the store is created.
+pub const MIN_SIZE: usize = 4096;
+pub const MAX_SIZE: usize = 65536;
+
+pub const DEFAULT_SIZE: usize = 8192;
+
+pub fn check_size(size: usize) -> Result<()> {
+    if size.is_power_of_two() && (MIN_SIZE..=MAX_SIZE).contains(&size) {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedPageSize { size })
+    }
+}
+
+// A zeroed buffer that holds a page of the store.
+pub fn buffer(store: &dyn PageStore) -> Vec<u8> {
+    vec![0u8; store.page_size()]
+}
+
+// Buffers have to hold exactly one page of the store, and are refused
+// otherwise.
+fn check_buffer(store: &dyn PageStore, buf: &[u8]) -> Result<()> {
+    if buf.len() != store.page_size() {
+        return Err(Error::BufferSizeMismatch {
+            expected: store.page_size(),
+            found: buf.len(),
+        });
     }
-    file.seek(io::SeekFrom::Start(page * SIZE as u64))?;
-    file.read_exact(buf).map(|_| ())
+    Ok(())
 }
 
-pub fn write(file: &mut File, page: u64, buf: &[u8; SIZE]) -> io::Result<()> {
-    let max = file.metadata()?.len() / SIZE as u64;
-    if page > max {
-        return Err(io::Error::other("tried to write distant page"));
+pub fn read(store: &mut dyn PageStore, page: u64, buf: &mut [u8]) -> Result<()> {
+    check_buffer(store, buf)?;
+    let size = store.page_size();
+    let len = store.size()?;
+    if page
+        .checked_mul(size as u64)
+        .is_none_or(|offset| offset >= len)
+    {
+        return Err(Error::OutOfRange {
+            page,
+            end: len.div_ceil(size as u64),
+        });
     }
-    file.seek(io::SeekFrom::Start(page * SIZE as u64))?;
-    file.write_all(buf).map(|_| ())
+    let found = store.read_page(page, buf)?;
+    if found < size {
+        return Err(Error::ShortRead {
+            page,
+            expected: size,
+            found,
+        });
+    }
+    Ok(())
 }
 
-pub fn copy(file: &mut File, src: u64, dst: u64) -> io::Result<()> {
+pub fn write(store: &mut dyn PageStore, page: u64, buf: &[u8]) -> Result<()> {
+    check_buffer(store, buf)?;
+    let end = count(store)?;
+    if page > end {
+        return Err(Error::OutOfRange { page, end });
+    }
+    Ok(store.write_page(page, buf)?)
+}
+
+pub fn copy(store: &mut dyn PageStore, src: u64, dst: u64) -> Result<()> {
     if src == dst {
-        return Err(io::Error::other("tried to copy page to itself"));
+        return Err(Error::CopyToSelf { page: src });
     }
-    let mut buf = [0u8; SIZE];
-    read(file, src, &mut buf)?;
-    write(file, dst, &buf)
+    let mut buf = buffer(store);
+    read(store, src, &mut buf)?;
+    write(store, dst, &buf)
 }
 
-pub mod slot {
-    use std::io;
+// Reads a run of adjacent pages with a single request to the store, which
+// for a file is a single preadv.
+pub fn read_range(store: &mut dyn PageStore, first: u64, bufs: &mut [&mut [u8]]) -> Result<()> {
+    for buf in bufs.iter() {
+        check_buffer(store, buf)?;
+    }
+    let size = store.page_size();
+    let len = store.size()?;
+    let end = len.div_ceil(size as u64);
+    if first
+        .checked_add(bufs.len() as u64)
+        .is_none_or(|last| last > end)
+    {
+        return Err(Error::OutOfRange {
+            page: first.max(end),
+            end,
+        });
+    }
+    let found = store.read_pages(first, bufs)?;
+    if found < bufs.len() * size {
+        return Err(Error::ShortRead {
+            page: first + (found / size) as u64,
+            expected: size,
+            found: found % size,
+        });
+    }
+    Ok(())
+}
+
+// Writes a run of adjacent pages with a single request to the store. Like a
+// single page, the run may start at the end of the store and extend it.
+pub fn write_range(store: &mut dyn PageStore, first: u64, bufs: &[&[u8]]) -> Result<()> {
+    for buf in bufs {
+        check_buffer(store, buf)?;
+    }
+    let end = count(store)?;
+    if first > end {
+        return Err(Error::OutOfRange { page: first, end });
+    }
+    Ok(store.write_pages(first, bufs)?)
+}
+
+// Tells the store that a scan is about to read the pages, so that it can
+// stream them in ahead of the scan. Pages past the end are left out.
+pub fn prefetch(store: &mut dyn PageStore, pages: Range<u64>) -> Result<()> {
+    let end = count(store)?;
+    let pages = pages.start..pages.end.min(end);
+    if !pages.is_empty() {
+        store.advise(pages)?;
+    }
+    Ok(())
+}
+
+// The number of whole pages in the store.
+pub fn count(store: &mut dyn PageStore) -> Result<u64> {
+    Ok(store.size()? / store.page_size() as u64)
+}
+
+// Reads a page and checks its header. A page that is protected by error
+// correction and has rotted is repaired, in the store as well.
+pub fn read_checked(
+    store: &mut dyn PageStore,
+    page: u64,
+    buf: &mut [u8],
+) -> Result<header::Health> {
+    read(store, page, buf)?;
+    let (_, health) = header::repair(buf, page)?;
+    if health != header::Health::Intact {
+        write(store, page, buf)?;
+    }
+    Ok(health)
+}
+
+pub mod header {
+    use std::{error, fmt};
+
+    use crate::dbms::storage::{
+        self,
+        integrity::{Algorithm, ecc},
+        wal::Lsn,
+    };
+
+    const MAGIC: [u8; 4] = *b"SHPG";
+
+    pub const VERSION: u16 = 1;
+
+    // The checksum comes first so that it can cover every byte after it,
+    // including the rest of the header.
+    const CHECKSUM: usize = 0;
+    const MAGIC_AT: usize = 8;
+    const KIND: usize = 12;
+    const ALGORITHM: usize = 13;
+    const VERSION_AT: usize = 14;
+    const ID: usize = 16;
+    const LSN: usize = 24;
+    const FLAGS: usize = 32;
+
+    pub const SIZE: usize = 40;
+
+    // The last eighth of a protected page holds error correcting parity.
+    const PROTECTED: u8 = 0x01;
+
+    // Meta pairs carry no header. They keep a layout of their own, which
+    // starts with the page size so that a file can be opened before the page
+    // size is known, and are never logged. Kind 1 was theirs and is unused.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Kind {
+        Free = 2,
+        Slotted = 3,
+        Overflow = 4,
+        Directory = 5,
+        FreeSpace = 6,
+    }
+
+    impl TryFrom<u8> for Kind {
+        type Error = Error;
+
+        fn try_from(n: u8) -> Result<Self, Self::Error> {
+            match n {
+                2 => Ok(Kind::Free),
+                3 => Ok(Kind::Slotted),
+                4 => Ok(Kind::Overflow),
+                5 => Ok(Kind::Directory),
+                6 => Ok(Kind::FreeSpace),
+                _ => Err(Error::UnknownKind(n)),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum Error {
+        NotAPage,
+        UnknownKind(u8),
+        // The page is of another kind than the format reading it.
+        UnexpectedKind(u8),
+        UnknownAlgorithm(u8),
+        UnsupportedVersion(u16),
+        // The page holds another page, most likely because a write went to
+        // the wrong place.
+        Misdirected { expected: u64, found: u64 },
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum Health {
+        Intact,
+        // The number of bytes that had to be corrected.
+        Repaired(usize),
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Error::NotAPage => write!(f, "page is not formatted"),
+                Error::UnknownKind(n) => write!(f, "unknown page kind {n}"),
+                Error::UnexpectedKind(n) => write!(f, "unexpected page kind {n}"),
+                Error::UnknownAlgorithm(n) => write!(f, "unknown checksum algorithm {n}"),
+                Error::UnsupportedVersion(n) => write!(f, "unsupported page version {n}"),
+                Error::Misdirected { found, .. } => {
+                    write!(f, "page {found} was found in its place")
+                }
+            }
+        }
+    }
+
+    impl error::Error for Error {}
+
+    // Formats the header of a page. Every page format picks the checksum
+    // that suits it, and the header records the choice so that the page can
+    // be verified without knowing its format. Everything after the header is
+    // left as is, and the checksum is not valid until the page is sealed.
+    pub fn init(page: &mut [u8], kind: Kind, id: u64, algorithm: Algorithm) {
+        page[0..SIZE].fill(0);
+        page[MAGIC_AT..MAGIC_AT + 4].copy_from_slice(&MAGIC);
+        page[KIND] = kind as u8;
+        page[ALGORITHM] = algorithm as u8;
+        page[VERSION_AT..VERSION_AT + 2].copy_from_slice(&VERSION.to_le_bytes());
+        page[ID..ID + 8].copy_from_slice(&id.to_le_bytes());
+    }
+
+    pub fn is_formatted(page: &[u8]) -> bool {
+        page[MAGIC_AT..MAGIC_AT + 4] == MAGIC
+    }
+
+    pub fn kind(page: &[u8]) -> Result<Kind, Error> {
+        Kind::try_from(page[KIND])
+    }
+
+    pub fn algorithm(page: &[u8]) -> Result<Algorithm, Error> {
+        Algorithm::try_from(page[ALGORITHM]).map_err(Error::UnknownAlgorithm)
+    }
+
+    pub fn version(page: &[u8]) -> u16 {
+        u16::from_le_bytes(page[VERSION_AT..VERSION_AT + 2].try_into().unwrap())
+    }
+
+    pub fn id(page: &[u8]) -> u64 {
+        u64::from_le_bytes(page[ID..ID + 8].try_into().unwrap())
+    }
+
+    // The LSN of the last log record describing a change to the page, which
+    // whoever changes the page sets before writing it. Redo skips records at
+    // or below it, since the page already reflects them.
+    pub fn lsn(page: &[u8]) -> Lsn {
+        u64::from_le_bytes(page[LSN..LSN + 8].try_into().unwrap())
+    }
+
+    pub fn set_lsn(page: &mut [u8], lsn: Lsn) {
+        page[LSN..LSN + 8].copy_from_slice(&lsn.to_le_bytes());
+    }
+
+    pub fn checksum(page: &[u8]) -> u64 {
+        u64::from_le_bytes(page[CHECKSUM..CHECKSUM + 8].try_into().unwrap())
+    }
+
+    // Reserves the end of the page for error correcting parity, so that the
+    // page can be repaired when it rots. Page formats that opt in must keep
+    // their data before `end`.
+    pub fn protect(page: &mut [u8]) {
+        page[FLAGS] |= PROTECTED;
+    }
+
+    pub fn is_protected(page: &[u8]) -> bool {
+        page[FLAGS] & PROTECTED != 0
+    }
+
+    // The end of the part of the page that page formats can use.
+    pub fn end(page: &[u8]) -> usize {
+        if is_protected(page) {
+            page.len() - ecc::parity(page.len())
+        } else {
+            page.len()
+        }
+    }
+
+    // The parity is computed after the checksum, so it is left out of it.
+    fn compute(page: &[u8], algorithm: Algorithm) -> u64 {
+        algorithm.get().checksum(&page[CHECKSUM + 8..end(page)])
+    }
+
+    // Computes the checksum over the whole page, and the parity if the page is
+    // protected. Has to be done after the last change to the page and before
+    // it is written out.
+    pub fn seal(page: &mut [u8]) {
+        let algorithm = algorithm(page).expect("page header is initialised");
+        let checksum = compute(page, algorithm);
+        page[CHECKSUM..CHECKSUM + 8].copy_from_slice(&checksum.to_le_bytes());
+        if is_protected(page) {
+            ecc::encode(page);
+        }
+    }
+
+    // Checks that the page is intact and that it is the page that was asked
+    // for, which catches writes that landed on the wrong page as well as
+    // reads of the wrong page.
+    pub fn verify(page: &[u8], id: u64) -> storage::Result<Kind> {
+        let bad = |error| storage::Error::BadHeader { page: id, error };
+        if !is_formatted(page) {
+            return Err(bad(Error::NotAPage));
+        }
+        let found = compute(page, algorithm(page).map_err(bad)?);
+        if checksum(page) != found {
+            return Err(storage::Error::ChecksumMismatch {
+                page: id,
+                expected: checksum(page),
+                found,
+            });
+        }
+        if version(page) != VERSION {
+            return Err(bad(Error::UnsupportedVersion(version(page))));
+        }
+        let kind = kind(page).map_err(bad)?;
+        if self::id(page) != id {
+            return Err(bad(Error::Misdirected {
+                expected: id,
+                found: self::id(page),
+            }));
+        }
+        Ok(kind)
+    }
+
+    // Like `verify`, for a page format that only ever reads pages of its own
+    // kind.
+    pub fn expect(page: &[u8], id: u64, kind: Kind) -> storage::Result<()> {
+        match verify(page, id)? {
+            found if found == kind => Ok(()),
+            found => Err(storage::Error::BadHeader {
+                page: id,
+                error: Error::UnexpectedKind(found as u8),
+            }),
+        }
+    }
+
+    // Like `verify`, but tries to correct a page that fails verification.
+    // Any header field could be what rotted, including the flag that marks
+    // the page as protected, so a repair is attempted regardless. The repair
+    // only sticks if the corrected page verifies.
+    pub fn repair(page: &mut [u8], id: u64) -> storage::Result<(Kind, Health)> {
+        let error = match verify(page, id) {
+            Ok(kind) => return Ok((kind, Health::Intact)),
+            Err(error) => error,
+        };
+        let mut corrected = page.to_vec();
+        match ecc::decode(&mut corrected) {
+            Ok(n) if n > 0 && is_protected(&corrected) => {
+                let kind = verify(&corrected, id).map_err(|_| error)?;
+                page.copy_from_slice(&corrected);
+                Ok((kind, Health::Repaired(n)))
+            }
+            _ => Err(error),
+        }
+    }
 
-    use crate::dbms::storage::{integrity, page};
+    #[cfg(test)]
+    mod tests {
+        use crate::dbms::storage::page;
+
+        use super::*;
+
+        fn sealed(kind: Kind, id: u64) -> [u8; page::DEFAULT_SIZE] {
+            let mut page = [7u8; page::DEFAULT_SIZE];
+            init(&mut page, kind, id, Algorithm::Crc32c);
+            seal(&mut page);
+            page
+        }
+
+        fn bad_header(page: &[u8; page::DEFAULT_SIZE], id: u64) -> Error {
+            match verify(page, id) {
+                Err(storage::Error::BadHeader { page, error }) if page == id => error,
+                other => panic!("expected bad header, got {other:?}"),
+            }
+        }
+
+        fn is_checksum_mismatch(page: &[u8; page::DEFAULT_SIZE], id: u64) -> bool {
+            matches!(
+                verify(page, id),
+                Err(storage::Error::ChecksumMismatch { page, expected, found })
+                    if page == id && expected != found
+            )
+        }
+
+        #[test]
+        fn init_formats_header_only() {
+            let mut page = [7u8; page::DEFAULT_SIZE];
+            init(&mut page, Kind::Overflow, 42, Algorithm::Xxh3);
+            set_lsn(&mut page, 1234);
+
+            assert_eq!(Ok(Kind::Overflow), kind(&page));
+            assert_eq!(Ok(Algorithm::Xxh3), algorithm(&page));
+            assert_eq!(VERSION, version(&page));
+            assert_eq!(42, id(&page));
+            assert_eq!(1234, lsn(&page));
+            assert_eq!([7u8; page::DEFAULT_SIZE - SIZE], page[SIZE..]);
+        }
+
+        #[test]
+        fn verify_when_intact() {
+            let page = sealed(Kind::Slotted, 9);
+            assert!(matches!(verify(&page, 9), Ok(Kind::Slotted)));
+        }
+
+        #[test]
+        fn verify_with_every_algorithm() {
+            for algorithm in [Algorithm::Crc32c, Algorithm::Crc64, Algorithm::Xxh3] {
+                let mut page = [7u8; page::DEFAULT_SIZE];
+                init(&mut page, Kind::Slotted, 9, algorithm);
+                seal(&mut page);
+                assert!(matches!(verify(&page, 9), Ok(Kind::Slotted)));
+                page[page::DEFAULT_SIZE / 2] ^= 0x10;
+                assert!(is_checksum_mismatch(&page, 9));
+            }
+        }
+
+        fn protected(id: u64) -> [u8; page::DEFAULT_SIZE] {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, Kind::Slotted, id, Algorithm::Crc32c);
+            protect(&mut page);
+            let end = end(&page);
+            for (n, byte) in page[SIZE..end].iter_mut().enumerate() {
+                *byte = (n % 253) as u8;
+            }
+            seal(&mut page);
+            page
+        }
+
+        #[test]
+        fn protect_reserves_end_of_page() {
+            let mut page = sealed(Kind::Slotted, 1);
+            assert_eq!(page::DEFAULT_SIZE, end(&page));
+            protect(&mut page);
+            assert!(is_protected(&page));
+            assert_eq!(page::DEFAULT_SIZE - 1024, end(&page));
+        }
+
+        #[test]
+        fn repair_when_intact() {
+            let mut page = protected(4);
+            assert_eq!(Kind::Slotted, repair(&mut page, 4).unwrap().0);
+            assert_eq!(Health::Intact, repair(&mut page, 4).unwrap().1);
+        }
+
+        #[test]
+        fn repair_corrects_rotten_sector() {
+            let original = protected(4);
+            // Corrupt every sector in turn, the header included. Rotten
+            // parity goes unnoticed, since it is not covered by the checksum.
+            for sector in 0..end(&original) / 512 {
+                let mut page = original;
+                page[sector * 512..(sector + 1) * 512].fill(0);
+                let (kind, health) = repair(&mut page, 4).unwrap();
+                assert_eq!(Kind::Slotted, kind);
+                assert!(matches!(health, Health::Repaired(n) if n > 0));
+                assert_eq!(original, page);
+            }
+        }
+
+        #[test]
+        fn repair_when_beyond_correction() {
+            let mut page = protected(4);
+            page[1000..2000].fill(0);
+            let rotten = page;
+            assert!(is_checksum_mismatch(&page, 4));
+            assert!(repair(&mut page, 4).is_err());
+            assert_eq!(rotten, page);
+        }
+
+        #[test]
+        fn repair_given_unprotected_page() {
+            let mut page = sealed(Kind::Slotted, 4);
+            page[100] ^= 0x01;
+            assert!(repair(&mut page, 4).is_err());
+        }
+
+        #[test]
+        fn repair_given_other_page() {
+            // Misdirected pages are intact, and must not be repaired into
+            // the page that was asked for.
+            let mut page = protected(4);
+            assert_eq!(
+                Error::Misdirected {
+                    expected: 5,
+                    found: 4
+                },
+                match repair(&mut page, 5) {
+                    Err(storage::Error::BadHeader { error, .. }) => error,
+                    other => panic!("expected bad header, got {other:?}"),
+                }
+            );
+        }
+
+        #[test]
+        fn verify_given_other_page() {
+            let page = sealed(Kind::Free, 9);
+            assert_eq!(
+                Error::Misdirected {
+                    expected: 10,
+                    found: 9
+                },
+                bad_header(&page, 10)
+            );
+        }
+
+        #[test]
+        fn expect_given_other_kind() {
+            let page = sealed(Kind::Free, 9);
+            assert!(expect(&page, 9, Kind::Free).is_ok());
+            match expect(&page, 9, Kind::Overflow) {
+                Err(storage::Error::BadHeader { page: 9, error }) => {
+                    assert_eq!(Error::UnexpectedKind(Kind::Free as u8), error)
+                }
+                other => panic!("expected bad header, got {other:?}"),
+            }
+        }
+
+        #[test]
+        fn verify_when_corrupt() {
+            let mut page = sealed(Kind::Slotted, 3);
+            page[page::DEFAULT_SIZE - 1] = !page[page::DEFAULT_SIZE - 1];
+            assert!(is_checksum_mismatch(&page, 3));
+
+            // The header itself is covered by the checksum.
+            let mut page = sealed(Kind::Slotted, 3);
+            set_lsn(&mut page, 5);
+            assert!(is_checksum_mismatch(&page, 3));
+        }
+
+        #[test]
+        fn verify_given_unformatted_page() {
+            assert_eq!(Error::NotAPage, bad_header(&[0u8; page::DEFAULT_SIZE], 0));
+        }
+
+        #[test]
+        fn verify_given_unknown_kind_or_version() {
+            let mut page = sealed(Kind::Slotted, 3);
+            page[KIND] = 0xEE;
+            seal(&mut page);
+            assert_eq!(Error::UnknownKind(0xEE), bad_header(&page, 3));
+
+            // Without knowing the algorithm the checksum cannot be checked.
+            let mut page = sealed(Kind::Slotted, 3);
+            page[ALGORITHM] = 0xEE;
+            assert_eq!(Error::UnknownAlgorithm(0xEE), bad_header(&page, 3));
+
+            let mut page = sealed(Kind::Slotted, 3);
+            page[VERSION_AT..VERSION_AT + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+            seal(&mut page);
+            assert_eq!(Error::UnsupportedVersion(VERSION + 1), bad_header(&page, 3));
+        }
+    }
+}
+
+pub mod slot {
+    use crate::dbms::storage::{self, integrity::Algorithm, page::header};
 
     type Index = u16;
 
-    const CRC_POLY: u8 = 0x07;
+    pub type Id = u16;
+
+    // The slot count lives right after the page header and is followed by
+    // the slot directory, which grows towards the end of the page. Record data
+    // is placed at the end of the page and grows towards the directory.
+    const COUNT: usize = header::SIZE;
+    const DIRECTORY: usize = COUNT + 2;
+
+    const ALGORITHM: Algorithm = Algorithm::Crc32c;
+
+    #[derive(Debug, PartialEq)]
+    pub enum Error {
+        PageFull,
+        NoSuchSlot,
+    }
 
     #[derive(Default, Copy, Clone)]
     struct Block {
@@ -54,40 +619,237 @@ pub mod slot {
         fn new(offset: Index, size: u16) -> Self {
             Self { offset, size }
         }
+
+        // Deleted slots are kept as tombstones so that the ids of the slots
+        // after them stay the same. No record can start at offset zero since
+        // that is where the page header is stored.
+        fn is_tombstone(&self) -> bool {
+            self.offset == 0
+        }
+
+        fn range(&self) -> std::ops::Range<usize> {
+            self.offset as usize..self.offset as usize + self.size as usize
+        }
+    }
+
+    // Where the record data ends. Offsets have to fit in an index, which
+    // leaves the last byte of a 64 KB page unused.
+    fn end(page: &[u8]) -> usize {
+        header::end(page).min(Index::MAX as usize)
     }
 
-    fn read_blocks(page: &[u8; super::SIZE]) -> [Block; 5] {
-        const OFFSET: usize = 3;
-        let mut blocks = [Block::default(); 5];
-        for (index, block) in blocks.iter_mut().enumerate() {
-            let mut base = OFFSET + Block::SIZE * index;
-            block.size = u16::from_le_bytes(page[base..base + 2].try_into().unwrap());
-            base += 2;
-            block.offset = u16::from_le_bytes(page[base..base + 2].try_into().unwrap());
+    fn count(page: &[u8]) -> usize {
+        u16::from_le_bytes(page[COUNT..COUNT + 2].try_into().unwrap()) as usize
+    }
+
+    fn set_count(page: &mut [u8], count: usize) {
+        page[COUNT..COUNT + 2].copy_from_slice(&(count as u16).to_le_bytes());
+    }
+
+    fn read_block(page: &[u8], index: usize) -> Block {
+        let base = DIRECTORY + Block::SIZE * index;
+        Block {
+            size: u16::from_le_bytes(page[base..base + 2].try_into().unwrap()),
+            offset: u16::from_le_bytes(page[base + 2..base + 4].try_into().unwrap()),
         }
-        blocks
     }
 
-    fn write_block(page: &mut [u8; super::SIZE], index: usize, block: &Block) {
-        if index > 4 {
+    fn read_blocks(page: &[u8]) -> Vec<Block> {
+        (0..count(page))
+            .map(|index| read_block(page, index))
+            .collect()
+    }
+
+    fn put_block(page: &mut [u8], index: usize, block: &Block) {
+        if index >= count(page) {
             panic!("illegal block index {}", index);
         }
-        let offset = 3 + Block::SIZE * index;
+        let offset = DIRECTORY + Block::SIZE * index;
         page[offset..offset + 2].copy_from_slice(&block.size.to_le_bytes());
         page[offset + 2..offset + 4].copy_from_slice(&block.offset.to_le_bytes());
+    }
+
+    fn write_block(page: &mut [u8], index: usize, block: &Block) {
+        put_block(page, index, block);
         write_checksum(page);
     }
 
-    fn write_checksum(page: &mut [u8; super::SIZE]) {
-        page[0] = integrity::crc(CRC_POLY, &page[1..]);
+    fn write_checksum(page: &mut [u8]) {
+        header::seal(page);
+    }
+
+    // Checks the header of the page, which has to be that of a slot page.
+    pub fn verify_checksum(page: &[u8], id: u64) -> storage::Result<()> {
+        header::expect(page, id, header::Kind::Slotted)
+    }
+
+    // The end of the directory and the start of the record data. Everything
+    // in between is free.
+    fn bounds(page: &[u8], blocks: &[Block]) -> (usize, usize) {
+        let lower = DIRECTORY + Block::SIZE * blocks.len();
+        let upper = blocks
+            .iter()
+            .filter(|block| !block.is_tombstone() && block.size > 0)
+            .map(|block| block.offset as usize)
+            .min()
+            .unwrap_or(end(page));
+        (lower, upper)
     }
 
-    pub fn verify_checksum(page: &[u8; super::SIZE]) -> Result<(), integrity::Error> {
-        if page[0] == integrity::crc(CRC_POLY, &page[1..]) {
-            Ok(())
+    // Carves out space for a record between the directory and the record
+    // data. The directory grows by one entry unless `grow` is false.
+    fn reserve(page: &[u8], blocks: &[Block], size: usize, grow: bool) -> Option<Block> {
+        let (lower, upper) = bounds(page, blocks);
+        let lower = if grow { lower + Block::SIZE } else { lower };
+        if lower > upper || upper - lower < size {
+            return None;
+        }
+        Some(Block::new((upper - size) as Index, size as u16))
+    }
+
+    // Bytes taken up by neither the directory nor live records, including the
+    // holes that deletes and updates leave behind in the record data.
+    fn unused(page: &[u8], blocks: &[Block]) -> usize {
+        let used: usize = blocks
+            .iter()
+            .filter(|block| !block.is_tombstone())
+            .map(|block| block.size as usize)
+            .sum();
+        end(page) - DIRECTORY - Block::SIZE * blocks.len() - used
+    }
+
+    // Like `reserve`, but falls back to compacting the page when there is
+    // enough free space in total and it is just too fragmented.
+    fn make_room(page: &mut [u8], size: usize, grow: bool) -> Option<Block> {
+        let blocks = read_blocks(page);
+        if let Some(block) = reserve(page, &blocks, size, grow) {
+            return Some(block);
+        }
+        let needed = if grow { size + Block::SIZE } else { size };
+        if unused(page, &blocks) < needed {
+            return None;
+        }
+        compact(page);
+        reserve(page, &read_blocks(page), size, grow)
+    }
+
+    fn live(page: &[u8], slot: Id) -> Result<Block, Error> {
+        if slot as usize >= count(page) {
+            return Err(Error::NoSuchSlot);
+        }
+        let block = read_block(page, slot as usize);
+        if block.is_tombstone() {
+            return Err(Error::NoSuchSlot);
+        }
+        Ok(block)
+    }
+
+    pub fn init(page: &mut [u8], id: u64) {
+        page.fill(0);
+        header::init(page, header::Kind::Slotted, id, ALGORITHM);
+        write_checksum(page);
+    }
+
+    pub fn insert(page: &mut [u8], record: &[u8]) -> Result<Id, Error> {
+        let blocks = read_blocks(page);
+        let reusable = blocks.iter().position(Block::is_tombstone);
+        let block = make_room(page, record.len(), reusable.is_none()).ok_or(Error::PageFull)?;
+        let index = match reusable {
+            Some(index) => index,
+            None => {
+                set_count(page, blocks.len() + 1);
+                blocks.len()
+            }
+        };
+        page[block.range()].copy_from_slice(record);
+        put_block(page, index, &block);
+        write_checksum(page);
+        Ok(index as Id)
+    }
+
+    pub fn read(page: &[u8], slot: Id) -> Result<&[u8], Error> {
+        let block = live(page, slot)?;
+        Ok(&page[block.range()])
+    }
+
+    // Overwrites the record in place when it does not grow, and moves it
+    // elsewhere in the page otherwise. The slot id stays the same either way.
+    pub fn update(page: &mut [u8], slot: Id, record: &[u8]) -> Result<(), Error> {
+        let block = live(page, slot)?;
+        let block = if record.len() <= block.size as usize {
+            Block::new(block.offset, record.len() as u16)
         } else {
-            Err(integrity::Error::BadChecksum)
+            // The old location is free once the record has moved.
+            let mut blocks = read_blocks(page);
+            blocks[slot as usize] = Block::default();
+            if unused(page, &blocks) < record.len() {
+                return Err(Error::PageFull);
+            }
+            put_block(page, slot as usize, &Block::default());
+            make_room(page, record.len(), false).expect("record fits once page is compacted")
+        };
+        page[block.range()].copy_from_slice(record);
+        put_block(page, slot as usize, &block);
+        write_checksum(page);
+        Ok(())
+    }
+
+    pub fn delete(page: &mut [u8], slot: Id) -> Result<(), Error> {
+        live(page, slot)?;
+        put_block(page, slot as usize, &Block::default());
+        // Tombstones at the end of the directory protect no slot ids, so the
+        // directory can shrink past them.
+        let blocks = read_blocks(page);
+        let count = blocks
+            .iter()
+            .rposition(|block| !block.is_tombstone())
+            .map_or(0, |index| index + 1);
+        page[DIRECTORY + Block::SIZE * count..DIRECTORY + Block::SIZE * blocks.len()].fill(0);
+        set_count(page, count);
+        write_checksum(page);
+        Ok(())
+    }
+
+    // The size of the largest record that can be inserted into the page,
+    // provided that it is compacted first.
+    pub fn free_space(page: &[u8]) -> usize {
+        let blocks = read_blocks(page);
+        let directory = if blocks.iter().any(Block::is_tombstone) {
+            0
+        } else {
+            Block::SIZE
+        };
+        unused(page, &blocks).saturating_sub(directory)
+    }
+
+    // Moves all live records to the end of the page so that the free space
+    // forms a single gap between them and the directory. Slot ids do not
+    // change, and records keep their relative order.
+    pub fn compact(page: &mut [u8]) {
+        let original = page.to_vec();
+        let blocks = read_blocks(page);
+        let mut live: Vec<usize> = (0..blocks.len())
+            .filter(|index| !blocks[*index].is_tombstone())
+            .collect();
+        live.sort_by_key(|index| std::cmp::Reverse(blocks[*index].offset));
+
+        let mut upper = end(page);
+        for index in live {
+            let block = blocks[index];
+            upper -= block.size as usize;
+            page[upper..upper + block.size as usize].copy_from_slice(&original[block.range()]);
+            put_block(page, index, &Block::new(upper as Index, block.size));
         }
+        page[DIRECTORY + Block::SIZE * blocks.len()..upper].fill(0);
+        write_checksum(page);
+    }
+
+    pub fn records(page: &[u8]) -> impl Iterator<Item = (Id, &[u8])> {
+        read_blocks(page)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, block)| !block.is_tombstone())
+            .map(|(index, block)| (index as Id, &page[block.range()]))
     }
 
     #[cfg(test)]
@@ -98,16 +860,17 @@ pub mod slot {
 
         #[test]
         fn read_blocks_when_partially_filled() {
-            let mut page = [0u8; page::SIZE];
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            page[COUNT..COUNT + 2].copy_from_slice(&3u16.to_le_bytes());
             // Medium sized values.
-            page[3..5].copy_from_slice(&1265u16.to_le_bytes());
-            page[5..7].copy_from_slice(&4032u16.to_le_bytes());
+            page[DIRECTORY..DIRECTORY + 2].copy_from_slice(&1265u16.to_le_bytes());
+            page[DIRECTORY + 2..DIRECTORY + 4].copy_from_slice(&4032u16.to_le_bytes());
             // Small sized values.
-            page[7..9].copy_from_slice(&45u16.to_le_bytes());
-            page[9..11].copy_from_slice(&128u16.to_le_bytes());
+            page[DIRECTORY + 4..DIRECTORY + 6].copy_from_slice(&45u16.to_le_bytes());
+            page[DIRECTORY + 6..DIRECTORY + 8].copy_from_slice(&128u16.to_le_bytes());
             // Max sized values.
-            page[11..13].copy_from_slice(&u16::MAX.to_le_bytes());
-            page[13..15].copy_from_slice(&u16::MAX.to_le_bytes());
+            page[DIRECTORY + 8..DIRECTORY + 10].copy_from_slice(&u16::MAX.to_le_bytes());
+            page[DIRECTORY + 10..DIRECTORY + 12].copy_from_slice(&u16::MAX.to_le_bytes());
 
             let blocks = read_blocks(&page);
             assert_eq!(blocks[0].size, 1265);
@@ -119,46 +882,40 @@ pub mod slot {
             assert_eq!(blocks[2].size, u16::MAX);
             assert_eq!(blocks[2].offset, u16::MAX);
 
-            // Remaining blocks should be zero'ed out.
-            for block in blocks[3..].iter() {
-                assert_eq!(block.size, 0);
-                assert_eq!(block.offset, 0);
-            }
+            // Blocks past the slot count are not part of the directory.
+            assert_eq!(blocks.len(), 3);
 
             // Single block.
-            let mut page = [0u8; page::SIZE];
-            page[3..5].copy_from_slice(&1265u16.to_le_bytes());
-            page[5..7].copy_from_slice(&4032u16.to_le_bytes());
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            page[COUNT..COUNT + 2].copy_from_slice(&1u16.to_le_bytes());
+            page[DIRECTORY..DIRECTORY + 2].copy_from_slice(&1265u16.to_le_bytes());
+            page[DIRECTORY + 2..DIRECTORY + 4].copy_from_slice(&4032u16.to_le_bytes());
 
             let blocks = read_blocks(&page);
             assert_eq!(blocks[0].size, 1265);
             assert_eq!(blocks[0].offset, 4032);
-
-            // Remaining blocks should be zero'ed out.
-            for block in blocks[1..].iter() {
-                assert_eq!(block.size, 0);
-                assert_eq!(block.offset, 0);
-            }
+            assert_eq!(blocks.len(), 1);
         }
 
         #[test]
         fn read_blocks_when_filled() {
-            let mut page = [0u8; page::SIZE];
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            page[COUNT..COUNT + 2].copy_from_slice(&5u16.to_le_bytes());
 
-            page[3..5].copy_from_slice(&1265u16.to_le_bytes());
-            page[5..7].copy_from_slice(&4032u16.to_le_bytes());
+            page[DIRECTORY..DIRECTORY + 2].copy_from_slice(&1265u16.to_le_bytes());
+            page[DIRECTORY + 2..DIRECTORY + 4].copy_from_slice(&4032u16.to_le_bytes());
 
-            page[7..9].copy_from_slice(&45u16.to_le_bytes());
-            page[9..11].copy_from_slice(&128u16.to_le_bytes());
+            page[DIRECTORY + 4..DIRECTORY + 6].copy_from_slice(&45u16.to_le_bytes());
+            page[DIRECTORY + 6..DIRECTORY + 8].copy_from_slice(&128u16.to_le_bytes());
 
-            page[11..13].copy_from_slice(&u16::MAX.to_le_bytes());
-            page[13..15].copy_from_slice(&u16::MAX.to_le_bytes());
+            page[DIRECTORY + 8..DIRECTORY + 10].copy_from_slice(&u16::MAX.to_le_bytes());
+            page[DIRECTORY + 10..DIRECTORY + 12].copy_from_slice(&u16::MAX.to_le_bytes());
 
-            page[15..17].copy_from_slice(&34444u16.to_le_bytes());
-            page[17..19].copy_from_slice(&12334u16.to_le_bytes());
+            page[DIRECTORY + 12..DIRECTORY + 14].copy_from_slice(&34444u16.to_le_bytes());
+            page[DIRECTORY + 14..DIRECTORY + 16].copy_from_slice(&12334u16.to_le_bytes());
 
-            page[19..21].copy_from_slice(&21123u16.to_le_bytes());
-            page[21..23].copy_from_slice(&0u16.to_le_bytes());
+            page[DIRECTORY + 16..DIRECTORY + 18].copy_from_slice(&21123u16.to_le_bytes());
+            page[DIRECTORY + 18..DIRECTORY + 20].copy_from_slice(&0u16.to_le_bytes());
 
             let blocks = read_blocks(&page);
             assert_eq!(blocks[0].size, 1265);
@@ -179,159 +936,647 @@ pub mod slot {
 
         #[test]
         fn read_blocks_when_empty() {
-            let mut page = [0u8; page::SIZE];
-            for block in read_blocks(&page) {
-                assert_eq!(block.size, 0);
-                assert_eq!(block.offset, 0);
-            }
+            let page = [0u8; page::DEFAULT_SIZE];
+            assert!(read_blocks(&page).is_empty());
         }
 
         #[test]
         fn write_block_given_legal_index() {
-            let mut page = [0u8; page::SIZE];
-            page[3..5].copy_from_slice(&9999u16.to_le_bytes());
-            page[5..7].copy_from_slice(&1234u16.to_le_bytes());
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            page[COUNT..COUNT + 2].copy_from_slice(&5u16.to_le_bytes());
+            page[DIRECTORY..DIRECTORY + 2].copy_from_slice(&9999u16.to_le_bytes());
+            page[DIRECTORY + 2..DIRECTORY + 4].copy_from_slice(&1234u16.to_le_bytes());
             write_block(&mut page, 1, &Block::new(1234, 1034));
 
-            assert_eq!(page[0], integrity::crc(CRC_POLY, &page[1..]));
-            assert_eq!(page[3..5], 9999u16.to_le_bytes());
-            assert_eq!(page[5..7], 1234u16.to_le_bytes());
-            assert_eq!(page[7..9], 1034u16.to_le_bytes());
-            assert_eq!(page[9..11], 1234u16.to_le_bytes());
-            assert_eq!(page[11..], [0u8; page::SIZE - 11]);
+            assert!(verify_checksum(&page, 0).is_ok());
+            assert_eq!(page[DIRECTORY..DIRECTORY + 2], 9999u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 2..DIRECTORY + 4], 1234u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 4..DIRECTORY + 6], 1034u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 6..DIRECTORY + 8], 1234u16.to_le_bytes());
+            assert_eq!(
+                page[DIRECTORY + 8..],
+                [0u8; page::DEFAULT_SIZE - DIRECTORY - 8]
+            );
 
             write_block(&mut page, 3, &Block::new(8011, 65535));
-            assert_eq!(page[0], integrity::crc(CRC_POLY, &page[1..]));
-            assert_eq!(page[3..5], 9999u16.to_le_bytes());
-            assert_eq!(page[5..7], 1234u16.to_le_bytes());
-            assert_eq!(page[7..9], 1034u16.to_le_bytes());
-            assert_eq!(page[9..11], 1234u16.to_le_bytes());
-            assert_eq!(page[11..13], 0u16.to_le_bytes());
-            assert_eq!(page[13..15], 0u16.to_le_bytes());
-            assert_eq!(page[15..17], 65535u16.to_le_bytes());
-            assert_eq!(page[17..19], 8011u16.to_le_bytes());
-            assert_eq!(page[19..], [0u8; page::SIZE - 19]);
+            assert!(verify_checksum(&page, 0).is_ok());
+            assert_eq!(page[DIRECTORY..DIRECTORY + 2], 9999u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 2..DIRECTORY + 4], 1234u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 4..DIRECTORY + 6], 1034u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 6..DIRECTORY + 8], 1234u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 8..DIRECTORY + 10], 0u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 10..DIRECTORY + 12], 0u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 12..DIRECTORY + 14], 65535u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 14..DIRECTORY + 16], 8011u16.to_le_bytes());
+            assert_eq!(
+                page[DIRECTORY + 16..],
+                [0u8; page::DEFAULT_SIZE - DIRECTORY - 16]
+            );
 
             write_block(&mut page, 3, &Block::default());
-            assert_eq!(page[0], integrity::crc(CRC_POLY, &page[1..]));
-            assert_eq!(page[3..5], 9999u16.to_le_bytes());
-            assert_eq!(page[5..7], 1234u16.to_le_bytes());
-            assert_eq!(page[7..9], 1034u16.to_le_bytes());
-            assert_eq!(page[9..11], 1234u16.to_le_bytes());
-            assert_eq!(page[11..], [0u8; page::SIZE - 11]);
+            assert!(verify_checksum(&page, 0).is_ok());
+            assert_eq!(page[DIRECTORY..DIRECTORY + 2], 9999u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 2..DIRECTORY + 4], 1234u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 4..DIRECTORY + 6], 1034u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 6..DIRECTORY + 8], 1234u16.to_le_bytes());
+            assert_eq!(
+                page[DIRECTORY + 8..],
+                [0u8; page::DEFAULT_SIZE - DIRECTORY - 8]
+            );
 
             write_block(&mut page, 4, &Block::new(2222, 2121));
-            assert_eq!(page[0], integrity::crc(CRC_POLY, &page[1..]));
-            assert_eq!(page[3..5], 9999u16.to_le_bytes());
-            assert_eq!(page[5..7], 1234u16.to_le_bytes());
-            assert_eq!(page[7..9], 1034u16.to_le_bytes());
-            assert_eq!(page[9..11], 1234u16.to_le_bytes());
-            assert_eq!(page[11..13], 0u16.to_le_bytes());
-            assert_eq!(page[13..15], 0u16.to_le_bytes());
-            assert_eq!(page[15..17], 0u16.to_le_bytes());
-            assert_eq!(page[17..19], 0u16.to_le_bytes());
-            assert_eq!(page[19..21], 2121u16.to_le_bytes());
-            assert_eq!(page[21..23], 2222u16.to_le_bytes());
-            assert_eq!(page[23..], [0u8; page::SIZE - 23]);
+            assert!(verify_checksum(&page, 0).is_ok());
+            assert_eq!(page[DIRECTORY..DIRECTORY + 2], 9999u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 2..DIRECTORY + 4], 1234u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 4..DIRECTORY + 6], 1034u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 6..DIRECTORY + 8], 1234u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 8..DIRECTORY + 10], 0u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 10..DIRECTORY + 12], 0u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 12..DIRECTORY + 14], 0u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 14..DIRECTORY + 16], 0u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 16..DIRECTORY + 18], 2121u16.to_le_bytes());
+            assert_eq!(page[DIRECTORY + 18..DIRECTORY + 20], 2222u16.to_le_bytes());
+            assert_eq!(
+                page[DIRECTORY + 20..],
+                [0u8; page::DEFAULT_SIZE - DIRECTORY - 20]
+            );
         }
 
         #[test]
         #[should_panic = "illegal block index 5"]
         fn write_block_given_illegal_index() {
-            let mut page = [0u8; page::SIZE];
+            let mut page = [0u8; page::DEFAULT_SIZE];
             write_block(&mut page, 5, &Block::default());
         }
 
         #[test]
         #[should_panic = "illegal block index 1100"]
         fn write_block_given_very_illegal_index() {
-            let mut page = [0u8; page::SIZE];
+            let mut page = [0u8; page::DEFAULT_SIZE];
             write_block(&mut page, 1100, &Block::default());
         }
+
+        #[test]
+        fn init_clears_page() {
+            let mut page = [7u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            assert_eq!(0, count(&page));
+            assert_eq!(
+                page[header::SIZE..],
+                [0u8; page::DEFAULT_SIZE - header::SIZE]
+            );
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        #[test]
+        fn verify_checksum_when_corrupt() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 7);
+            insert(&mut page, b"record").unwrap();
+            page[page::DEFAULT_SIZE - 1] ^= 0x01;
+            match verify_checksum(&page, 7) {
+                Err(storage::Error::ChecksumMismatch {
+                    page: 7,
+                    expected,
+                    found,
+                }) => assert_ne!(expected, found),
+                other => panic!("allowed corrupt page, got {other:?}"),
+            }
+        }
+
+        #[test]
+        fn verify_checksum_given_other_page() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 7);
+            assert!(matches!(
+                verify_checksum(&page, 8),
+                Err(storage::Error::BadHeader {
+                    page: 8,
+                    error: header::Error::Misdirected { .. }
+                })
+            ));
+
+            header::init(&mut page, header::Kind::Overflow, 7, ALGORITHM);
+            header::seal(&mut page);
+            assert!(matches!(
+                verify_checksum(&page, 7),
+                Err(storage::Error::BadHeader {
+                    page: 7,
+                    error: header::Error::UnexpectedKind(4)
+                })
+            ));
+        }
+
+        #[test]
+        fn insert_places_records_from_end_of_page() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            assert_eq!(Ok(0), insert(&mut page, b"first"));
+            assert_eq!(Ok(1), insert(&mut page, b"second"));
+            assert_eq!(Ok(2), insert(&mut page, b""));
+
+            assert_eq!(page[page::DEFAULT_SIZE - 5..], *b"first");
+            assert_eq!(
+                page[page::DEFAULT_SIZE - 11..page::DEFAULT_SIZE - 5],
+                *b"second"
+            );
+            assert_eq!(Ok(&b"first"[..]), read(&page, 0));
+            assert_eq!(Ok(&b"second"[..]), read(&page, 1));
+            assert_eq!(Ok(&b""[..]), read(&page, 2));
+            assert_eq!(3, count(&page));
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        #[test]
+        fn insert_when_page_is_full() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            let record = [1u8; page::DEFAULT_SIZE - DIRECTORY - Block::SIZE];
+            assert_eq!(
+                Err(Error::PageFull),
+                insert(&mut page, &[1u8; page::DEFAULT_SIZE])
+            );
+            assert_eq!(Ok(0), insert(&mut page, &record));
+            assert_eq!(0, free_space(&page));
+            assert_eq!(Err(Error::PageFull), insert(&mut page, b""));
+            assert_eq!(1, count(&page));
+        }
+
+        #[test]
+        fn insert_reuses_tombstones() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            for record in [b"a", b"b", b"c"] {
+                insert(&mut page, record).unwrap();
+            }
+            delete(&mut page, 1).unwrap();
+            assert_eq!(Ok(1), insert(&mut page, b"d"));
+            assert_eq!(Ok(3), insert(&mut page, b"e"));
+            assert_eq!(Ok(&b"d"[..]), read(&page, 1));
+        }
+
+        #[test]
+        fn read_given_missing_slot() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            assert_eq!(Err(Error::NoSuchSlot), read(&page, 0));
+            insert(&mut page, b"a").unwrap();
+            insert(&mut page, b"b").unwrap();
+            delete(&mut page, 0).unwrap();
+            assert_eq!(Err(Error::NoSuchSlot), read(&page, 0));
+            assert_eq!(Err(Error::NoSuchSlot), read(&page, 2));
+            assert_eq!(Err(Error::NoSuchSlot), update(&mut page, 0, b"c"));
+            assert_eq!(Err(Error::NoSuchSlot), delete(&mut page, 0));
+        }
+
+        #[test]
+        fn update_in_place_when_record_shrinks() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            insert(&mut page, b"longer").unwrap();
+            let before = read_block(&page, 0);
+            update(&mut page, 0, b"short").unwrap();
+            let after = read_block(&page, 0);
+
+            assert_eq!(before.offset, after.offset);
+            assert_eq!(5, after.size);
+            assert_eq!(Ok(&b"short"[..]), read(&page, 0));
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        #[test]
+        fn update_relocates_when_record_grows() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            insert(&mut page, b"a").unwrap();
+            insert(&mut page, b"b").unwrap();
+            update(&mut page, 0, b"grown").unwrap();
+
+            assert_eq!(Ok(&b"grown"[..]), read(&page, 0));
+            assert_eq!(Ok(&b"b"[..]), read(&page, 1));
+            assert_eq!(page::DEFAULT_SIZE - 7, read_block(&page, 0).offset as usize);
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        #[test]
+        fn update_when_page_is_full() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            insert(&mut page, b"a").unwrap();
+            let record = vec![2u8; free_space(&page)];
+            insert(&mut page, &record).unwrap();
+
+            assert_eq!(Err(Error::PageFull), update(&mut page, 0, b"ab"));
+            assert_eq!(Ok(&b"a"[..]), read(&page, 0));
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        #[test]
+        fn delete_leaves_tombstone() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            insert(&mut page, b"a").unwrap();
+            insert(&mut page, b"b").unwrap();
+            delete(&mut page, 0).unwrap();
+
+            assert_eq!(2, count(&page));
+            assert!(read_block(&page, 0).is_tombstone());
+            assert_eq!(Ok(&b"b"[..]), read(&page, 1));
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        #[test]
+        fn delete_shrinks_directory_past_trailing_tombstones() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            for record in [b"a", b"b", b"c"] {
+                insert(&mut page, record).unwrap();
+            }
+            delete(&mut page, 1).unwrap();
+            delete(&mut page, 2).unwrap();
+            assert_eq!(1, count(&page));
+            delete(&mut page, 0).unwrap();
+            assert_eq!(0, count(&page));
+            assert_eq!(
+                page::DEFAULT_SIZE - DIRECTORY - Block::SIZE,
+                free_space(&page)
+            );
+        }
+
+        #[test]
+        fn free_space_accounts_for_directory() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            assert_eq!(
+                page::DEFAULT_SIZE - DIRECTORY - Block::SIZE,
+                free_space(&page)
+            );
+            insert(&mut page, &[0u8; 100]).unwrap();
+            assert_eq!(
+                page::DEFAULT_SIZE - DIRECTORY - 2 * Block::SIZE - 100,
+                free_space(&page)
+            );
+            insert(&mut page, &[0u8; 100]).unwrap();
+            delete(&mut page, 0).unwrap();
+            // The tombstone can be reused, so no new directory entry is
+            // needed, and the hole it left behind counts as free.
+            assert_eq!(
+                page::DEFAULT_SIZE - DIRECTORY - 2 * Block::SIZE - 100,
+                free_space(&page)
+            );
+        }
+
+        #[test]
+        fn compact_merges_holes() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            for record in [b"aaaa", b"bbbb", b"cccc", b"dddd"] {
+                insert(&mut page, record).unwrap();
+            }
+            insert(&mut page, b"").unwrap();
+            delete(&mut page, 0).unwrap();
+            delete(&mut page, 2).unwrap();
+            update(&mut page, 3, b"dd").unwrap();
+            compact(&mut page);
+
+            assert_eq!(Ok(&b"bbbb"[..]), read(&page, 1));
+            assert_eq!(Ok(&b"dd"[..]), read(&page, 3));
+            assert_eq!(Ok(&b""[..]), read(&page, 4));
+            assert_eq!(page::DEFAULT_SIZE - 4, read_block(&page, 1).offset as usize);
+            assert_eq!(page::DEFAULT_SIZE - 6, read_block(&page, 3).offset as usize);
+            assert!(read_block(&page, 0).is_tombstone());
+            assert!(read_block(&page, 2).is_tombstone());
+            let lower = DIRECTORY + Block::SIZE * 5;
+            assert!(
+                page[lower..page::DEFAULT_SIZE - 6]
+                    .iter()
+                    .all(|byte| *byte == 0)
+            );
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        #[test]
+        fn compact_when_empty() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            compact(&mut page);
+            assert_eq!(
+                page[header::SIZE..],
+                [0u8; page::DEFAULT_SIZE - header::SIZE]
+            );
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        // Fills the page with 1000 byte records and deletes every other one,
+        // leaving less than 1000 bytes between the directory and the data.
+        fn fragmented() -> [u8; page::DEFAULT_SIZE] {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            for n in 0..8 {
+                insert(&mut page, &[n as u8; 1000]).unwrap();
+            }
+            for slot in [1, 3, 5] {
+                delete(&mut page, slot).unwrap();
+            }
+            page
+        }
+
+        #[test]
+        fn insert_compacts_when_fragmented() {
+            let mut page = fragmented();
+            assert_eq!(Ok(1), insert(&mut page, &[9u8; 2500]));
+
+            assert_eq!(Ok(&[9u8; 2500][..]), read(&page, 1));
+            for slot in [0, 2, 4, 6, 7] {
+                assert_eq!(Ok(&[slot as u8; 1000][..]), read(&page, slot));
+            }
+            assert!(verify_checksum(&page, 0).is_ok());
+            assert_eq!(Err(Error::PageFull), insert(&mut page, &[9u8; 1000]));
+        }
+
+        #[test]
+        fn update_compacts_when_fragmented() {
+            let mut page = fragmented();
+            update(&mut page, 2, &[9u8; 3500]).unwrap();
+
+            assert_eq!(Ok(&[9u8; 3500][..]), read(&page, 2));
+            for slot in [0, 4, 6, 7] {
+                assert_eq!(Ok(&[slot as u8; 1000][..]), read(&page, slot));
+            }
+            assert!(verify_checksum(&page, 0).is_ok());
+        }
+
+        #[test]
+        fn update_when_fragmented_page_is_full() {
+            let mut page = fragmented();
+            let before = page;
+            assert_eq!(Err(Error::PageFull), update(&mut page, 2, &[9u8; 4500]));
+            assert_eq!(before, page);
+        }
+
+        #[test]
+        fn insert_into_largest_page() {
+            let mut page = vec![0u8; page::MAX_SIZE];
+            init(&mut page, 0);
+            let record = vec![1u8; page::MAX_SIZE - 1 - DIRECTORY - 2 * Block::SIZE];
+            assert_eq!(Ok(0), insert(&mut page, &record));
+            // An empty record is not mistaken for a tombstone.
+            assert_eq!(Ok(1), insert(&mut page, &[]));
+            assert_eq!(0, free_space(&page));
+            assert_eq!(Ok(&record[..]), read(&page, 0));
+            assert_eq!(Ok(&[][..]), read(&page, 1));
+        }
+
+        #[test]
+        fn records_skips_tombstones() {
+            let mut page = [0u8; page::DEFAULT_SIZE];
+            init(&mut page, 0);
+            for record in [b"a", b"b", b"c"] {
+                insert(&mut page, record).unwrap();
+            }
+            delete(&mut page, 1).unwrap();
+            assert_eq!(
+                vec![(0, &b"a"[..]), (2, &b"c"[..])],
+                records(&page).collect::<Vec<_>>()
+            );
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::dbms::storage::ephemeral;
+    use crate::dbms::storage::{ephemeral, integrity::Algorithm, store::Memory};
 
     use super::*;
-    use std::io::Write;
 
     #[test]
     fn read_seeks_multiple_of_page_size() {
         ephemeral::file!(tmp {
-            let mut write_buffer = [0u8; SIZE * 2];
-            write_buffer[0..SIZE].copy_from_slice(&[5u8; SIZE]);
-            write_buffer[SIZE..SIZE*2].copy_from_slice(&[9u8; SIZE]);
-            tmp.borrow_mut().write_all(&write_buffer).unwrap();
+            tmp.borrow_mut().write_page(0, &[5u8; DEFAULT_SIZE]).unwrap();
+            tmp.borrow_mut().write_page(1, &[9u8; DEFAULT_SIZE]).unwrap();
 
-            let mut read_buffer = [0u8; SIZE];
+            let mut read_buffer = [0u8; DEFAULT_SIZE];
             read(tmp.borrow_mut(), 0, &mut read_buffer).unwrap();
-            assert_eq!(read_buffer, [5u8; SIZE]);
+            assert_eq!(read_buffer, [5u8; DEFAULT_SIZE]);
             read(tmp.borrow_mut(), 1, &mut read_buffer).unwrap();
-            assert_eq!(read_buffer, [9u8; SIZE]);
+            assert_eq!(read_buffer, [9u8; DEFAULT_SIZE]);
         });
     }
 
     #[test]
     fn read_given_distant_page() {
         ephemeral::file!(tmp {
-            let mut read_buffer = [0u8; SIZE];
+            let mut read_buffer = [0u8; DEFAULT_SIZE];
             match read(tmp.borrow_mut(), 0, &mut read_buffer) {
                 Ok(_) => panic!("allowed reading distant page"),
-                Err(error) => assert_eq!("tried to read distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 0, end: 0 }), "{error}"),
             }
         });
         ephemeral::file!(tmp {
-            write(tmp.borrow_mut(), 0, &[0u8; SIZE]).unwrap();
-            let mut read_buffer = [0u8; SIZE];
+            write(tmp.borrow_mut(), 0, &[0u8; DEFAULT_SIZE]).unwrap();
+            let mut read_buffer = [0u8; DEFAULT_SIZE];
             match read(tmp.borrow_mut(), 1, &mut read_buffer) {
                 Ok(_) => panic!("allowed reading distant page"),
-                Err(error) => assert_eq!("tried to read distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 1, end: 1 }), "{error}"),
             }
         });
         ephemeral::file!(tmp {
-            write(tmp.borrow_mut(), 0, &[0u8; SIZE]).unwrap();
-            let mut read_buffer = [0u8; SIZE];
+            write(tmp.borrow_mut(), 0, &[0u8; DEFAULT_SIZE]).unwrap();
+            let mut read_buffer = [0u8; DEFAULT_SIZE];
             match read(tmp.borrow_mut(), 4, &mut read_buffer) {
                 Ok(_) => panic!("allowed reading distant page"),
-                Err(error) => assert_eq!("tried to read distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 4, end: 1 }), "{error}"),
             }
         });
     }
 
+    #[test]
+    fn read_given_page_past_addressable_range() {
+        let mut store = Memory::from(vec![0u8; DEFAULT_SIZE]);
+        let mut read_buffer = [0u8; DEFAULT_SIZE];
+        match read(&mut store, u64::MAX / 2, &mut read_buffer) {
+            Ok(_) => panic!("allowed reading page past addressable range"),
+            Err(error) => assert!(matches!(error, Error::OutOfRange { end: 1, .. }), "{error}"),
+        }
+    }
+
+    #[test]
+    fn read_and_write_given_buffer_of_other_size() {
+        let mut store = Memory::from(vec![3u8; 2 * DEFAULT_SIZE]);
+        let mut short = [0u8; 16];
+        let mut long = [0u8; 2 * DEFAULT_SIZE];
+        for result in [
+            read(&mut store, 0, &mut short),
+            read(&mut store, 0, &mut long),
+            write(&mut store, 1, &short),
+            write(&mut store, 1, &long),
+        ] {
+            match result {
+                Ok(_) => panic!("allowed buffer that does not hold one page"),
+                Err(error) => assert!(
+                    matches!(
+                        error,
+                        Error::BufferSizeMismatch {
+                            expected: DEFAULT_SIZE,
+                            ..
+                        }
+                    ),
+                    "{error}"
+                ),
+            }
+        }
+
+        let mut page = [0u8; DEFAULT_SIZE];
+        match read_range(&mut store, 0, &mut [&mut page, &mut short]) {
+            Ok(_) => panic!("allowed range with buffer that does not hold one page"),
+            Err(error) => assert!(
+                matches!(
+                    error,
+                    Error::BufferSizeMismatch {
+                        expected: DEFAULT_SIZE,
+                        found: 16
+                    }
+                ),
+                "{error}"
+            ),
+        }
+        match write_range(&mut store, 1, &[&[1u8; DEFAULT_SIZE], &long]) {
+            Ok(_) => panic!("allowed range with buffer that does not hold one page"),
+            Err(error) => assert!(
+                matches!(error, Error::BufferSizeMismatch { found, .. } if found == 2 * DEFAULT_SIZE),
+                "{error}"
+            ),
+        }
+
+        // Nothing was written.
+        assert_eq!(2, count(&mut store).unwrap());
+        read(&mut store, 1, &mut page).unwrap();
+        assert_eq!([3u8; DEFAULT_SIZE], page);
+    }
+
+    #[test]
+    fn read_when_file_ends_mid_page() {
+        let mut store = Memory::from(vec![3u8; DEFAULT_SIZE + DEFAULT_SIZE / 2]);
+        let mut read_buffer = [0u8; DEFAULT_SIZE];
+        match read(&mut store, 1, &mut read_buffer) {
+            Ok(_) => panic!("allowed reading partial page"),
+            Err(error) => assert!(
+                matches!(error, Error::ShortRead { page: 1, expected: DEFAULT_SIZE, found } if found == DEFAULT_SIZE / 2),
+                "{error}"
+            ),
+        }
+    }
+
+    #[test]
+    fn range_round_trip() {
+        ephemeral::file!(tmp {
+            write(tmp.borrow_mut(), 0, &[1u8; DEFAULT_SIZE]).unwrap();
+            write_range(tmp.borrow_mut(), 1, &[&[2u8; DEFAULT_SIZE], &[3u8; DEFAULT_SIZE]]).unwrap();
+            prefetch(tmp.borrow_mut(), 0..8).unwrap();
+
+            let (mut a, mut b, mut c) = ([0u8; DEFAULT_SIZE], [0u8; DEFAULT_SIZE], [0u8; DEFAULT_SIZE]);
+            read_range(tmp.borrow_mut(), 0, &mut [&mut a, &mut b, &mut c]).unwrap();
+            assert_eq!([[1u8; DEFAULT_SIZE], [2u8; DEFAULT_SIZE], [3u8; DEFAULT_SIZE]], [a, b, c]);
+        });
+    }
+
+    #[test]
+    fn read_range_past_end() {
+        ephemeral::file!(tmp {
+            write_range(tmp.borrow_mut(), 0, &[&[0u8; DEFAULT_SIZE], &[0u8; DEFAULT_SIZE]]).unwrap();
+            let (mut a, mut b) = ([0u8; DEFAULT_SIZE], [0u8; DEFAULT_SIZE]);
+            match read_range(tmp.borrow_mut(), 1, &mut [&mut a, &mut b]) {
+                Ok(_) => panic!("allowed reading range past the end"),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 2, end: 2 }), "{error}"),
+            }
+            match read_range(tmp.borrow_mut(), 4, &mut [&mut a]) {
+                Ok(_) => panic!("allowed reading distant range"),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 4, end: 2 }), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn read_range_when_file_ends_mid_page() {
+        let mut store = Memory::from(vec![3u8; 2 * DEFAULT_SIZE + 10]);
+        let (mut a, mut b) = ([0u8; DEFAULT_SIZE], [0u8; DEFAULT_SIZE]);
+        match read_range(&mut store, 1, &mut [&mut a, &mut b]) {
+            Ok(_) => panic!("allowed reading partial page"),
+            Err(error) => assert!(
+                matches!(
+                    error,
+                    Error::ShortRead {
+                        page: 2,
+                        expected: DEFAULT_SIZE,
+                        found: 10
+                    }
+                ),
+                "{error}"
+            ),
+        }
+    }
+
+    #[test]
+    fn write_range_given_distant_page() {
+        ephemeral::file!(tmp {
+            match write_range(tmp.borrow_mut(), 1, &[&[1u8; DEFAULT_SIZE]]) {
+                Ok(_) => panic!("allowed writing distant range"),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 1, end: 0 }), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn read_checked_repairs_page_in_file() {
+        ephemeral::file!(tmp {
+            let mut page = [0u8; DEFAULT_SIZE];
+            header::init(&mut page, header::Kind::Overflow, 0, Algorithm::Xxh3);
+            header::protect(&mut page);
+            page[header::SIZE..header::SIZE + 5].copy_from_slice(b"hello");
+            header::seal(&mut page);
+            let mut rotten = page;
+            rotten[header::SIZE..header::SIZE + 3].fill(0);
+            write(tmp.borrow_mut(), 0, &rotten).unwrap();
+
+            let mut buf = [0u8; DEFAULT_SIZE];
+            assert_eq!(header::Health::Repaired(3), read_checked(tmp.borrow_mut(), 0, &mut buf).unwrap());
+            assert_eq!(page, buf);
+            assert_eq!(header::Health::Intact, read_checked(tmp.borrow_mut(), 0, &mut buf).unwrap());
+        });
+    }
+
     #[test]
     fn write_seeks_multiple_of_page_size() {
         ephemeral::file!(tmp {
-            let write_buffer = [1u8; SIZE];
+            let write_buffer = [1u8; DEFAULT_SIZE];
             assert!(write(tmp.borrow_mut(), 0, &write_buffer).is_ok());
 
-            let write_buffer = [2u8; SIZE];
+            let write_buffer = [2u8; DEFAULT_SIZE];
             assert!(write(tmp.borrow_mut(), 1, &write_buffer).is_ok());
 
-            tmp.borrow_mut().seek(io::SeekFrom::Start(0)).unwrap();
-            let mut read_buffer = [0u8; SIZE * 2];
-            tmp.borrow_mut().read_exact(&mut read_buffer).unwrap();
-            assert_eq!(read_buffer[0..SIZE], [1u8; SIZE]);
-            assert_eq!(read_buffer[SIZE..SIZE*2], [2u8; SIZE]);
+            let mut read_buffer = [0u8; DEFAULT_SIZE];
+            tmp.borrow_mut().read_page(0, &mut read_buffer).unwrap();
+            assert_eq!(read_buffer, [1u8; DEFAULT_SIZE]);
+            tmp.borrow_mut().read_page(1, &mut read_buffer).unwrap();
+            assert_eq!(read_buffer, [2u8; DEFAULT_SIZE]);
         });
     }
 
     #[test]
     fn write_given_distant_page() {
         ephemeral::file!(tmp {
-            let write_buffer = [1u8; SIZE];
+            let write_buffer = [1u8; DEFAULT_SIZE];
             match write(tmp.borrow_mut(), 1, &write_buffer) {
                 Ok(_) => panic!("allowed writing distant page"),
-                Err(error) => assert_eq!("tried to write distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 1, end: 0 }), "{error}"),
             }
         });
         ephemeral::file!(tmp {
-            let write_buffer = [1u8; SIZE];
+            let write_buffer = [1u8; DEFAULT_SIZE];
             match write(tmp.borrow_mut(), 4, &write_buffer) {
                 Ok(_) => panic!("allowed writing distant page"),
-                Err(error) => assert_eq!("tried to write distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 4, end: 0 }), "{error}"),
             }
         });
     }
@@ -339,38 +1584,38 @@ mod tests {
     #[test]
     fn copy_given_invalid_page_combination() {
         ephemeral::file!(tmp {
-            write(tmp.borrow_mut(), 0, &[0u8; SIZE]).unwrap();
+            write(tmp.borrow_mut(), 0, &[0u8; DEFAULT_SIZE]).unwrap();
             match copy(tmp.borrow_mut(), 0, 0) {
                 Ok(_) => panic!("allowed copying page to itself"),
-                Err(error) => assert_eq!("tried to copy page to itself", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::CopyToSelf { page: 0 }), "{error}"),
             }
         });
         ephemeral::file!(tmp {
-            write(tmp.borrow_mut(), 0, &[0u8; SIZE]).unwrap();
+            write(tmp.borrow_mut(), 0, &[0u8; DEFAULT_SIZE]).unwrap();
             match copy(tmp.borrow_mut(), 1, 0) {
                 Ok(_) => panic!("allowed copying from distant page"),
-                Err(error) => assert_eq!("tried to read distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 1, end: 1 }), "{error}"),
             }
         });
         ephemeral::file!(tmp {
-            write(tmp.borrow_mut(), 0, &[0u8; SIZE]).unwrap();
+            write(tmp.borrow_mut(), 0, &[0u8; DEFAULT_SIZE]).unwrap();
             match copy(tmp.borrow_mut(), 4, 0) {
                 Ok(_) => panic!("allowed copying from distant page"),
-                Err(error) => assert_eq!("tried to read distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 4, end: 1 }), "{error}"),
             }
         });
         ephemeral::file!(tmp {
-            write(tmp.borrow_mut(), 0, &[0u8; SIZE]).unwrap();
+            write(tmp.borrow_mut(), 0, &[0u8; DEFAULT_SIZE]).unwrap();
             match copy(tmp.borrow_mut(), 0, 2) {
                 Ok(_) => panic!("allowed copying from distant page"),
-                Err(error) => assert_eq!("tried to write distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 2, end: 1 }), "{error}"),
             }
         });
         ephemeral::file!(tmp {
-            write(tmp.borrow_mut(), 0, &[0u8; SIZE]).unwrap();
+            write(tmp.borrow_mut(), 0, &[0u8; DEFAULT_SIZE]).unwrap();
             match copy(tmp.borrow_mut(), 0, 4) {
                 Ok(_) => panic!("allowed copying from distant page"),
-                Err(error) => assert_eq!("tried to write distant page", error.to_string()),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 4, end: 1 }), "{error}"),
             }
         });
     }
@@ -378,17 +1623,17 @@ mod tests {
     #[test]
     fn copy_copies_from_src_to_dst() {
         ephemeral::file!(tmp {
-            write(tmp.borrow_mut(), 0, &[1u8; SIZE]).unwrap();
-            write(tmp.borrow_mut(), 1, &[2u8; SIZE]).unwrap();
+            write(tmp.borrow_mut(), 0, &[1u8; DEFAULT_SIZE]).unwrap();
+            write(tmp.borrow_mut(), 1, &[2u8; DEFAULT_SIZE]).unwrap();
 
-            let mut buf = [0u8; SIZE];
+            let mut buf = [0u8; DEFAULT_SIZE];
             read(tmp.borrow_mut(), 1, &mut buf).unwrap();
-            assert_eq!([2u8; SIZE], buf);
+            assert_eq!([2u8; DEFAULT_SIZE], buf);
 
             copy(tmp.borrow_mut(), 0, 1).unwrap();
 
             read(tmp.borrow_mut(), 1, &mut buf).unwrap();
-            assert_eq!([1u8; SIZE], buf);
+            assert_eq!([1u8; DEFAULT_SIZE], buf);
         });
     }
 }
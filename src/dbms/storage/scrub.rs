@@ -0,0 +1,373 @@
+use std::{
+    io,
+    sync::{Arc, Condvar, Mutex, mpsc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::dbms::storage::{
+    PageStore, meta,
+    page::{self, header},
+};
+
+// How many pages ahead of itself a pass asks the store to fetch.
+const READAHEAD: u64 = 64;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub pages: u64,
+    pub repaired: Vec<u64>,
+    pub corrupt: Vec<u64>,
+    // Pages that hold nothing but zeroes. Pages that have been allocated but
+    // not formatted yet look like that, and so do pages that were wiped, which
+    // only the owner of a page can tell apart.
+    pub blank: Vec<u64>,
+}
+
+// Checks every page in the store and repairs the ones it can. Pages in one of
+// the given meta pairs are checked against their CRC and repaired from the
+// other page of the pair. Other pages are checked through their page header.
+//
+// Repairs assume that nothing writes the damaged page in the meantime, which
+// would otherwise be overwritten with older contents.
+pub fn scrub(store: &mut dyn PageStore, pairs: &[(u64, u64)]) -> io::Result<Report> {
+    let mut report = Report {
+        pages: page::count(store)?,
+        ..Report::default()
+    };
+    for page in 0..report.pages {
+        if page % READAHEAD == 0 {
+            page::prefetch(store, page..page + READAHEAD)?;
+        }
+        check(store, pairs, page, &mut report)?;
+    }
+    Ok(report)
+}
+
+fn check(
+    store: &mut dyn PageStore,
+    pairs: &[(u64, u64)],
+    page: u64,
+    report: &mut Report,
+) -> io::Result<()> {
+    if let Some(pair) = pairs.iter().find(|pair| pair.0 == page || pair.1 == page) {
+        return check_meta(store, *pair, page, report);
+    }
+    let mut buf = page::buffer(store);
+    page::read(store, page, &mut buf)?;
+    if buf.iter().all(|byte| *byte == 0) {
+        report.blank.push(page);
+        return Ok(());
+    }
+    match header::repair(&mut buf, page) {
+        Ok((_, header::Health::Intact)) => {}
+        Ok((_, header::Health::Repaired(_))) => {
+            page::write(store, page, &buf)?;
+            report.repaired.push(page);
+        }
+        Err(_) => report.corrupt.push(page),
+    }
+    Ok(())
+}
+
+fn check_meta(
+    store: &mut dyn PageStore,
+    pair: (u64, u64),
+    page: u64,
+    report: &mut Report,
+) -> io::Result<()> {
+    let mut buf = page::buffer(store);
+    page::read(store, page, &mut buf)?;
+    if meta::is_intact(&buf) {
+        return Ok(());
+    }
+    let other = if page == pair.0 { pair.1 } else { pair.0 };
+    page::read(store, other, &mut buf)?;
+    if !meta::is_intact(&buf) {
+        report.corrupt.push(page);
+        return Ok(());
+    }
+    // A copy of the intact page holds the same version under the same
+    // sequence number, and the next write of the pair replaces one of them.
+    page::copy(store, other, page)?;
+    store.sync()?;
+    report.repaired.push(page);
+    Ok(())
+}
+
+// Scrubs the store over and over on a background thread, pausing between
+// every page so that it does not crowd out other I/O, and sending a report
+// after every pass. Every page is checked and repaired while holding the lock
+// on the store, so that writers that share the store through it cannot slip
+// in between reading a damaged page and repairing it.
+pub struct Scrubber {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Scrubber {
+    pub fn spawn(
+        store: Arc<Mutex<impl PageStore + Send + 'static>>,
+        pairs: Vec<(u64, u64)>,
+        pause: Duration,
+        interval: Duration,
+        reports: mpsc::Sender<Report>,
+    ) -> Self {
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = stopped.clone();
+        let handle = thread::spawn(move || {
+            // Sleeps for the given time unless the scrubber is stopped, and
+            // tells whether it was.
+            let wait = |duration| {
+                let (lock, condvar) = &*signal;
+                let guard = lock.lock().unwrap();
+                *condvar
+                    .wait_timeout_while(guard, duration, |stopped| !*stopped)
+                    .unwrap()
+                    .0
+            };
+            loop {
+                let mut report = Report {
+                    pages: page::count(&mut *store.lock().unwrap())?,
+                    ..Report::default()
+                };
+                for page in 0..report.pages {
+                    if wait(pause) {
+                        return Ok(());
+                    }
+                    check(&mut *store.lock().unwrap(), &pairs, page, &mut report)?;
+                }
+                if reports.send(report).is_err() || wait(interval) {
+                    return Ok(());
+                }
+            }
+        });
+        Self {
+            stopped,
+            handle: Some(handle),
+        }
+    }
+
+    // Stops the scrubber in the middle of its pass, and returns the error
+    // that stopped it early, if any.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        let (lock, condvar) = &*self.stopped;
+        *lock.lock().unwrap() = true;
+        condvar.notify_all();
+        match self.handle.take() {
+            Some(handle) => handle.join().unwrap(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Scrubber {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::storage::{alloc, ephemeral, integrity::Algorithm, overflow, page::slot};
+    use std::io::Write;
+
+    // Meta pair at pages 0 and 1, a slot page at 2, and an overflow chain of
+    // two pages at 3 and 4.
+    fn setup(store: &mut dyn PageStore) {
+        page::write(store, 0, &[0u8; page::DEFAULT_SIZE]).unwrap();
+        page::write(store, 1, &[0u8; page::DEFAULT_SIZE]).unwrap();
+        alloc::init(store, (0, 1)).unwrap();
+        let page = alloc::allocate(store, (0, 1)).unwrap();
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        slot::init(&mut buf, page);
+        slot::insert(&mut buf, b"record").unwrap();
+        page::write(store, page, &buf).unwrap();
+        let mut writer = overflow::Writer::new(store, (0, 1)).unwrap();
+        writer
+            .write_all(&[7u8; overflow::capacity(page::DEFAULT_SIZE) + 1])
+            .unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn corrupt(store: &mut dyn PageStore, page: u64, at: usize) {
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        page::read(store, page, &mut buf).unwrap();
+        buf[at] ^= 0x01;
+        page::write(store, page, &buf).unwrap();
+    }
+
+    // Writes a protected page at 5 whose magic has rotted, and returns what
+    // it held before.
+    fn rotted(store: &mut dyn PageStore) -> [u8; page::DEFAULT_SIZE] {
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        header::init(&mut buf, header::Kind::Slotted, 5, Algorithm::Crc64);
+        header::protect(&mut buf);
+        buf[header::SIZE..header::SIZE + 4].copy_from_slice(b"data");
+        header::seal(&mut buf);
+        let intact = buf;
+        // Rot the magic, so that the page looks unformatted.
+        buf[8..16].fill(0);
+        page::write(store, 5, &buf).unwrap();
+        intact
+    }
+
+    #[test]
+    fn scrub_when_intact() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            assert_eq!(
+                Report { pages: 5, ..Report::default() },
+                scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap()
+            );
+
+            // A page that has not been formatted yet is not corrupt, but it is
+            // reported as blank.
+            alloc::allocate(tmp.borrow_mut(), (0, 1)).unwrap();
+            assert_eq!(
+                Report { pages: 6, blank: vec![5], ..Report::default() },
+                scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn scrub_reports_corrupt_pages() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            corrupt(tmp.borrow_mut(), 2, page::DEFAULT_SIZE - 1);
+            corrupt(tmp.borrow_mut(), 4, page::DEFAULT_SIZE / 2);
+
+            let report = scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap();
+            assert_eq!(vec![2, 4], report.corrupt);
+            assert!(report.repaired.is_empty());
+        });
+    }
+
+    #[test]
+    fn scrub_repairs_meta_pages_from_each_other() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let mut backup = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 1, &mut backup).unwrap();
+            corrupt(tmp.borrow_mut(), 0, 3);
+
+            let report = scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap();
+            assert_eq!(vec![0], report.repaired);
+            assert!(report.corrupt.is_empty());
+
+            corrupt(tmp.borrow_mut(), 1, 3);
+            let report = scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap();
+            assert_eq!(vec![1], report.repaired);
+            assert_eq!(Report { pages: 5, ..Report::default() }, scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap());
+
+            // Both pages hold what the intact page held when the first one was
+            // repaired.
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            for page in [0, 1] {
+                page::read(tmp.borrow_mut(), page, &mut buf).unwrap();
+                assert_eq!(backup, buf);
+            }
+        });
+    }
+
+    #[test]
+    fn scrub_when_both_meta_pages_are_corrupt() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            corrupt(tmp.borrow_mut(), 0, 3);
+            corrupt(tmp.borrow_mut(), 1, 3);
+            let report = scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap();
+            assert_eq!(vec![0, 1], report.corrupt);
+        });
+    }
+
+    #[test]
+    fn scrub_repairs_protected_pages() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let intact = rotted(tmp.borrow_mut());
+
+            let report = scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap();
+            assert_eq!(vec![5], report.repaired);
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 5, &mut buf).unwrap();
+            assert_eq!(intact, buf);
+        });
+    }
+
+    #[test]
+    fn scrubber_reports_every_pass() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            corrupt(tmp.borrow_mut(), 3, 100);
+            let (sender, receiver) = mpsc::channel();
+            let scrubber = Scrubber::spawn(Arc::new(Mutex::new(tmp.borrow_mut().clone())), vec![(0, 1)], Duration::ZERO, Duration::ZERO, sender);
+
+            for _ in 0..2 {
+                let report = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+                assert_eq!(5, report.pages);
+                assert_eq!(vec![3], report.corrupt);
+            }
+            scrubber.stop().unwrap();
+        });
+    }
+
+    #[test]
+    fn scrubber_repairs_pages() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let intact = rotted(tmp.borrow_mut());
+            let mut backup = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 1, &mut backup).unwrap();
+            corrupt(tmp.borrow_mut(), 0, 3);
+            let store = Arc::new(Mutex::new(tmp.borrow_mut().clone()));
+            let (sender, receiver) = mpsc::channel();
+            let writer = store.lock().unwrap();
+            let scrubber = Scrubber::spawn(store.clone(), vec![(0, 1)], Duration::ZERO, Duration::ZERO, sender);
+
+            // A pass cannot get past a writer that holds the store.
+            assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+            drop(writer);
+            let report = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+            assert_eq!(vec![0, 5], report.repaired);
+            assert!(report.corrupt.is_empty());
+            let report = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+            assert_eq!(Report { pages: 6, ..Report::default() }, report);
+            scrubber.stop().unwrap();
+
+            let mut buf = [0u8; page::DEFAULT_SIZE];
+            page::read(tmp.borrow_mut(), 0, &mut buf).unwrap();
+            assert_eq!(backup, buf);
+            page::read(tmp.borrow_mut(), 5, &mut buf).unwrap();
+            assert_eq!(intact, buf);
+        });
+    }
+
+    #[test]
+    fn scrub_reports_blank_pages() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            page::write(tmp.borrow_mut(), 3, &[0u8; page::DEFAULT_SIZE]).unwrap();
+            let report = scrub(tmp.borrow_mut(), &[(0, 1)]).unwrap();
+            assert_eq!(vec![3], report.blank);
+            assert!(report.corrupt.is_empty());
+        });
+    }
+
+    #[test]
+    fn scrubber_stops_mid_pass() {
+        ephemeral::file!(tmp {
+            setup(tmp.borrow_mut());
+            let (sender, receiver) = mpsc::channel();
+            let scrubber = Scrubber::spawn(Arc::new(Mutex::new(tmp.borrow_mut().clone())), vec![(0, 1)], Duration::from_secs(60), Duration::ZERO, sender);
+            scrubber.stop().unwrap();
+            assert!(receiver.try_recv().is_err());
+        });
+    }
+}
@@ -0,0 +1,801 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs::File,
+    io::{self, IoSlice, IoSliceMut},
+    ops::Range,
+    os::{fd::AsRawFd, unix::fs::FileExt},
+    sync::{Arc, Mutex},
+};
+
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+use crate::dbms::storage::{self, meta, page};
+
+mod mmap;
+
+pub use mmap::Mapped;
+
+// The most buffers that a single preadv or pwritev takes on Linux.
+const IOV_MAX: usize = 1024;
+
+// Where pages are kept. A store only moves whole pages to and from its
+// medium, deciding which pages may be read or written is left to `page`.
+pub trait PageStore {
+    // The size of every page in the store, which never changes.
+    fn page_size(&self) -> usize;
+
+    // The size of the store in bytes, which is a multiple of the page size
+    // unless a write was cut short.
+    fn size(&mut self) -> io::Result<u64>;
+
+    // Reads as much of the page as the store holds, and returns how much
+    // that was.
+    fn read_page(&mut self, page: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    fn write_page(&mut self, page: u64, buf: &[u8]) -> io::Result<()>;
+
+    // Cuts the store short or extends it with zeroes.
+    fn truncate(&mut self, size: u64) -> io::Result<()>;
+
+    // Makes every write so far durable.
+    fn sync(&mut self) -> io::Result<()>;
+
+    // Reads a run of adjacent pages starting at `first` into the buffers,
+    // and returns how much of the run the store holds. Stores that can move
+    // a run in one go override this.
+    fn read_pages(&mut self, first: u64, bufs: &mut [&mut [u8]]) -> io::Result<usize> {
+        let mut found = 0;
+        for (page, buf) in (first..).zip(bufs.iter_mut()) {
+            let n = self.read_page(page, buf)?;
+            found += n;
+            if n < self.page_size() {
+                break;
+            }
+        }
+        Ok(found)
+    }
+
+    fn write_pages(&mut self, first: u64, bufs: &[&[u8]]) -> io::Result<()> {
+        for (page, buf) in (first..).zip(bufs) {
+            self.write_page(page, buf)?;
+        }
+        Ok(())
+    }
+
+    // Hints that the pages are about to be read, so that the store can start
+    // fetching them. Stores that have nothing to gain from it ignore it.
+    fn advise(&mut self, _pages: Range<u64>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A file of pages. The page size is chosen when the file is created and
+// recorded in the meta pair at its start, which is where `open` finds it.
+// Positional reads and writes leave the file offset alone, so cloned handles
+// can be used side by side.
+#[derive(Debug)]
+pub struct Disk {
+    file: File,
+    page_size: usize,
+}
+
+impl Disk {
+    // Starts a store in a file that has no meta pair yet.
+    pub fn create(file: File, page_size: usize) -> storage::Result<Self> {
+        page::check_size(page_size)?;
+        Ok(Self { file, page_size })
+    }
+
+    pub fn open(file: File) -> storage::Result<Self> {
+        let page_size = meta::probe(&file)?;
+        Ok(Self { file, page_size })
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            file: self.file.try_clone()?,
+            page_size: self.page_size,
+        })
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+impl PageStore for Disk {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn read_page(&mut self, page: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let offset = page * self.page_size as u64;
+        let mut found = 0;
+        while found < buf.len() {
+            match self.file.read_at(&mut buf[found..], offset + found as u64) {
+                Ok(0) => break,
+                Ok(n) => found += n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(found)
+    }
+
+    fn write_page(&mut self, page: u64, buf: &[u8]) -> io::Result<()> {
+        self.file.write_all_at(buf, page * self.page_size as u64)
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.file.set_len(size)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn read_pages(&mut self, first: u64, bufs: &mut [&mut [u8]]) -> io::Result<usize> {
+        let offset = first * self.page_size as u64;
+        let mut slices: Vec<IoSliceMut> = bufs
+            .iter_mut()
+            .map(|buf| IoSliceMut::new(&mut buf[..]))
+            .collect();
+        let mut slices = &mut slices[..];
+        let mut found = 0;
+        while !slices.is_empty() {
+            // SAFETY: `IoSliceMut` has the layout of `iovec` on Unix, and
+            // every slice stays borrowed for the length of the call.
+            let n = unsafe {
+                libc::preadv(
+                    self.file.as_raw_fd(),
+                    slices.as_ptr().cast(),
+                    slices.len().min(IOV_MAX) as libc::c_int,
+                    (offset + found as u64) as libc::off_t,
+                )
+            };
+            match n {
+                -1 => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+                0 => break,
+                n => {
+                    found += n as usize;
+                    IoSliceMut::advance_slices(&mut slices, n as usize);
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    fn write_pages(&mut self, first: u64, bufs: &[&[u8]]) -> io::Result<()> {
+        let offset = first * self.page_size as u64;
+        let mut slices: Vec<IoSlice> = bufs.iter().map(|buf| IoSlice::new(&buf[..])).collect();
+        let mut slices = &mut slices[..];
+        let mut written = 0;
+        while !slices.is_empty() {
+            // SAFETY: `IoSlice` has the layout of `iovec` on Unix, and every
+            // slice stays borrowed for the length of the call.
+            let n = unsafe {
+                libc::pwritev(
+                    self.file.as_raw_fd(),
+                    slices.as_ptr().cast(),
+                    slices.len().min(IOV_MAX) as libc::c_int,
+                    (offset + written as u64) as libc::off_t,
+                )
+            };
+            match n {
+                -1 => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => {
+                    written += n as usize;
+                    IoSlice::advance_slices(&mut slices, n as usize);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn advise(&mut self, pages: Range<u64>) -> io::Result<()> {
+        let offset = pages.start * self.page_size as u64;
+        let len = (pages.end - pages.start) * self.page_size as u64;
+        // SAFETY: the advice only affects the page cache, not the file.
+        let error = unsafe {
+            libc::posix_fadvise(
+                self.file.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                libc::POSIX_FADV_WILLNEED,
+            )
+        };
+        match error {
+            0 => Ok(()),
+            error => Err(io::Error::from_raw_os_error(error)),
+        }
+    }
+}
+
+// Keeps pages in memory, where everything is durable as soon as it is
+// written. Clones share their contents, like handles to the same file.
+#[derive(Debug, Clone)]
+pub struct Memory {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    page_size: usize,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::with_page_size(page::DEFAULT_SIZE)
+    }
+
+    pub fn with_page_size(page_size: usize) -> Self {
+        Self {
+            bytes: Arc::default(),
+            page_size,
+        }
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Vec<u8>> for Memory {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes: Arc::new(Mutex::new(bytes)),
+            page_size: page::DEFAULT_SIZE,
+        }
+    }
+}
+
+impl PageStore for Memory {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.bytes.lock().unwrap().len() as u64)
+    }
+
+    fn read_page(&mut self, page: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.bytes.lock().unwrap();
+        let offset = (page as usize * self.page_size).min(bytes.len());
+        let found = (bytes.len() - offset).min(buf.len());
+        buf[..found].copy_from_slice(&bytes[offset..offset + found]);
+        Ok(found)
+    }
+
+    fn write_page(&mut self, page: u64, buf: &[u8]) -> io::Result<()> {
+        let mut bytes = self.bytes.lock().unwrap();
+        let offset = page as usize * self.page_size;
+        if bytes.len() < offset + buf.len() {
+            bytes.resize(offset + buf.len(), 0);
+        }
+        bytes[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.bytes.lock().unwrap().resize(size as usize, 0);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Read,
+    Write,
+    Sync,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    // Fails every operation of the kind, on the given page or on every page.
+    Fail(Op, Option<u64>),
+    // Fails the nth write from now, counting from zero, before it touches
+    // the store.
+    FailWrite(u64),
+    // Lets only the first bytes of the nth write from now reach the store,
+    // and then fails it.
+    TearWrite(u64, usize),
+    // Fails the nth sync from now, counting from zero.
+    FailSync(u64),
+    // Flips a bit of the page whenever it is read, while the stored page
+    // stays as it is.
+    FlipBit(u64, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Crash {
+    // Every write since the last sync is lost.
+    Drop,
+    // Any of the writes since the last sync may have reached the store, in
+    // any order. The seed decides which ones did and in what order.
+    Reorder(u64),
+}
+
+// Wraps another store and injects faults into it, so that error paths and
+// crash consistency can be tested. Writes go straight through to the inner
+// store, but those since the last sync are remembered so that a crash can
+// take them back. Clones share their faults and unsynced writes, so a store
+// can be handed off and still be failed from the outside.
+#[derive(Debug, Clone)]
+pub struct Faulty<S> {
+    inner: S,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // Faults on the nth write or sync count from the first one ever made.
+    faults: Vec<Fault>,
+    writes: u64,
+    syncs: u64,
+    // The size of the store and the pages as they were at the last sync,
+    // for the pages that have been written since.
+    synced: Option<u64>,
+    originals: HashMap<u64, Box<[u8]>>,
+    // Writes since the last sync, with the number of bytes that reached the
+    // store.
+    unsynced: Vec<(u64, Box<[u8]>, usize)>,
+}
+
+impl<S: PageStore> Faulty<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        let mut state = self.state.lock().unwrap();
+        let fault = match fault {
+            Fault::FailWrite(nth) => Fault::FailWrite(state.writes + nth),
+            Fault::TearWrite(nth, len) => Fault::TearWrite(state.writes + nth, len),
+            Fault::FailSync(nth) => Fault::FailSync(state.syncs + nth),
+            fault => fault,
+        };
+        state.faults.push(fault);
+    }
+
+    pub fn heal(&self) {
+        self.state.lock().unwrap().faults.clear();
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // The number of writes made so far, including the ones that failed.
+    pub fn writes(&self) -> u64 {
+        self.state.lock().unwrap().writes
+    }
+
+    // The number of syncs made so far, including the ones that failed.
+    pub fn syncs(&self) -> u64 {
+        self.state.lock().unwrap().syncs
+    }
+
+    // The pages written since the last sync, in the order they were written.
+    pub fn unsynced(&self) -> Vec<u64> {
+        let state = self.state.lock().unwrap();
+        state.unsynced.iter().map(|(page, ..)| *page).collect()
+    }
+
+    // Puts the inner store in a state it could be in after the machine lost
+    // power, and carries on from there as if it had been restarted.
+    pub fn crash(&mut self, crash: Crash) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some(size) = state.synced.take() else {
+            return Ok(());
+        };
+        for (page, image) in state.originals.drain() {
+            self.inner.write_page(page, &image)?;
+        }
+        self.inner.truncate(size)?;
+        let mut landed = std::mem::take(&mut state.unsynced);
+        match crash {
+            Crash::Drop => landed.clear(),
+            Crash::Reorder(seed) => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                landed.retain(|_| rng.random_bool(0.5));
+                landed.shuffle(&mut rng);
+            }
+        }
+        for (page, buf, len) in landed {
+            tear(&mut self.inner, page, &buf, len)?;
+        }
+        Ok(())
+    }
+
+    fn check(&self, op: Op, page: Option<u64>) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+        let failing = state.faults.iter().any(|fault| match *fault {
+            Fault::Fail(failing, on) => failing == op && (on.is_none() || on == page),
+            _ => false,
+        });
+        if failing {
+            return Err(io::Error::other(format!("injected {op:?} fault")));
+        }
+        Ok(())
+    }
+}
+
+// Writes the first `len` bytes of the page, leaving the rest of it as it was.
+fn tear(store: &mut impl PageStore, page: u64, buf: &[u8], len: usize) -> io::Result<()> {
+    if len == buf.len() {
+        return store.write_page(page, buf);
+    }
+    let size = store.size()?;
+    let mut torn = vec![0u8; buf.len()];
+    store.read_page(page, &mut torn)?;
+    torn[..len].copy_from_slice(&buf[..len]);
+    store.write_page(page, &torn)?;
+    store.truncate(size.max(page * buf.len() as u64 + len as u64))
+}
+
+impl<S: PageStore> PageStore for Faulty<S> {
+    fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        self.inner.size()
+    }
+
+    fn read_page(&mut self, page: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.check(Op::Read, Some(page))?;
+        let found = self.inner.read_page(page, buf)?;
+        for fault in &self.state.lock().unwrap().faults {
+            if let Fault::FlipBit(on, bit) = *fault
+                && on == page
+                && bit / 8 < found
+            {
+                buf[bit / 8] ^= 1 << (bit % 8);
+            }
+        }
+        Ok(found)
+    }
+
+    fn write_page(&mut self, page: u64, buf: &[u8]) -> io::Result<()> {
+        self.check(Op::Write, Some(page))?;
+        let mut state = self.state.lock().unwrap();
+        let nth = state.writes;
+        state.writes += 1;
+        let mut len = buf.len();
+        for fault in &state.faults {
+            match *fault {
+                Fault::FailWrite(at) if at == nth => {
+                    return Err(io::Error::other("injected Write fault"));
+                }
+                Fault::TearWrite(at, torn) if at == nth => len = torn.min(buf.len()),
+                _ => {}
+            }
+        }
+        if state.synced.is_none() {
+            state.synced = Some(self.inner.size()?);
+        }
+        if let Entry::Vacant(entry) = state.originals.entry(page) {
+            let mut original = vec![0u8; buf.len()].into_boxed_slice();
+            self.inner.read_page(page, &mut original)?;
+            entry.insert(original);
+        }
+        tear(&mut self.inner, page, buf, len)?;
+        state.unsynced.push((page, buf.into(), len));
+        if len < buf.len() {
+            return Err(io::Error::other("injected torn write"));
+        }
+        Ok(())
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.inner.truncate(size)
+    }
+
+    fn advise(&mut self, pages: Range<u64>) -> io::Result<()> {
+        self.inner.advise(pages)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.check(Op::Sync, None)?;
+        let mut state = self.state.lock().unwrap();
+        let nth = state.syncs;
+        state.syncs += 1;
+        if state.faults.contains(&Fault::FailSync(nth)) {
+            return Err(io::Error::other("injected Sync fault"));
+        }
+        self.inner.sync()?;
+        state.synced = None;
+        state.originals.clear();
+        state.unsynced.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::storage::ephemeral;
+
+    fn round_trip(store: &mut dyn PageStore) {
+        store.write_page(0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+        store.write_page(1, &[2u8; page::DEFAULT_SIZE]).unwrap();
+        store.write_page(0, &[3u8; page::DEFAULT_SIZE]).unwrap();
+        store.sync().unwrap();
+        assert_eq!(2 * page::DEFAULT_SIZE as u64, store.size().unwrap());
+
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        assert_eq!(page::DEFAULT_SIZE, store.read_page(0, &mut buf).unwrap());
+        assert_eq!([3u8; page::DEFAULT_SIZE], buf);
+        assert_eq!(page::DEFAULT_SIZE, store.read_page(1, &mut buf).unwrap());
+        assert_eq!([2u8; page::DEFAULT_SIZE], buf);
+        assert_eq!(0, store.read_page(2, &mut buf).unwrap());
+    }
+
+    fn range_round_trip(store: &mut dyn PageStore) {
+        let pages: Vec<[u8; page::DEFAULT_SIZE]> =
+            (0..4).map(|n| [n as u8 + 1; page::DEFAULT_SIZE]).collect();
+        store
+            .write_pages(0, &pages.iter().map(|page| &page[..]).collect::<Vec<_>>())
+            .unwrap();
+        store.write_pages(4, &[&[9u8; page::DEFAULT_SIZE]]).unwrap();
+        store.advise(0..5).unwrap();
+
+        let mut bufs = [[0u8; page::DEFAULT_SIZE]; 5];
+        let mut refs: Vec<&mut [u8]> = bufs.iter_mut().map(|buf| &mut buf[..]).collect();
+        assert_eq!(
+            5 * page::DEFAULT_SIZE,
+            store.read_pages(0, &mut refs).unwrap()
+        );
+        assert_eq!(pages[..], bufs[..4]);
+        assert_eq!([9u8; page::DEFAULT_SIZE], bufs[4]);
+
+        // The run is cut short where the store ends.
+        let mut refs: Vec<&mut [u8]> = bufs.iter_mut().map(|buf| &mut buf[..]).collect();
+        assert_eq!(
+            2 * page::DEFAULT_SIZE,
+            store.read_pages(3, &mut refs).unwrap()
+        );
+    }
+
+    #[test]
+    fn file_round_trip() {
+        ephemeral::disk!(tmp {
+            round_trip(&mut tmp.store(page::DEFAULT_SIZE));
+        });
+    }
+
+    #[test]
+    fn memory_round_trip() {
+        round_trip(&mut Memory::new());
+    }
+
+    #[test]
+    fn file_range_round_trip() {
+        ephemeral::disk!(tmp {
+            range_round_trip(&mut tmp.store(page::DEFAULT_SIZE));
+        });
+    }
+
+    #[test]
+    fn memory_range_round_trip() {
+        range_round_trip(&mut Memory::new());
+    }
+
+    // More buffers than a single preadv takes.
+    #[test]
+    fn file_range_longer_than_iov_max() {
+        ephemeral::disk!(tmp {
+            let pages: Vec<[u8; page::DEFAULT_SIZE]> = (0..IOV_MAX + 3).map(|n| [n as u8; page::DEFAULT_SIZE]).collect();
+            let mut store = tmp.store(page::DEFAULT_SIZE);
+            store
+                .write_pages(0, &pages.iter().map(|page| &page[..]).collect::<Vec<_>>())
+                .unwrap();
+
+            let mut bufs = vec![[0u8; page::DEFAULT_SIZE]; pages.len()];
+            let mut refs: Vec<&mut [u8]> = bufs.iter_mut().map(|buf| &mut buf[..]).collect();
+            assert_eq!(pages.len() * page::DEFAULT_SIZE, store.read_pages(0, &mut refs).unwrap());
+            assert!(pages == bufs);
+        });
+    }
+
+    #[test]
+    fn memory_with_small_pages() {
+        let mut store = Memory::with_page_size(page::MIN_SIZE);
+        store.write_page(1, &[1u8; page::MIN_SIZE]).unwrap();
+        assert_eq!(2 * page::MIN_SIZE as u64, store.size().unwrap());
+        let mut buf = [0u8; page::MIN_SIZE];
+        assert_eq!(page::MIN_SIZE, store.read_page(1, &mut buf).unwrap());
+        assert_eq!([1u8; page::MIN_SIZE], buf);
+    }
+
+    #[test]
+    fn disk_given_unsupported_page_size() {
+        ephemeral::disk!(tmp {
+            for size in [0, 2048, 6000, 131072] {
+                match Disk::create(tmp.borrow_mut().try_clone().unwrap(), size) {
+                    Ok(_) => panic!("allowed page size {size}"),
+                    Err(error) => assert!(
+                        matches!(error, storage::Error::UnsupportedPageSize { size: found } if found == size),
+                        "{error}"
+                    ),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn disk_opens_with_recorded_page_size() {
+        ephemeral::disk!(tmp {
+            let mut store = tmp.store(32768);
+            meta::init(&mut store, (1, 0)).unwrap();
+            let store = Disk::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            assert_eq!(32768, store.page_size());
+        });
+    }
+
+    #[test]
+    fn memory_read_of_partial_page() {
+        let mut store = Memory::from(vec![4u8; page::DEFAULT_SIZE + 10]);
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        assert_eq!(10, store.read_page(1, &mut buf).unwrap());
+        assert_eq!([4u8; 10], buf[..10]);
+    }
+
+    #[test]
+    fn memory_clones_share_contents() {
+        let mut store = Memory::new();
+        let mut clone = store.clone();
+        store.write_page(0, &[5u8; page::DEFAULT_SIZE]).unwrap();
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        clone.read_page(0, &mut buf).unwrap();
+        assert_eq!([5u8; page::DEFAULT_SIZE], buf);
+    }
+
+    #[test]
+    fn faulty_fails_given_operations() {
+        let mut store = Faulty::new(Memory::new());
+        store.write_page(0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+        store.write_page(1, &[1u8; page::DEFAULT_SIZE]).unwrap();
+        store.inject(Fault::Fail(Op::Write, Some(1)));
+        store.inject(Fault::Fail(Op::Sync, None));
+
+        assert!(store.write_page(0, &[2u8; page::DEFAULT_SIZE]).is_ok());
+        match store.write_page(1, &[2u8; page::DEFAULT_SIZE]) {
+            Ok(_) => panic!("allowed write to failing page"),
+            Err(error) => assert_eq!("injected Write fault", error.to_string()),
+        }
+        assert!(store.sync().is_err());
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        store.read_page(1, &mut buf).unwrap();
+        assert_eq!([1u8; page::DEFAULT_SIZE], buf);
+
+        store.heal();
+        assert!(store.write_page(1, &[2u8; page::DEFAULT_SIZE]).is_ok());
+        assert!(store.sync().is_ok());
+    }
+
+    fn contents(store: &mut dyn PageStore, page: u64) -> [u8; page::DEFAULT_SIZE] {
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        store.read_page(page, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn faulty_fails_nth_write() {
+        let mut store = Faulty::new(Memory::new());
+        store.write_page(0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+        store.inject(Fault::FailWrite(1));
+        assert!(store.write_page(0, &[2u8; page::DEFAULT_SIZE]).is_ok());
+        assert!(store.write_page(0, &[3u8; page::DEFAULT_SIZE]).is_err());
+        assert!(store.write_page(0, &[4u8; page::DEFAULT_SIZE]).is_ok());
+        assert_eq!(4, store.writes());
+        assert_eq!([4u8; page::DEFAULT_SIZE], contents(&mut store, 0));
+    }
+
+    #[test]
+    fn faulty_fails_nth_sync() {
+        let mut store = Faulty::new(Memory::new());
+        store.write_page(0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+        store.inject(Fault::FailSync(0));
+        assert!(store.sync().is_err());
+        assert_eq!(vec![0], store.unsynced());
+        store.sync().unwrap();
+        assert!(store.unsynced().is_empty());
+        assert_eq!(2, store.syncs());
+    }
+
+    #[test]
+    fn faulty_tears_nth_write() {
+        let mut store = Faulty::new(Memory::new());
+        store.write_page(0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+        store.inject(Fault::TearWrite(0, 100));
+        store.inject(Fault::TearWrite(1, 10));
+        match store.write_page(0, &[2u8; page::DEFAULT_SIZE]) {
+            Ok(_) => panic!("allowed torn write"),
+            Err(error) => assert_eq!("injected torn write", error.to_string()),
+        }
+        let buf = contents(&mut store, 0);
+        assert_eq!([2u8; 100], buf[..100]);
+        assert!(buf[100..].iter().all(|&n| n == 1));
+
+        // A torn append leaves a partial page at the end of the store.
+        assert!(store.write_page(1, &[3u8; page::DEFAULT_SIZE]).is_err());
+        assert_eq!(page::DEFAULT_SIZE as u64 + 10, store.size().unwrap());
+    }
+
+    #[test]
+    fn faulty_flips_bits_on_read() {
+        let mut store = Faulty::new(Memory::new());
+        store.write_page(0, &[0u8; page::DEFAULT_SIZE]).unwrap();
+        store.inject(Fault::FlipBit(0, 8 * 5 + 3));
+        let buf = contents(&mut store, 0);
+        assert_eq!(0x08, buf[5]);
+        assert_eq!(1, buf.iter().filter(|&&n| n != 0).count());
+        assert_eq!(
+            [0u8; page::DEFAULT_SIZE],
+            contents(&mut store.clone().into_inner(), 0)
+        );
+    }
+
+    #[test]
+    fn faulty_crash_drops_unsynced_writes() {
+        let mut store = Faulty::new(Memory::new());
+        store.write_page(0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+        store.sync().unwrap();
+        store.write_page(0, &[2u8; page::DEFAULT_SIZE]).unwrap();
+        store.write_page(1, &[2u8; page::DEFAULT_SIZE]).unwrap();
+        store.crash(Crash::Drop).unwrap();
+
+        assert_eq!(page::DEFAULT_SIZE as u64, store.size().unwrap());
+        assert_eq!([1u8; page::DEFAULT_SIZE], contents(&mut store, 0));
+        // Writes after the crash are unsynced in turn.
+        store.write_page(0, &[3u8; page::DEFAULT_SIZE]).unwrap();
+        store.crash(Crash::Drop).unwrap();
+        assert_eq!([1u8; page::DEFAULT_SIZE], contents(&mut store, 0));
+    }
+
+    #[test]
+    fn faulty_crash_reorders_unsynced_writes() {
+        let outcome = |seed| {
+            let mut store = Faulty::new(Memory::new());
+            for page in 0..4 {
+                store.write_page(page, &[0u8; page::DEFAULT_SIZE]).unwrap();
+            }
+            store.sync().unwrap();
+            for page in 0..4 {
+                store.write_page(page, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            }
+            store.crash(Crash::Reorder(seed)).unwrap();
+            (0..4)
+                .map(|page| contents(&mut store, page)[0])
+                .collect::<Vec<u8>>()
+        };
+        // The same seed always crashes the same way.
+        assert_eq!(outcome(7), outcome(7));
+        // Later writes land without earlier ones for some seed.
+        assert!((0..64).any(|seed| {
+            let pages = outcome(seed);
+            pages.windows(2).any(|pair| pair == [0, 1])
+        }));
+    }
+}
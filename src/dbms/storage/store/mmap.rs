@@ -0,0 +1,234 @@
+use std::{fs::File, io, ops::Range};
+
+use memmap2::{Advice, MmapMut, MmapOptions};
+
+use crate::dbms::storage::{Error, PageStore, Result, page};
+
+// The smallest mapping made, in pages, so that a small file does not have to
+// be mapped again for every page appended to it.
+const MINIMUM: u64 = 64;
+
+// Keeps a file mapped into memory, so that reading a page takes neither a
+// system call nor a copy. The mapping is grown ahead of the file, doubling
+// every time it runs out, and only the part of it that the file backs is
+// ever touched, since touching the rest would fault.
+pub struct Mapped {
+    file: File,
+    page_size: usize,
+    map: Option<MmapMut>,
+    len: u64,
+    // Whether the length of the file has changed since the last sync, which
+    // msync alone does not make durable.
+    resized: bool,
+}
+
+impl Mapped {
+    pub fn open(file: File, page_size: usize) -> Result<Self> {
+        page::check_size(page_size)?;
+        let len = file.metadata()?.len();
+        let mut mapped = Self {
+            file,
+            page_size,
+            map: None,
+            len,
+            resized: false,
+        };
+        mapped.reserve(len)?;
+        Ok(mapped)
+    }
+
+    // Borrows the page straight from the mapping. The borrow has to end
+    // before the store is written to, which may map the file again.
+    pub fn page(&self, page: u64) -> Result<&[u8]> {
+        let size = self.page_size;
+        let offset = page * size as u64;
+        if offset >= self.len {
+            return Err(Error::OutOfRange {
+                page,
+                end: self.len.div_ceil(size as u64),
+            });
+        }
+        if self.len - offset < size as u64 {
+            return Err(Error::ShortRead {
+                page,
+                expected: size,
+                found: (self.len - offset) as usize,
+            });
+        }
+        let offset = offset as usize;
+        Ok(&self.map()[offset..offset + size])
+    }
+
+    fn map(&self) -> &MmapMut {
+        self.map
+            .as_ref()
+            .expect("file is mapped once it has contents")
+    }
+
+    // Makes sure that the mapping covers the first `len` bytes of the file.
+    fn reserve(&mut self, len: u64) -> io::Result<()> {
+        let mapped = self.map.as_ref().map_or(0, |map| map.len() as u64);
+        if len <= mapped || len == 0 {
+            return Ok(());
+        }
+        let capacity = len.max(mapped * 2).max(MINIMUM * self.page_size as u64);
+        // SAFETY: the file is owned by the store, and nothing else is
+        // expected to truncate it while it is mapped.
+        let map = unsafe {
+            MmapOptions::new()
+                .len(capacity as usize)
+                .map_mut(&self.file)?
+        };
+        self.map = Some(map);
+        Ok(())
+    }
+
+    fn resize(&mut self, len: u64) -> io::Result<()> {
+        self.reserve(len)?;
+        self.file.set_len(len)?;
+        self.len = len;
+        self.resized = true;
+        Ok(())
+    }
+}
+
+impl PageStore for Mapped {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+
+    fn read_page(&mut self, page: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let offset = page * self.page_size as u64;
+        if offset >= self.len {
+            return Ok(0);
+        }
+        let found = (self.len - offset).min(buf.len() as u64) as usize;
+        let offset = offset as usize;
+        buf[..found].copy_from_slice(&self.map()[offset..offset + found]);
+        Ok(found)
+    }
+
+    fn write_page(&mut self, page: u64, buf: &[u8]) -> io::Result<()> {
+        let offset = page * self.page_size as u64;
+        let end = offset + buf.len() as u64;
+        if end > self.len {
+            self.resize(end)?;
+        }
+        let offset = offset as usize;
+        self.map.as_mut().unwrap()[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.resize(size)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        if let Some(map) = &self.map {
+            map.flush()?;
+        }
+        if self.resized {
+            self.file.sync_data()?;
+            self.resized = false;
+        }
+        Ok(())
+    }
+
+    // Only the part of the run that the file backs can be advised.
+    fn advise(&mut self, pages: Range<u64>) -> io::Result<()> {
+        let offset = (pages.start * self.page_size as u64).min(self.len);
+        let end = (pages.end * self.page_size as u64).min(self.len);
+        match &self.map {
+            Some(map) if offset < end => {
+                map.advise_range(Advice::WillNeed, offset as usize, (end - offset) as usize)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::storage::{ephemeral, meta};
+
+    #[test]
+    fn page_borrows_from_mapping() {
+        ephemeral::disk!(tmp {
+            let mut store = Mapped::open(tmp.borrow_mut().try_clone().unwrap(), page::DEFAULT_SIZE).unwrap();
+            page::write(&mut store, 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(&mut store, 1, &[2u8; page::DEFAULT_SIZE]).unwrap();
+            assert_eq!(&[1u8; page::DEFAULT_SIZE], store.page(0).unwrap());
+            assert_eq!(&[2u8; page::DEFAULT_SIZE], store.page(1).unwrap());
+
+            match store.page(2) {
+                Ok(_) => panic!("allowed borrowing distant page"),
+                Err(error) => assert!(matches!(error, Error::OutOfRange { page: 2, end: 2 }), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn page_when_file_ends_mid_page() {
+        ephemeral::disk!(tmp {
+            tmp.borrow_mut().set_len(page::DEFAULT_SIZE as u64 / 2).unwrap();
+            let store = Mapped::open(tmp.borrow_mut().try_clone().unwrap(), page::DEFAULT_SIZE).unwrap();
+            match store.page(0) {
+                Ok(_) => panic!("allowed borrowing partial page"),
+                Err(error) => assert!(
+                    matches!(error, Error::ShortRead { page: 0, found, .. } if found == page::DEFAULT_SIZE / 2),
+                    "{error}"
+                ),
+            }
+        });
+    }
+
+    #[test]
+    fn mapping_grows_with_appends() {
+        ephemeral::disk!(tmp {
+            let mut store = Mapped::open(tmp.borrow_mut().try_clone().unwrap(), page::DEFAULT_SIZE).unwrap();
+            let pages = 3 * MINIMUM;
+            for page in 0..pages {
+                page::write(&mut store, page, &[page as u8; page::DEFAULT_SIZE]).unwrap();
+            }
+            assert_eq!(4 * MINIMUM * page::DEFAULT_SIZE as u64, store.map().len() as u64);
+            for page in 0..pages {
+                assert_eq!(&[page as u8; page::DEFAULT_SIZE], store.page(page).unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn sync_reaches_file() {
+        ephemeral::disk!(tmp {
+            let mut store = Mapped::open(tmp.borrow_mut().try_clone().unwrap(), page::DEFAULT_SIZE).unwrap();
+            page::write(&mut store, 0, &[0u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(&mut store, 1, &[0u8; page::DEFAULT_SIZE]).unwrap();
+            meta::init(&mut store, (0, 1)).unwrap();
+            meta::write(&mut store, (0, 1), &[7u8; 64]).unwrap();
+            store.sync().unwrap();
+
+            let mut buf = [0u8; 64];
+            meta::read(&mut tmp.store(page::DEFAULT_SIZE), (0, 1), &mut buf).unwrap();
+            assert_eq!([7u8; 64], buf);
+        });
+    }
+
+    #[test]
+    fn truncate_shrinks_file() {
+        ephemeral::disk!(tmp {
+            let mut store = Mapped::open(tmp.borrow_mut().try_clone().unwrap(), page::DEFAULT_SIZE).unwrap();
+            page::write(&mut store, 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            page::write(&mut store, 1, &[1u8; page::DEFAULT_SIZE]).unwrap();
+            store.truncate(page::DEFAULT_SIZE as u64).unwrap();
+            assert_eq!(page::DEFAULT_SIZE as u64, tmp.borrow_mut().metadata().unwrap().len());
+            assert!(store.page(1).is_err());
+            page::write(&mut store, 1, &[3u8; page::DEFAULT_SIZE]).unwrap();
+            assert_eq!(&[3u8; page::DEFAULT_SIZE], store.page(1).unwrap());
+        });
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    fs::File,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::dbms::storage::{
+    Error, PageStore, Result,
+    meta::{
+        self,
+        record::{self, Reader, Record, Writer},
+    },
+    page,
+    store::Disk,
+};
+
+// The superblock is kept in the meta pair at the start of the file, which is
+// where the page size of a file is looked for when it is opened.
+pub const PAIR: (u64, u64) = (0, 1);
+
+const MAGIC: [u8; 4] = *b"SHDB";
+
+// The on-disk format this version reads and writes. Files of any other
+// version are refused rather than misread.
+pub const VERSION: u16 = 1;
+
+// Feature flags mark on-disk structures that are optional within a format
+// version. A file with a flag that this version does not know is refused,
+// since it could not be read correctly. There are none so far.
+pub const FEATURES: u64 = 0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Superblock {
+    // Tells a database from a foreign file. A pair that holds no superblock
+    // reads as the default record, which lacks it as well.
+    magic: [u8; 4],
+    pub version: u16,
+    pub page_size: usize,
+    // Seconds since the Unix epoch.
+    pub created: u64,
+    // Tells databases apart, so that files of one are never mistaken for
+    // those of another.
+    pub id: u128,
+    pub features: u64,
+}
+
+// The magic number and the format version share the first eight bytes and
+// the page size takes the next eight, followed by the creation time, the id
+// and the features.
+impl Record for Superblock {
+    const VERSION: u16 = 1;
+    const SIZE: usize = 48;
+
+    fn encode(&self, writer: &mut Writer<'_>) {
+        writer.bytes(&self.magic);
+        writer.u16(self.version);
+        writer.bytes(&[0u8; 2]);
+        writer.u32(self.page_size as u32);
+        writer.bytes(&[0u8; 4]);
+        writer.u64(self.created);
+        writer.u128(self.id);
+        writer.u64(self.features);
+    }
+
+    fn decode(&mut self, reader: &mut Reader<'_>, _: u16) {
+        self.magic = reader.bytes();
+        self.version = reader.u16();
+        reader.bytes::<2>();
+        self.page_size = reader.u32() as usize;
+        reader.bytes::<4>();
+        self.created = reader.u64();
+        self.id = reader.u128();
+        self.features = reader.u64();
+    }
+}
+
+// Formats an empty store as a new database.
+pub fn init(store: &mut dyn PageStore) -> Result<Superblock> {
+    if page::count(store)? > 0 {
+        return Err(Error::NotEmpty);
+    }
+    let superblock = Superblock {
+        magic: MAGIC,
+        version: VERSION,
+        page_size: store.page_size(),
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+        id: rand::random(),
+        features: FEATURES,
+    };
+    let zeroes = page::buffer(store);
+    page::write(store, PAIR.0, &zeroes)?;
+    meta::init(store, PAIR)?;
+    record::write(store, PAIR, &superblock)?;
+    Ok(superblock)
+}
+
+pub fn read(store: &mut dyn PageStore) -> Result<Superblock> {
+    let superblock: Superblock = record::read(store, PAIR)?;
+    if superblock.magic != MAGIC {
+        return Err(Error::NotADatabase);
+    }
+    if superblock.version != VERSION {
+        return Err(Error::UnsupportedFormat {
+            version: superblock.version,
+        });
+    }
+    if superblock.features & !FEATURES != 0 {
+        return Err(Error::UnsupportedFeatures {
+            features: superblock.features & !FEATURES,
+        });
+    }
+    if superblock.page_size != store.page_size() {
+        return Err(Error::PageSizeMismatch {
+            page: PAIR.0,
+            expected: store.page_size(),
+            found: superblock.page_size,
+        });
+    }
+    Ok(superblock)
+}
+
+// Creates a database in an empty file.
+pub fn create(file: File, page_size: usize) -> Result<(Disk, Superblock)> {
+    let mut disk = Disk::create(file, page_size)?;
+    let superblock = init(&mut disk)?;
+    Ok((disk, superblock))
+}
+
+// Opens the database in the file. A file without an intact meta pair at its
+// start is no database, or one damaged beyond what the pair protects against.
+pub fn open(file: File) -> Result<(Disk, Superblock)> {
+    let mut disk = match Disk::open(file) {
+        Err(Error::UnknownPageSize) => return Err(Error::NotADatabase),
+        disk => disk?,
+    };
+    let superblock = read(&mut disk)?;
+    Ok((disk, superblock))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::dbms::storage::{ephemeral, store::Memory};
+
+    fn tamper(store: &mut dyn PageStore, tamper: impl FnOnce(&mut Superblock)) {
+        let mut superblock = record::read(store, PAIR).unwrap();
+        tamper(&mut superblock);
+        record::write(store, PAIR, &superblock).unwrap();
+    }
+
+    #[test]
+    fn init_then_read() {
+        let mut store = Memory::with_page_size(16384);
+        let superblock = init(&mut store).unwrap();
+        assert_eq!(VERSION, superblock.version);
+        assert_eq!(16384, superblock.page_size);
+        assert_eq!(FEATURES, superblock.features);
+        assert!(superblock.created > 0);
+        assert_eq!(superblock, read(&mut store).unwrap());
+
+        // Every database gets an id of its own.
+        assert_ne!(superblock.id, init(&mut Memory::new()).unwrap().id);
+    }
+
+    #[test]
+    fn init_given_store_with_pages() {
+        let mut store = Memory::new();
+        page::write(&mut store, 0, &[1u8; page::DEFAULT_SIZE]).unwrap();
+        match init(&mut store) {
+            Ok(_) => panic!("allowed formatting store with pages"),
+            Err(error) => assert!(matches!(error, Error::NotEmpty), "{error}"),
+        }
+    }
+
+    #[test]
+    fn read_given_foreign_pair() {
+        let mut store = Memory::new();
+        init(&mut store).unwrap();
+        tamper(&mut store, |superblock| superblock.magic[0] = b'X');
+        match read(&mut store) {
+            Ok(_) => panic!("allowed reading foreign superblock"),
+            Err(error) => assert!(matches!(error, Error::NotADatabase), "{error}"),
+        }
+    }
+
+    #[test]
+    fn read_given_other_version() {
+        let mut store = Memory::new();
+        init(&mut store).unwrap();
+        tamper(&mut store, |superblock| superblock.version = VERSION + 1);
+        match read(&mut store) {
+            Ok(_) => panic!("allowed reading superblock of other version"),
+            Err(error) => assert_eq!(
+                format!(
+                    "unsupported format version {}, expected {VERSION}",
+                    VERSION + 1
+                ),
+                error.to_string()
+            ),
+        }
+    }
+
+    #[test]
+    fn read_given_unknown_features() {
+        let mut store = Memory::new();
+        init(&mut store).unwrap();
+        tamper(&mut store, |superblock| superblock.features |= 0x04);
+        match read(&mut store) {
+            Ok(_) => panic!("allowed reading superblock with unknown features"),
+            Err(error) => assert!(
+                matches!(error, Error::UnsupportedFeatures { features: 0x04 }),
+                "{error}"
+            ),
+        }
+    }
+
+    #[test]
+    fn create_then_open() {
+        ephemeral::disk!(tmp {
+            let (_, created) = create(tmp.borrow_mut().try_clone().unwrap(), 4096).unwrap();
+            let (disk, opened) = open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            assert_eq!(created, opened);
+            assert_eq!(4096, disk.page_size());
+        });
+    }
+
+    #[test]
+    fn open_given_foreign_file() {
+        ephemeral::disk!(tmp {
+            tmp.borrow_mut().write_all(&[7u8; 3 * page::MAX_SIZE]).unwrap();
+            match open(tmp.borrow_mut().try_clone().unwrap()) {
+                Ok(_) => panic!("allowed opening foreign file"),
+                Err(error) => assert_eq!("file is not a shepherd database", error.to_string()),
+            }
+        });
+        ephemeral::disk!(tmp {
+            match open(tmp.borrow_mut().try_clone().unwrap()) {
+                Ok(_) => panic!("allowed opening empty file"),
+                Err(error) => assert!(matches!(error, Error::NotADatabase), "{error}"),
+            }
+        });
+    }
+}
@@ -0,0 +1,1250 @@
+use std::{
+    collections::{BinaryHeap, HashMap},
+    fs::File,
+    io::{self, BufReader, Read, Seek, Write},
+    os::unix::fs::FileExt,
+    sync::{Condvar, Mutex, MutexGuard},
+};
+
+use crate::dbms::storage::{
+    Error, PageStore, Result,
+    integrity::{Checksum, Crc32c},
+    page::{self, header},
+};
+
+pub type Lsn = u64;
+
+const MAGIC: [u8; 4] = *b"SHWL";
+
+// The log starts with its magic number and the LSN of the first record in
+// it. LSNs keep counting up across truncations, so the first LSN of a
+// truncated log is the LSN its next record would have had.
+const HEADER: u64 = 12;
+
+// Every record starts with its total length and a CRC-32C over everything
+// that follows the checksum.
+const PREFIX: usize = 8;
+
+const UPDATE: u8 = 1;
+const COMPENSATION: u8 = 2;
+const COMMIT: u8 = 3;
+const ABORT: u8 = 4;
+const END: u8 = 5;
+
+// The body of a record starts with its kind, the transaction it belongs to
+// and the LSN of the previous record of that transaction.
+const BODY: usize = 17;
+
+// Page images are as large as the pages of the data store, which the log does
+// not know, so their size is taken from the length of the record.
+const MAX_BODY: usize = BODY + 8 + 2 * page::MAX_SIZE;
+
+// LSNs start at 1, so this terminates the chain of records of a transaction.
+const NONE: Lsn = 0;
+
+fn checksum(body: &[u8]) -> u32 {
+    Crc32c.checksum(body) as u32
+}
+
+fn stored(prefix: &[u8; PREFIX]) -> u32 {
+    u32::from_le_bytes(prefix[4..8].try_into().unwrap())
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub txn: u64,
+    pub prev: Lsn,
+    pub kind: Kind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Kind {
+    // The full images of a page before and after it was modified.
+    Update {
+        page: u64,
+        before: Box<[u8]>,
+        after: Box<[u8]>,
+    },
+    // Logged when an update is rolled back, with the image the page was
+    // restored to. Compensations are redone but never undone themselves, and
+    // `undo_next` points past the update they compensate for.
+    Compensation {
+        page: u64,
+        image: Box<[u8]>,
+        undo_next: Lsn,
+    },
+    Commit,
+    Abort,
+    // Logged once a transaction has been committed or completely rolled back.
+    End,
+}
+
+impl Record {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; PREFIX];
+        buf.push(match self.kind {
+            Kind::Update { .. } => UPDATE,
+            Kind::Compensation { .. } => COMPENSATION,
+            Kind::Commit => COMMIT,
+            Kind::Abort => ABORT,
+            Kind::End => END,
+        });
+        buf.extend_from_slice(&self.txn.to_le_bytes());
+        buf.extend_from_slice(&self.prev.to_le_bytes());
+        match &self.kind {
+            Kind::Update {
+                page,
+                before,
+                after,
+            } => {
+                buf.extend_from_slice(&page.to_le_bytes());
+                buf.extend_from_slice(&before[..]);
+                buf.extend_from_slice(&after[..]);
+            }
+            Kind::Compensation {
+                page,
+                image,
+                undo_next,
+            } => {
+                buf.extend_from_slice(&page.to_le_bytes());
+                buf.extend_from_slice(&undo_next.to_le_bytes());
+                buf.extend_from_slice(&image[..]);
+            }
+            Kind::Commit | Kind::Abort | Kind::End => {}
+        }
+        let len = buf.len() as u32;
+        buf[0..4].copy_from_slice(&len.to_le_bytes());
+        let crc = checksum(&buf[PREFIX..]);
+        buf[4..8].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < BODY {
+            return None;
+        }
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap());
+        let image_at =
+            |offset: usize, size: usize| -> Box<[u8]> { buf[offset..offset + size].into() };
+        let is_page_size = |size: usize| page::check_size(size).is_ok();
+        let size = buf.len().saturating_sub(BODY + 8) / 2;
+        let kind = match buf[0] {
+            UPDATE if is_page_size(size) && buf.len() == BODY + 8 + 2 * size => Kind::Update {
+                page: u64_at(BODY),
+                before: image_at(BODY + 8, size),
+                after: image_at(BODY + 8 + size, size),
+            },
+            COMPENSATION if is_page_size(buf.len().saturating_sub(BODY + 16)) => {
+                Kind::Compensation {
+                    page: u64_at(BODY),
+                    undo_next: u64_at(BODY + 8),
+                    image: image_at(BODY + 16, buf.len() - BODY - 16),
+                }
+            }
+            COMMIT if buf.len() == BODY => Kind::Commit,
+            ABORT if buf.len() == BODY => Kind::Abort,
+            END if buf.len() == BODY => Kind::End,
+            _ => return None,
+        };
+        Some(Record {
+            txn: u64_at(1),
+            prev: u64_at(9),
+            kind,
+        })
+    }
+}
+
+// Reads records from the start of the log until it runs out of records or
+// runs into one that is incomplete or corrupt, which is how the tail of a log
+// that was cut short by a crash looks.
+struct Scanner<'a> {
+    reader: BufReader<&'a mut File>,
+    lsn: Lsn,
+    offset: u64,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(file: &'a mut File) -> Result<Self> {
+        file.seek(io::SeekFrom::Start(0))?;
+        let mut header = [0u8; HEADER as usize];
+        file.read_exact(&mut header)?;
+        if header[0..4] != MAGIC {
+            return Err(Error::NotALog);
+        }
+        Ok(Self {
+            reader: BufReader::new(file),
+            lsn: u64::from_le_bytes(header[4..12].try_into().unwrap()),
+            offset: HEADER,
+        })
+    }
+
+    fn next(&mut self) -> Result<Option<(Lsn, Record)>> {
+        let mut prefix = [0u8; PREFIX];
+        if !read_fully(&mut self.reader, &mut prefix)? {
+            return Ok(None);
+        }
+        let len = u32::from_le_bytes(prefix[0..4].try_into().unwrap()) as usize;
+        if len <= PREFIX || len > PREFIX + MAX_BODY {
+            return Ok(None);
+        }
+        let mut body = vec![0u8; len - PREFIX];
+        if !read_fully(&mut self.reader, &mut body)? {
+            return Ok(None);
+        }
+        if stored(&prefix) != checksum(&body) {
+            return Ok(None);
+        }
+        let Some(record) = Record::decode(&body) else {
+            return Ok(None);
+        };
+        let lsn = self.lsn;
+        self.lsn += len as u64;
+        self.offset += len as u64;
+        Ok(Some((lsn, record)))
+    }
+}
+
+// Like `read_exact`, except that running out of data is not an error.
+fn read_fully(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => filled += n,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(true)
+}
+
+struct State {
+    file: File,
+    // Records that have been appended but not yet written to the file.
+    buffer: Vec<u8>,
+    // The LSN and file offset of the first byte in `buffer`. Every record
+    // before it is durable.
+    durable: Lsn,
+    tail: u64,
+    // The LSN the next appended record gets.
+    next: Lsn,
+    // The LSN of the first record in the file.
+    base: Lsn,
+    next_txn: u64,
+    // The LSN of the last record of every transaction that has not ended,
+    // which is where its undo chain starts.
+    active: HashMap<u64, Lsn>,
+    // Set while some thread writes and syncs the buffer on behalf of all
+    // threads waiting for their records to become durable.
+    flushing: bool,
+    poisoned: bool,
+    syncs: u64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Status {
+    Running,
+    Committed,
+    Aborting,
+}
+
+// What the analysis pass learns about the state of things at the time of the
+// crash. Transactions that ended are left out of the transaction table, and
+// the dirty page table holds the LSN of the first record that may have
+// modified each page without that modification reaching the data file.
+struct Analysis {
+    txns: HashMap<u64, (Lsn, Status)>,
+    dirty: HashMap<u64, Lsn>,
+}
+
+pub struct Log {
+    state: Mutex<State>,
+    flushed: Condvar,
+}
+
+impl Log {
+    // Opens the log stored in `file`, initialising it if the file is empty.
+    // A torn record at the end of the log is cut off. The log must be
+    // recovered before new transactions are started.
+    pub fn open(mut file: File) -> Result<Self> {
+        if file.metadata()?.len() == 0 {
+            let mut header = [0u8; HEADER as usize];
+            header[0..4].copy_from_slice(&MAGIC);
+            header[4..12].copy_from_slice(&1u64.to_le_bytes());
+            file.write_all(&header)?;
+            file.sync_all()?;
+        }
+        let mut scanner = Scanner::new(&mut file)?;
+        let base = scanner.lsn;
+        let mut next_txn = 1;
+        while let Some((_, record)) = scanner.next()? {
+            next_txn = next_txn.max(record.txn + 1);
+        }
+        let (tail, next) = (scanner.offset, scanner.lsn);
+        if file.metadata()?.len() != tail {
+            file.set_len(tail)?;
+            file.sync_all()?;
+        }
+        Ok(Self {
+            state: Mutex::new(State {
+                file,
+                buffer: Vec::new(),
+                durable: next,
+                tail,
+                next,
+                base,
+                next_txn,
+                active: HashMap::new(),
+                flushing: false,
+                poisoned: false,
+                syncs: 0,
+            }),
+            flushed: Condvar::new(),
+        })
+    }
+
+    pub fn begin(&self) -> u64 {
+        let mut state = self.lock();
+        let txn = state.next_txn;
+        state.next_txn += 1;
+        state.active.insert(txn, NONE);
+        txn
+    }
+
+    // Buffers a record for the transaction, chained to its previous one, and
+    // returns its LSN. The record is not durable until the log has been
+    // flushed up to it.
+    fn append(&self, txn: u64, kind: Kind) -> Lsn {
+        let mut state = self.lock();
+        let lsn = state.next;
+        let prev = match kind {
+            Kind::End => state.active.remove(&txn),
+            _ => state.active.insert(txn, lsn),
+        };
+        let record = Record {
+            txn,
+            prev: prev.unwrap_or(NONE),
+            kind,
+        };
+        let encoded = record.encode();
+        state.next += encoded.len() as u64;
+        state.buffer.extend_from_slice(&encoded);
+        lsn
+    }
+
+    // Logs a modification of a page. The modified page must not be written to
+    // the data file before the log has been flushed up to the returned LSN.
+    // Pages with the common header must carry that LSN by then, which is how
+    // redo tells that they are up to date.
+    pub fn update(&self, txn: u64, page: u64, before: &[u8], after: &[u8]) -> Lsn {
+        self.append(
+            txn,
+            Kind::Update {
+                page,
+                before: before.into(),
+                after: after.into(),
+            },
+        )
+    }
+
+    // Makes the transaction durable. Once this returns, its updates survive a
+    // crash.
+    pub fn commit(&self, txn: u64) -> Result<Lsn> {
+        let lsn = self.append(txn, Kind::Commit);
+        self.flush(lsn)?;
+        self.append(txn, Kind::End);
+        Ok(lsn)
+    }
+
+    // Undoes every update of the transaction, restoring the pages in the data
+    // file to how they were before it started.
+    pub fn rollback(&self, txn: u64, data: &mut dyn PageStore) -> Result<()> {
+        let lsn = self.append(txn, Kind::Abort);
+        let prev = self.read(lsn)?.prev;
+        self.undo(HashMap::from([(txn, prev)]), data)
+    }
+
+    // Blocks until the record at `lsn` and every record before it is durable.
+    // Threads that call this while a flush is in progress wait for it and then
+    // have their records written by a single thread, so a burst of commits
+    // shares one sync.
+    pub fn flush(&self, lsn: Lsn) -> Result<()> {
+        let mut state = self.lock();
+        loop {
+            if state.poisoned {
+                return Err(Error::LogFailed);
+            }
+            if state.durable > lsn {
+                return Ok(());
+            }
+            if !state.flushing {
+                break;
+            }
+            state = self.flushed.wait(state).unwrap();
+        }
+        state.flushing = true;
+        let buffer = std::mem::take(&mut state.buffer);
+        let (target, offset) = (state.next, state.tail);
+        let file = state.file.try_clone();
+        drop(state);
+
+        // The log is written at an explicit offset, since the file position is
+        // shared with scans of the log that may run in the meantime.
+        let result = file.and_then(|file| {
+            file.write_all_at(&buffer, offset)?;
+            file.sync_data()
+        });
+
+        let mut state = self.lock();
+        state.flushing = false;
+        match result {
+            Ok(()) => {
+                state.durable = target;
+                state.tail += buffer.len() as u64;
+                state.syncs += 1;
+            }
+            // The buffered records may or may not have reached the file, so
+            // there is no telling which LSN the next record would get.
+            Err(_) => state.poisoned = true,
+        }
+        drop(state);
+        self.flushed.notify_all();
+        Ok(result?)
+    }
+
+    fn flush_all(&self) -> Result<()> {
+        let next = self.lock().next;
+        if next == NONE + 1 {
+            return Ok(());
+        }
+        self.flush(next - 1)
+    }
+
+    // Discards every record in the log. Only safe once every page modified by
+    // a committed transaction has been written to the data file and synced.
+    pub fn truncate(&self) -> Result<()> {
+        let mut state = self.lock();
+        if state.flushing || !state.buffer.is_empty() {
+            return Err(Error::PendingLogRecords);
+        }
+        if !state.active.is_empty() {
+            return Err(Error::ActiveTransactions);
+        }
+        let next = state.next;
+        state.file.set_len(HEADER)?;
+        state.file.write_all_at(&next.to_le_bytes(), 4)?;
+        state.file.sync_all()?;
+        state.tail = HEADER;
+        state.base = next;
+        Ok(())
+    }
+
+    // Reads the record at `lsn`, flushing the log first if the record has not
+    // been written to the file yet.
+    fn read(&self, lsn: Lsn) -> Result<Record> {
+        if lsn >= self.lock().durable {
+            self.flush(lsn)?;
+        }
+        let state = self.lock();
+        if lsn < state.base {
+            return Err(Error::TruncatedLogRecord { lsn });
+        }
+        let offset = HEADER + lsn - state.base;
+        let mut prefix = [0u8; PREFIX];
+        state.file.read_exact_at(&mut prefix, offset)?;
+        let len = u32::from_le_bytes(prefix[0..4].try_into().unwrap()) as usize;
+        let mut body = vec![0u8; len.saturating_sub(PREFIX).min(MAX_BODY)];
+        state
+            .file
+            .read_exact_at(&mut body, offset + PREFIX as u64)?;
+        if stored(&prefix) != checksum(&body) {
+            return Err(Error::LogChecksumMismatch {
+                lsn,
+                expected: stored(&prefix) as u64,
+                found: checksum(&body) as u64,
+            });
+        }
+        Record::decode(&body).ok_or(Error::CorruptLogRecord { lsn })
+    }
+
+    // Brings the data file back to a consistent state after a crash, in the
+    // three passes of ARIES. Analysis finds the transactions that were still
+    // running and the pages that may be out of date, redo repeats history by
+    // reapplying the logged page images that did not reach the data file,
+    // including those of transactions that never committed, and undo then
+    // rolls those transactions back.
+    pub fn recover(&self, data: &mut dyn PageStore) -> Result<()> {
+        let analysis = self.analyze()?;
+        self.redo(&analysis, data)?;
+
+        let mut losers = HashMap::new();
+        {
+            let mut state = self.lock();
+            for (txn, (last, status)) in analysis.txns {
+                state.active.insert(txn, last);
+                match status {
+                    Status::Committed => {}
+                    Status::Running | Status::Aborting => {
+                        losers.insert(txn, last);
+                    }
+                }
+            }
+        }
+        // Committed transactions only lack their end record.
+        let winners: Vec<u64> = self
+            .lock()
+            .active
+            .keys()
+            .copied()
+            .filter(|txn| !losers.contains_key(txn))
+            .collect();
+        for txn in winners {
+            self.append(txn, Kind::End);
+        }
+        self.undo(losers, data)
+    }
+
+    fn analyze(&self) -> Result<Analysis> {
+        let mut state = self.lock();
+        let mut analysis = Analysis {
+            txns: HashMap::new(),
+            dirty: HashMap::new(),
+        };
+        let mut scanner = Scanner::new(&mut state.file)?;
+        while let Some((lsn, record)) = scanner.next()? {
+            let status = match record.kind {
+                Kind::Update { page, .. } | Kind::Compensation { page, .. } => {
+                    analysis.dirty.entry(page).or_insert(lsn);
+                    Status::Running
+                }
+                Kind::Commit => Status::Committed,
+                Kind::Abort => Status::Aborting,
+                Kind::End => {
+                    analysis.txns.remove(&record.txn);
+                    continue;
+                }
+            };
+            let entry = analysis
+                .txns
+                .entry(record.txn)
+                .or_insert((lsn, Status::Running));
+            entry.0 = lsn;
+            // Compensations are logged after the abort record, and must not
+            // make the transaction look like it is running again.
+            if entry.1 == Status::Running {
+                entry.1 = status;
+            }
+        }
+        Ok(analysis)
+    }
+
+    fn redo(&self, analysis: &Analysis, data: &mut dyn PageStore) -> Result<()> {
+        let Some(start) = analysis.dirty.values().min().copied() else {
+            return Ok(());
+        };
+        let mut state = self.lock();
+        let mut scanner = Scanner::new(&mut state.file)?;
+        while let Some((lsn, record)) = scanner.next()? {
+            let (page, image) = match record.kind {
+                Kind::Update { page, after, .. } => (page, after),
+                Kind::Compensation { page, image, .. } => (page, image),
+                _ => continue,
+            };
+            if lsn < start || analysis.dirty.get(&page).is_none_or(|first| lsn < *first) {
+                continue;
+            }
+            if reflects(data, page, lsn)? {
+                continue;
+            }
+            let mut image = image.into_vec();
+            stamp(&mut image, page, lsn);
+            write_page(data, page, &image)?;
+        }
+        Ok(data.sync()?)
+    }
+
+    // Rolls back the given transactions, starting from the given LSNs and
+    // always undoing the newest remaining record across all of them first.
+    // Compensations that are already in the log are jumped over, so updates
+    // rolled back before a crash are not rolled back twice.
+    fn undo(&self, losers: HashMap<u64, Lsn>, data: &mut dyn PageStore) -> Result<()> {
+        let mut pending: BinaryHeap<(Lsn, u64)> = BinaryHeap::new();
+        for (txn, lsn) in losers {
+            if lsn == NONE {
+                self.append(txn, Kind::End);
+            } else {
+                pending.push((lsn, txn));
+            }
+        }
+        while let Some((lsn, txn)) = pending.pop() {
+            let record = self.read(lsn)?;
+            let next = match record.kind {
+                Kind::Update { page, before, .. } => {
+                    let compensation = self.append(
+                        txn,
+                        Kind::Compensation {
+                            page,
+                            image: before.clone(),
+                            undo_next: record.prev,
+                        },
+                    );
+                    let mut image = before.into_vec();
+                    stamp(&mut image, page, compensation);
+                    write_page(data, page, &image)?;
+                    record.prev
+                }
+                Kind::Compensation { undo_next, .. } => undo_next,
+                _ => record.prev,
+            };
+            if next == NONE {
+                self.append(txn, Kind::End);
+            } else {
+                pending.push((next, txn));
+            }
+        }
+        self.flush_all()?;
+        Ok(data.sync()?)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+// Whether the page in the data store already reflects the record at `lsn`.
+// Only an intact page with the common header can tell, through its LSN.
+// Others are always redone, which is harmless since records hold full images.
+fn reflects(data: &mut dyn PageStore, page: u64, lsn: Lsn) -> Result<bool> {
+    if page >= page::count(data)? {
+        return Ok(false);
+    }
+    let mut buf = page::buffer(data);
+    page::read(data, page, &mut buf)?;
+    Ok(header::verify(&buf, page).is_ok() && header::lsn(&buf) >= lsn)
+}
+
+// Records the LSN of the record that an image is applied for in the header of
+// the page, if it has one.
+fn stamp(image: &mut [u8], page: u64, lsn: Lsn) {
+    if header::verify(image, page).is_ok() {
+        header::set_lsn(image, lsn);
+        header::seal(image);
+    }
+}
+
+// Pages of transactions that were rolled back before they reached the data
+// store may leave a gap, which is larger than a page write allows.
+fn write_page(data: &mut dyn PageStore, page: u64, image: &[u8]) -> Result<()> {
+    if image.len() != data.page_size() {
+        return Err(Error::BufferSizeMismatch {
+            expected: data.page_size(),
+            found: image.len(),
+        });
+    }
+    let pages = page::count(data)?;
+    let zeroes = page::buffer(data);
+    for missing in pages..page {
+        page::write(data, missing, &zeroes)?;
+    }
+    page::write(data, page, image)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+    use crate::dbms::storage::{
+        ephemeral,
+        integrity::Algorithm,
+        store::{Fault, Faulty, Memory, Op},
+    };
+
+    fn records(log: &Log) -> Vec<(Lsn, Record)> {
+        let mut state = log.lock();
+        let mut scanner = Scanner::new(&mut state.file).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = scanner.next().unwrap() {
+            records.push(record);
+        }
+        records
+    }
+
+    fn kinds(log: &Log) -> Vec<(u64, &'static str)> {
+        records(log)
+            .into_iter()
+            .map(|(_, record)| {
+                let kind = match record.kind {
+                    Kind::Update { .. } => "update",
+                    Kind::Compensation { .. } => "compensation",
+                    Kind::Commit => "commit",
+                    Kind::Abort => "abort",
+                    Kind::End => "end",
+                };
+                (record.txn, kind)
+            })
+            .collect()
+    }
+
+    fn image(n: u8) -> [u8; page::DEFAULT_SIZE] {
+        [n; page::DEFAULT_SIZE]
+    }
+
+    fn assert_page(data: &mut dyn PageStore, page: u64, expected: u8) {
+        let mut buf = [0u8; page::DEFAULT_SIZE];
+        page::read(data, page, &mut buf).unwrap();
+        assert_eq!(image(expected), buf, "unexpected contents of page {page}");
+    }
+
+    // Writes a page the way a buffer pool that steals frames would, after the
+    // log has been flushed up to the update.
+    fn steal(log: &Log, data: &mut dyn PageStore, lsn: Lsn, page: u64, n: u8) {
+        log.flush(lsn).unwrap();
+        write_page(data, page, &image(n)).unwrap();
+    }
+
+    // An image of a page with the common header, holding `n` after it.
+    fn formatted(page: u64, n: u8) -> Box<[u8]> {
+        let mut buf = vec![n; page::DEFAULT_SIZE];
+        header::init(&mut buf, header::Kind::Slotted, page, Algorithm::Crc32c);
+        header::seal(&mut buf);
+        buf.into()
+    }
+
+    const COMMIT_LEN: u64 = (PREFIX + BODY) as u64;
+
+    #[test]
+    fn open_initialises_empty_file() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            assert_eq!(HEADER, tmp.borrow_mut().metadata().unwrap().len());
+            assert!(records(&log).is_empty());
+            assert_eq!(1, log.begin());
+        });
+    }
+
+    #[test]
+    fn open_given_foreign_file() {
+        ephemeral::disk!(tmp {
+            tmp.borrow_mut().write_all(&[0u8; 64]).unwrap();
+            match Log::open(tmp.borrow_mut().try_clone().unwrap()) {
+                Ok(_) => panic!("allowed opening foreign file as log"),
+                Err(error) => assert!(matches!(error, Error::NotALog), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn append_assigns_increasing_lsns() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let first = log.update(1, 0, &image(0), &image(1));
+            let second = log.append(1, Kind::Commit);
+            let third = log.append(2, Kind::Commit);
+            assert_eq!(1, first);
+            assert_eq!(first + (PREFIX + BODY + 8 + 2 * page::DEFAULT_SIZE) as u64, second);
+            assert_eq!(second + COMMIT_LEN, third);
+        });
+    }
+
+    #[test]
+    fn append_chains_records_of_transaction() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let (first, second) = (log.begin(), log.begin());
+            let a = log.update(first, 0, &image(0), &image(1));
+            let b = log.update(second, 0, &image(1), &image(2));
+            let c = log.update(first, 1, &image(0), &image(3));
+            let d = log.commit(first).unwrap();
+            log.flush_all().unwrap();
+
+            let prevs: Vec<(Lsn, Lsn)> = records(&log)
+                .into_iter()
+                .map(|(lsn, record)| (lsn, record.prev))
+                .collect();
+            assert_eq!(vec![(a, NONE), (b, NONE), (c, a), (d, c), (d + COMMIT_LEN, d)], prevs);
+            assert_eq!(Some(&b), log.lock().active.get(&second));
+            assert!(!log.lock().active.contains_key(&first));
+        });
+    }
+
+    #[test]
+    fn records_are_durable_after_flush() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let txn = log.begin();
+            log.update(txn, 3, &image(0), &image(1));
+            // Nothing is written until the log is flushed.
+            assert_eq!(HEADER, tmp.borrow_mut().metadata().unwrap().len());
+            let lsn = log.commit(txn).unwrap();
+            drop(log);
+
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let records = records(&log);
+            assert_eq!(2, records.len());
+            assert_eq!(
+                (1, Record {
+                    txn,
+                    prev: NONE,
+                    kind: Kind::Update {
+                        page: 3,
+                        before: Box::new(image(0)),
+                        after: Box::new(image(1)),
+                    },
+                }),
+                records[0]
+            );
+            assert_eq!((lsn, Record { txn, prev: 1, kind: Kind::Commit }), records[1]);
+            // Transaction ids are not handed out twice.
+            assert_eq!(txn + 1, log.begin());
+        });
+    }
+
+    #[test]
+    fn flush_writes_buffered_records_with_single_sync() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let mut lsns = Vec::new();
+            for txn in 1..=5 {
+                lsns.push(log.append(txn, Kind::Commit));
+            }
+            log.flush(lsns[4]).unwrap();
+            for lsn in lsns {
+                log.flush(lsn).unwrap();
+            }
+            assert_eq!(1, log.lock().syncs);
+            assert_eq!(5, records(&log).len());
+        });
+    }
+
+    #[test]
+    fn concurrent_commits_are_durable() {
+        ephemeral::disk!(tmp {
+            let log = Arc::new(Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap());
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    let log = log.clone();
+                    thread::spawn(move || {
+                        for _ in 0..16 {
+                            let txn = log.begin();
+                            log.commit(txn).unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            log.flush_all().unwrap();
+            assert!(log.lock().syncs <= 8 * 16 + 1);
+            let records = records(&log);
+            assert_eq!(8 * 16 * 2, records.len());
+            assert_eq!(8 * 16, records.iter().filter(|(_, r)| r.kind == Kind::Commit).count());
+        });
+    }
+
+    #[test]
+    fn record_with_images_of_any_page_size() {
+        for size in [page::MIN_SIZE, page::MAX_SIZE] {
+            let record = Record {
+                txn: 1,
+                prev: NONE,
+                kind: Kind::Update {
+                    page: 3,
+                    before: vec![1u8; size].into(),
+                    after: vec![2u8; size].into(),
+                },
+            };
+            let encoded = record.encode();
+            assert_eq!(Some(record), Record::decode(&encoded[PREFIX..]));
+            let prefix = encoded[..PREFIX].try_into().unwrap();
+            assert_eq!(checksum(&encoded[PREFIX..]), stored(&prefix));
+        }
+        // Images must have the size of a page.
+        let record = Record {
+            txn: 1,
+            prev: NONE,
+            kind: Kind::Compensation {
+                page: 3,
+                image: vec![1u8; 5000].into(),
+                undo_next: NONE,
+            },
+        };
+        assert_eq!(None, Record::decode(&record.encode()[PREFIX..]));
+    }
+
+    #[test]
+    fn open_cuts_off_torn_record() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let lsn = log.append(1, Kind::Commit);
+            log.flush(lsn).unwrap();
+            let end = lsn + COMMIT_LEN;
+            drop(log);
+
+            // Half of an update record made it to the file before the crash.
+            let encoded = Record {
+                txn: 2,
+                prev: NONE,
+                kind: Kind::Update { page: 0, before: Box::new(image(0)), after: Box::new(image(1)) },
+            }
+            .encode();
+            tmp.borrow_mut().seek(io::SeekFrom::End(0)).unwrap();
+            tmp.borrow_mut().write_all(&encoded[..encoded.len() / 2]).unwrap();
+
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            assert_eq!(1, records(&log).len());
+            assert_eq!(HEADER + end - 1, tmp.borrow_mut().metadata().unwrap().len());
+            // The next record takes the place of the torn one.
+            assert_eq!(end, log.commit(2).unwrap());
+            assert_eq!(2, records(&log).len());
+        });
+    }
+
+    #[test]
+    fn open_cuts_off_corrupt_record() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            log.append(1, Kind::Commit);
+            let lsn = log.append(2, Kind::Commit);
+            log.flush(lsn).unwrap();
+            drop(log);
+
+            let offset = HEADER + lsn - 1 + PREFIX as u64 + 1;
+            tmp.borrow_mut().seek(io::SeekFrom::Start(offset)).unwrap();
+            tmp.borrow_mut().write_all(&[0xFF]).unwrap();
+
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            assert_eq!(vec![(1, Record { txn: 1, prev: NONE, kind: Kind::Commit })], records(&log));
+        });
+    }
+
+    #[test]
+    fn read_when_record_is_corrupt_or_truncated() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let lsn = log.append(1, Kind::Commit);
+            log.flush(lsn).unwrap();
+            tmp.borrow_mut().write_all_at(&[0xFF], HEADER + lsn - 1 + PREFIX as u64 + 1).unwrap();
+            match log.read(lsn) {
+                Ok(_) => panic!("allowed reading corrupt log record"),
+                Err(error) => assert!(
+                    matches!(error, Error::LogChecksumMismatch { lsn: l, expected, found } if l == lsn && expected != found),
+                    "{error}"
+                ),
+            }
+
+            log.append(1, Kind::End);
+            log.flush_all().unwrap();
+            log.truncate().unwrap();
+            match log.read(lsn) {
+                Ok(_) => panic!("allowed reading truncated log record"),
+                Err(error) => assert!(matches!(error, Error::TruncatedLogRecord { lsn: l } if l == lsn), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn truncate_keeps_lsns_increasing() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let txn = log.begin();
+            let lsn = log.commit(txn).unwrap();
+            log.flush_all().unwrap();
+            log.truncate().unwrap();
+            assert!(records(&log).is_empty());
+
+            let txn = log.begin();
+            let next = log.commit(txn).unwrap();
+            assert_eq!(lsn + 2 * COMMIT_LEN, next);
+            assert_eq!(Kind::Commit, log.read(next).unwrap().kind);
+            log.flush_all().unwrap();
+            drop(log);
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            assert_eq!(vec![(txn, "commit"), (txn, "end")], kinds(&log));
+            assert_eq!(next, records(&log)[0].0);
+        });
+    }
+
+    #[test]
+    fn truncate_given_pending_records() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let txn = log.begin();
+            log.commit(txn).unwrap();
+            match log.truncate() {
+                Ok(_) => panic!("allowed truncating log with pending records"),
+                Err(error) => assert!(matches!(error, Error::PendingLogRecords), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn truncate_given_active_transactions() {
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let txn = log.begin();
+            log.flush(log.update(txn, 0, &image(0), &image(1))).unwrap();
+            match log.truncate() {
+                Ok(_) => panic!("allowed truncating log with active transactions"),
+                Err(error) => assert!(matches!(error, Error::ActiveTransactions), "{error}"),
+            }
+        });
+    }
+
+    #[test]
+    fn rollback_restores_before_images() {
+        ephemeral::file!(data {
+            ephemeral::disk!(tmp {
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                let txn = log.begin();
+                let lsn = log.update(txn, 0, &image(0), &image(1));
+                steal(&log, data.borrow_mut(), lsn, 0, 1);
+                let lsn = log.update(txn, 1, &image(0), &image(2));
+                steal(&log, data.borrow_mut(), lsn, 1, 2);
+                log.update(txn, 0, &image(1), &image(3));
+
+                log.rollback(txn, data.borrow_mut()).unwrap();
+                assert_page(data.borrow_mut(), 0, 0);
+                assert_page(data.borrow_mut(), 1, 0);
+                assert_eq!(
+                    vec![
+                        (txn, "update"),
+                        (txn, "update"),
+                        (txn, "update"),
+                        (txn, "abort"),
+                        (txn, "compensation"),
+                        (txn, "compensation"),
+                        (txn, "compensation"),
+                        (txn, "end"),
+                    ],
+                    kinds(&log)
+                );
+                assert!(log.lock().active.is_empty());
+            });
+        });
+    }
+
+    #[test]
+    fn rollback_leaves_other_transactions_alone() {
+        ephemeral::file!(data {
+            ephemeral::disk!(tmp {
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                let (first, second) = (log.begin(), log.begin());
+                let lsn = log.update(first, 0, &image(0), &image(1));
+                steal(&log, data.borrow_mut(), lsn, 0, 1);
+                let lsn = log.update(second, 1, &image(0), &image(2));
+                steal(&log, data.borrow_mut(), lsn, 1, 2);
+
+                log.rollback(first, data.borrow_mut()).unwrap();
+                assert_page(data.borrow_mut(), 0, 0);
+                assert_page(data.borrow_mut(), 1, 2);
+                assert!(log.lock().active.contains_key(&second));
+            });
+        });
+    }
+
+    #[test]
+    fn recover_redoes_winners_and_undoes_losers() {
+        ephemeral::file!(data {
+            ephemeral::disk!(tmp {
+                write_page(data.borrow_mut(), 1, &image(0)).unwrap();
+
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                let (first, second, third) = (log.begin(), log.begin(), log.begin());
+                log.update(first, 0, &image(0), &image(1));
+                let lsn = log.update(second, 1, &image(0), &image(2));
+                steal(&log, data.borrow_mut(), lsn, 1, 2);
+                log.update(first, 1, &image(2), &image(3));
+                log.commit(first).unwrap();
+                log.update(third, 0, &image(1), &image(4));
+                log.commit(third).unwrap();
+                // The second transaction never commits, but one of its
+                // updates made it to the data file anyway.
+                let lsn = log.update(second, 0, &image(4), &image(5));
+                steal(&log, data.borrow_mut(), lsn, 0, 5);
+                drop(log);
+
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                log.recover(data.borrow_mut()).unwrap();
+                assert_page(data.borrow_mut(), 0, 4);
+                // Undoing the second transaction restores the image its
+                // update started from, which was later overwritten by the
+                // first transaction. Page level locking keeps this from
+                // happening in practice.
+                assert_page(data.borrow_mut(), 1, 0);
+                assert!(log.lock().active.is_empty());
+                assert_eq!(
+                    vec![(second, "compensation"), (second, "compensation"), (second, "end")],
+                    kinds(&log)[kinds(&log).len() - 3..]
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn recover_extends_data_file() {
+        ephemeral::file!(data {
+            ephemeral::disk!(tmp {
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                let (first, second) = (log.begin(), log.begin());
+                log.update(first, 0, &image(0), &image(1));
+                log.update(second, 1, &image(0), &image(2));
+                log.update(first, 2, &image(0), &image(3));
+                log.commit(first).unwrap();
+                drop(log);
+
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                log.recover(data.borrow_mut()).unwrap();
+                assert_page(data.borrow_mut(), 0, 1);
+                assert_page(data.borrow_mut(), 1, 0);
+                assert_page(data.borrow_mut(), 2, 3);
+
+                // Records appended after recovery go to the end of the log.
+                let txn = log.begin();
+                let lsn = log.commit(txn).unwrap();
+                log.flush_all().unwrap();
+                assert_eq!(lsn, records(&log)[records(&log).len() - 2].0);
+            });
+        });
+    }
+
+    #[test]
+    fn recover_resumes_interrupted_rollback() {
+        ephemeral::file!(data {
+            ephemeral::disk!(tmp {
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                let txn = log.begin();
+                let lsn = log.update(txn, 0, &image(0), &image(1));
+                steal(&log, data.borrow_mut(), lsn, 0, 1);
+                let update = log.update(txn, 1, &image(0), &image(2));
+                steal(&log, data.borrow_mut(), update, 1, 2);
+                // The rollback compensated for the last update before the
+                // crash, but never got to the first one.
+                log.append(txn, Kind::Abort);
+                let lsn = log.append(txn, Kind::Compensation {
+                    page: 1,
+                    image: Box::new(image(0)),
+                    undo_next: log.read(update).unwrap().prev,
+                });
+                log.flush(lsn).unwrap();
+                drop(log);
+
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                log.recover(data.borrow_mut()).unwrap();
+                assert_page(data.borrow_mut(), 0, 0);
+                assert_page(data.borrow_mut(), 1, 0);
+                // Only the first update is compensated for during recovery.
+                assert_eq!(
+                    vec![
+                        (txn, "update"),
+                        (txn, "update"),
+                        (txn, "abort"),
+                        (txn, "compensation"),
+                        (txn, "compensation"),
+                        (txn, "end"),
+                    ],
+                    kinds(&log)
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn recover_is_idempotent() {
+        ephemeral::file!(data {
+            ephemeral::disk!(tmp {
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                let (first, second) = (log.begin(), log.begin());
+                log.update(first, 0, &image(0), &image(1));
+                log.commit(first).unwrap();
+                let lsn = log.update(second, 0, &image(1), &image(2));
+                steal(&log, data.borrow_mut(), lsn, 0, 2);
+                drop(log);
+
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                log.recover(data.borrow_mut()).unwrap();
+                let after = kinds(&log);
+                drop(log);
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                log.recover(data.borrow_mut()).unwrap();
+
+                assert_page(data.borrow_mut(), 0, 1);
+                assert_eq!(after, kinds(&log));
+            });
+        });
+    }
+
+    #[test]
+    fn recover_ends_committed_transactions() {
+        ephemeral::file!(data {
+            ephemeral::disk!(tmp {
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                let txn = log.begin();
+                log.update(txn, 0, &image(0), &image(1));
+                // The end record was still buffered at the time of the crash.
+                log.commit(txn).unwrap();
+                drop(log);
+
+                let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+                log.recover(data.borrow_mut()).unwrap();
+                assert_page(data.borrow_mut(), 0, 1);
+                assert_eq!(vec![(txn, "update"), (txn, "commit"), (txn, "end")], kinds(&log));
+            });
+        });
+    }
+
+    #[test]
+    fn redo_skips_records_page_already_reflects() {
+        let mut data = Faulty::new(Memory::new());
+        ephemeral::disk!(tmp {
+            write_page(&mut data, 0, &formatted(0, 0)).unwrap();
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let txn = log.begin();
+            let lsn = log.update(txn, 0, &formatted(0, 0), &formatted(0, 1));
+            log.commit(txn).unwrap();
+            let mut after = formatted(0, 1);
+            header::set_lsn(&mut after, lsn);
+            header::seal(&mut after);
+            write_page(&mut data, 0, &after).unwrap();
+            drop(log);
+
+            // Recovery must not so much as try to write the page.
+            data.inject(Fault::Fail(Op::Write, Some(0)));
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            log.recover(&mut data).unwrap();
+            let mut buf = page::buffer(&data);
+            page::read(&mut data, 0, &mut buf).unwrap();
+            assert_eq!(after[..], buf[..]);
+        });
+    }
+
+    #[test]
+    fn recover_stamps_pages_with_record_lsn() {
+        let mut data = Memory::new();
+        ephemeral::disk!(tmp {
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            let (first, second) = (log.begin(), log.begin());
+            let redone = log.update(first, 0, &formatted(0, 0), &formatted(0, 1));
+            log.commit(first).unwrap();
+            let undone = log.update(second, 1, &formatted(1, 0), &formatted(1, 2));
+            log.flush(undone).unwrap();
+            drop(log);
+
+            let log = Log::open(tmp.borrow_mut().try_clone().unwrap()).unwrap();
+            log.recover(&mut data).unwrap();
+            let mut buf = page::buffer(&data);
+            page::read(&mut data, 0, &mut buf).unwrap();
+            header::expect(&buf, 0, header::Kind::Slotted).unwrap();
+            assert_eq!(redone, header::lsn(&buf));
+            assert_eq!(1, buf[header::SIZE]);
+            // The page that was rolled back carries the LSN of the
+            // compensation that restored it.
+            page::read(&mut data, 1, &mut buf).unwrap();
+            header::expect(&buf, 1, header::Kind::Slotted).unwrap();
+            assert!(header::lsn(&buf) > undone);
+            assert_eq!(0, buf[header::SIZE]);
+        });
+    }
+}